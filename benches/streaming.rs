@@ -0,0 +1,168 @@
+//! Compare the streaming path with a sink which holds each document in memory,
+//! as options which need to see a whole document do, and time long strings,
+//! heavily indented input, and tracking the path through a flat array.
+//!
+//! Run with `cargo bench --bench streaming`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use iowrap::Ignore;
+use unnest_ndjson::{unnest_to_ndjson_with, Config, Header, HeaderStyle, MiniWrite, Sinker};
+
+/// Count allocations, to show the work done for each document.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Hold each document until it is complete, then pass it on.
+struct Buffering<S> {
+    inner: S,
+    doc: Vec<u8>,
+}
+
+impl<S: MiniWrite> MiniWrite for Buffering<S> {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.doc.extend_from_slice(buf);
+        Ok(())
+    }
+}
+
+impl<S: MiniWrite> Sinker for Buffering<S> {
+    fn observe_end_with(&mut self, _: &Header) -> io::Result<()> {
+        self.inner.write_all(&self.doc)?;
+        self.inner.write_all(b"\n")?;
+        self.doc.clear();
+        Ok(())
+    }
+}
+
+fn input() -> Vec<u8> {
+    let mut buf = b"[".to_vec();
+    for i in 0..400_000 {
+        if 0 != i {
+            buf.extend_from_slice(b",\n  ");
+        }
+        buf.extend_from_slice(
+            format!(
+                r#"{{"id": {}, "name": "item number {}", "tags": ["a", "b", "c"], "nested": {{"x": 1.5, "y": null}}}}"#,
+                i, i
+            )
+            .as_bytes(),
+        );
+    }
+    buf.extend_from_slice(b"]");
+    buf
+}
+
+/// Documents which are mostly long strings, without escapes.
+fn long_strings() -> Vec<u8> {
+    let text = "lorem ipsum dolor sit amet ".repeat(1_000);
+    let mut buf = b"[".to_vec();
+    for i in 0..4_000 {
+        if 0 != i {
+            buf.push(b',');
+        }
+        buf.extend_from_slice(format!(r#"{{"id": {}, "text": "{}"}}"#, i, text).as_bytes());
+    }
+    buf.extend_from_slice(b"]");
+    buf
+}
+
+/// Deeply nested, pretty-printed documents, which are mostly indentation.
+fn indented() -> Vec<u8> {
+    let mut buf = b"[".to_vec();
+    for i in 0..20_000 {
+        if 0 != i {
+            buf.push(b',');
+        }
+        for depth in 1..=20 {
+            buf.push(b'\n');
+            buf.resize(buf.len() + depth * 4, b' ');
+            buf.extend_from_slice(br#"{"a":"#);
+        }
+        buf.extend_from_slice(format!("\n{}", i).as_bytes());
+        for depth in (1..=20).rev() {
+            buf.push(b'\n');
+            buf.resize(buf.len() + depth * 4, b' ');
+            buf.push(b'}');
+        }
+    }
+    buf.extend_from_slice(b"]");
+    buf
+}
+
+/// A flat array of small numbers.
+fn flat_array() -> Vec<u8> {
+    let mut buf = b"[".to_vec();
+    for i in 0..1_000_000 {
+        if 0 != i {
+            buf.push(b',');
+        }
+        buf.extend_from_slice(format!("{}", i % 100).as_bytes());
+    }
+    buf.extend_from_slice(b"]");
+    buf
+}
+
+fn time(name: &str, input: &[u8], mut run: impl FnMut()) {
+    let mut best = Duration::MAX;
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..5 {
+        let start = Instant::now();
+        run();
+        best = best.min(start.elapsed());
+    }
+    let mb = input.len() as f64 / 1024. / 1024.;
+    println!(
+        "{:>10}: {:>8.2?} ({:.0} MB/s, {} allocations)",
+        name,
+        best,
+        mb / best.as_secs_f64(),
+        (ALLOCATIONS.load(Ordering::Relaxed) - allocations) / 5
+    );
+}
+
+fn main() {
+    let input = input();
+    let config = Config::new(1);
+    time("streaming", &input, || {
+        unnest_to_ndjson_with(&input[..], Ignore {}, &config).expect("valid input")
+    });
+    time("buffering", &input, || {
+        let sink = Buffering {
+            inner: Ignore {},
+            doc: Vec::new(),
+        };
+        unnest_to_ndjson_with(&input[..], sink, &config).expect("valid input")
+    });
+    let strings = long_strings();
+    time("strings", &strings, || {
+        unnest_to_ndjson_with(&strings[..], Ignore {}, &config).expect("valid input")
+    });
+    let indented = indented();
+    time("indented", &indented, || {
+        unnest_to_ndjson_with(&indented[..], Ignore {}, &config).expect("valid input")
+    });
+    let flat = flat_array();
+    let paths = Config::new(1).header_style(HeaderStyle::PathArray);
+    time("paths", &flat, || {
+        unnest_to_ndjson_with(&flat[..], Ignore {}, &paths).expect("valid input")
+    });
+}
@@ -0,0 +1,20 @@
+use std::io;
+use unnest_ndjson::sinks::fn_sink;
+use unnest_ndjson::{unnest_to_ndjson_with, Config, UnnestError};
+
+/// Count the documents one level down, and their total size, without writing them anywhere.
+fn main() -> Result<(), UnnestError> {
+    let stdin = io::stdin();
+    let stdin = stdin.lock();
+
+    let mut documents = 0u64;
+    let mut bytes = 0u64;
+    let sink = fn_sink(|_, value| {
+        documents += 1;
+        bytes += value.len() as u64;
+    });
+    unnest_to_ndjson_with(stdin, sink, &Config::new(1))?;
+
+    println!("{} documents, {} bytes", documents, bytes);
+    Ok(())
+}
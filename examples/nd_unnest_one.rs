@@ -1,12 +1,12 @@
 use std::io;
-use unnest_ndjson::{unnest_to_ndjson, HeaderStyle};
+use unnest_ndjson::{unnest_to_ndjson_with, Config, UnnestError};
 
-fn main() -> io::Result<()> {
+fn main() -> Result<(), UnnestError> {
     let stdin = io::stdin();
     let stdin = stdin.lock();
 
     let stdout = io::stdout();
     let stdout = stdout.lock();
 
-    unnest_to_ndjson(stdin, stdout, 1, HeaderStyle::None)
+    unnest_to_ndjson_with(stdin, stdout, &Config::new(1))
 }
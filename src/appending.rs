@@ -0,0 +1,113 @@
+use crate::error::Position;
+use crate::iter::{self, Collect};
+use crate::source::Source;
+use crate::{step, Config, Document, Loc, UnnestError, UTF8_BOM};
+
+/// What [Appending::try_next] found.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Progress {
+    /// A complete document.
+    Document(Document),
+    /// The input so far ends part way through a document, or a number which may continue,
+    /// or there is no input left. [Appending::push] more, and try again.
+    NeedMoreData,
+    /// [Config::max_documents] have been produced.
+    Finished,
+}
+
+/// Documents from an input which is still being written, such as a file which another
+/// process is appending array elements to.
+///
+/// Where [crate::documents] would fail with [UnnestError::Truncated], this waits for more
+/// input instead. Each document is parsed again from its start when more input arrives,
+/// so only input after the last returned document is held.
+///
+/// ```
+/// use unnest_ndjson::{Appending, Config, Progress};
+///
+/// let mut docs = Appending::new(&Config::new(1))?;
+/// docs.push(b"[1, 2");
+/// assert!(matches!(docs.try_next()?, Progress::Document(doc) if doc.value == b"1"));
+/// // the `2` might be the start of `23`
+/// assert_eq!(Progress::NeedMoreData, docs.try_next()?);
+/// # Ok::<(), unnest_ndjson::UnnestError>(())
+/// ```
+pub struct Appending {
+    /// The state after the last returned document.
+    loc: Loc,
+    /// The input after the last returned document.
+    pending: Vec<u8>,
+    /// Where `pending` starts in the whole input, for errors.
+    position: Position,
+}
+
+impl Appending {
+    /// Start with no input, which [Appending::push] provides.
+    ///
+    /// Each [Appending::try_next] copies the parser's state, including the [Config], and
+    /// parses from the end of the last returned document, so a document which arrives in
+    /// many small pieces is parsed many times. The options which [crate::documents] does
+    /// not support are an error here.
+    pub fn new(config: &Config) -> Result<Appending, UnnestError> {
+        iter::check_supported(config, "Appending")?;
+        let mut loc = Loc::new(config.clone())?;
+        loc.pause = true;
+        Ok(Appending {
+            loc,
+            pending: Vec::new(),
+            position: Position {
+                offset: 0,
+                line: 1,
+                column: 1,
+            },
+        })
+    }
+
+    /// Add some input, which follows on from the previous input.
+    pub fn push(&mut self, data: &[u8]) {
+        self.pending.extend_from_slice(data);
+    }
+
+    /// Try to produce the next document from the input so far.
+    ///
+    /// Errors other than running out of input are returned as usual, and are permanent.
+    pub fn try_next(&mut self) -> Result<Progress, UnnestError> {
+        loop {
+            if self.loc.finished() {
+                return Ok(Progress::Finished);
+            }
+            // a byte order mark can only be recognised, and skipped, once it is complete
+            if 0 == self.position.offset && UTF8_BOM.starts_with(&self.pending) {
+                return Ok(Progress::NeedMoreData);
+            }
+            let mut loc = self.loc.clone();
+            loc.paused = false;
+            let mut sink = Collect::default();
+            let mut from = Source::with_capacity(&self.pending[..], self.pending.len().max(1))
+                .starting_at(self.position);
+            match step(&mut from, &mut sink, &mut loc) {
+                Ok(true) => (),
+                Ok(false) | Err(UnnestError::Truncated { .. }) => {
+                    return Ok(Progress::NeedMoreData)
+                }
+                Err(e) => return Err(e),
+            }
+            // a number or literal which ends with the input might not be finished
+            let closed = matches!(from.previous(), Some(b'}' | b']' | b'"' | b'\''));
+            if !closed && from.at_eof()? {
+                return Ok(Progress::NeedMoreData);
+            }
+
+            let position = from.position();
+            let consumed = (position.offset - self.position.offset) as usize;
+            self.pending.drain(..consumed);
+            self.position = position;
+            self.loc = loc;
+            if let Some(doc) = sink.ready.take() {
+                return Ok(Progress::Document(doc));
+            }
+            // the end of a top-level value, which did not produce a document
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! Reading from an `AsyncRead`, and writing to an `AsyncWrite`, behind the `async` feature.
+
+use std::io::{self, Read, Write};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio::task;
+
+use crate::{unnest_to_ndjson_with, Config, UnnestError};
+
+/// Chunks in flight, in each direction.
+const CHUNKS: usize = 4;
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// [unnest_to_ndjson_with], for tokio's `AsyncRead` and `AsyncWrite`.
+///
+/// The parser runs on tokio's blocking thread pool, and is fed chunks of input as they
+/// arrive, so it does not matter how the input is split up between reads. `to` is flushed
+/// before returning. Only the default output format is available.
+pub async fn unnest_to_ndjson_async<R, W>(
+    mut from: R,
+    mut to: W,
+    config: &Config,
+) -> Result<(), UnnestError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let (input, input_rx) = mpsc::channel::<Vec<u8>>(CHUNKS);
+    let (output_tx, mut output) = mpsc::channel::<Vec<u8>>(CHUNKS);
+    let config = config.clone();
+    let parser = task::spawn_blocking(move || {
+        let mut writer = ChannelWriter {
+            tx: output_tx,
+            buf: Vec::with_capacity(CHUNK_SIZE),
+        };
+        let reader = ChannelReader {
+            rx: input_rx,
+            chunk: Vec::new(),
+            pos: 0,
+        };
+        unnest_to_ndjson_with(reader, &mut writer, &config)?;
+        writer.flush()?;
+        Ok::<_, UnnestError>(())
+    });
+
+    let from = &mut from;
+    // owns `input`, so the parser sees the end of the input as soon as this finishes
+    let feed = async move {
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        loop {
+            let found = from.read(&mut buf).await?;
+            if 0 == found {
+                return Ok(());
+            }
+            if input.send(buf[..found].to_vec()).await.is_err() {
+                // the parser has given up, and will report why
+                return Ok(());
+            }
+        }
+    };
+
+    // owns `output`, so the parser's sends fail as soon as this gives up
+    let drain = async move {
+        while let Some(chunk) = output.recv().await {
+            to.write_all(&chunk).await?;
+        }
+        to.flush().await
+    };
+
+    let (fed, drained): (io::Result<()>, io::Result<()>) = tokio::join!(feed, drain);
+
+    let parsed = parser.await.map_err(io::Error::other)?;
+    fed?;
+    // if the output failed, the parser only knows that its sends were refused
+    drained?;
+    parsed?;
+    Ok(())
+}
+
+/// Present the chunks from the channel as a `Read`, on the blocking side.
+struct ChannelReader {
+    rx: mpsc::Receiver<Vec<u8>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.chunk.len() {
+            match self.rx.blocking_recv() {
+                Some(chunk) => {
+                    self.chunk = chunk;
+                    self.pos = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+        let available = &self.chunk[self.pos..];
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+/// Collect the output into chunks, and send them to the channel, on the blocking side.
+struct ChannelWriter {
+    tx: mpsc::Sender<Vec<u8>>,
+    buf: Vec<u8>,
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        if self.buf.len() >= CHUNK_SIZE {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.buf, Vec::with_capacity(CHUNK_SIZE));
+        self.tx
+            .blocking_send(chunk)
+            .map_err(|_| io::ErrorKind::BrokenPipe.into())
+    }
+}
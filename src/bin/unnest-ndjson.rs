@@ -1,33 +1,137 @@
 use std::env;
+use std::fmt;
+use std::fs::File;
 use std::io;
+use std::io::{BufWriter, Read, Write};
 use std::process;
 use std::str::FromStr;
-use std::thread;
 
-use unnest_ndjson::{unnest_to_ndjson, HeaderStyle};
+use unnest_ndjson::{unnest_to_ndjson_with, Config, HeaderStyle};
 
 fn main() -> io::Result<()> {
-    process::exit(
-        // evading stack problems, the lazy way
-        thread::Builder::new()
-            .stack_size(20 * 1024 * 1024)
-            .spawn(run)?
-            .join()
-            .expect("worker panicked")?,
-    )
+    process::exit(run()?)
 }
 
 fn run() -> io::Result<i32> {
     let mut args = env::args();
     let us = args.next().expect("bin name");
-    let mut header_style = HeaderStyle::None;
-    let mut target = None;
-    let usage = || eprintln!("usage: {:?} [--path] TARGET_DEPTH", us);
-    for arg in args {
+    let mut gzip_level = None;
+    let mut wrap_width = None;
+    let mut limit = None;
+    let mut skip = None;
+    let mut dump_config = false;
+    let mut input = None;
+    let mut output = None;
+    let mut style_flag = None;
+    let mut path_separator = None;
+    let usage = || {
+        eprintln!(
+            "usage: {:?} [--path | --leaf | --line | --dotted[=SEP] | --byte-range | --seq]",
+            us
+        );
+        eprintln!(
+            "    [--gzip-level N] [--wrap N] [--skip N] [--limit N] [--input FILE] [--output FILE] [--dump-config] TARGET_DEPTH"
+        );
+        eprintln!(
+            "defaults are read from UNNEST_TARGET, UNNEST_HEADER_STYLE and UNNEST_BUFFER_SIZE"
+        );
+    };
+
+    let (mut target, mut header_style, buffer_size) = match env_defaults() {
+        Ok(defaults) => defaults,
+        Err(e) => {
+            eprintln!("{}", e);
+            usage();
+            return Ok(4);
+        }
+    };
+
+    while let Some(arg) = args.next() {
+        if let Some((style, separator)) = header_flag(&arg) {
+            // the same flag twice is harmless, but it is unclear which style was meant
+            match style_flag.replace(arg.clone()) {
+                Some(previous) if previous != arg => {
+                    eprintln!("{} conflicts with {}", arg, previous);
+                    usage();
+                    return Ok(3);
+                }
+                _ => (),
+            }
+            header_style = style;
+            path_separator = separator.map(str::to_string);
+            continue;
+        }
+
         if arg.starts_with('-') {
             match arg.as_str() {
-                "--path" => {
-                    header_style = HeaderStyle::PathArray;
+                "--wrap" => {
+                    match args.next().as_deref().map(usize::from_str) {
+                        Some(Ok(v)) if v > 0 => wrap_width = Some(v),
+                        _ => {
+                            eprintln!("--wrap requires a width");
+                            usage();
+                            return Ok(3);
+                        }
+                    }
+                    continue;
+                }
+                "--skip" => {
+                    match args.next().as_deref().map(u64::from_str) {
+                        Some(Ok(v)) => skip = Some(v),
+                        _ => {
+                            eprintln!("--skip requires a number of documents");
+                            usage();
+                            return Ok(3);
+                        }
+                    }
+                    continue;
+                }
+                "--limit" => {
+                    match args.next().as_deref().map(u64::from_str) {
+                        Some(Ok(v)) => limit = Some(v),
+                        _ => {
+                            eprintln!("--limit requires a number of documents");
+                            usage();
+                            return Ok(3);
+                        }
+                    }
+                    continue;
+                }
+                "--input" => {
+                    match args.next() {
+                        Some(path) => input = Some(path),
+                        None => {
+                            eprintln!("--input requires a file");
+                            usage();
+                            return Ok(3);
+                        }
+                    }
+                    continue;
+                }
+                "--output" => {
+                    match args.next() {
+                        Some(path) => output = Some(path),
+                        None => {
+                            eprintln!("--output requires a file");
+                            usage();
+                            return Ok(3);
+                        }
+                    }
+                    continue;
+                }
+                "--dump-config" => {
+                    dump_config = true;
+                    continue;
+                }
+                "--gzip-level" => {
+                    match args.next().as_deref().map(u32::from_str) {
+                        Some(Ok(v)) if v <= 9 => gzip_level = Some(v),
+                        _ => {
+                            eprintln!("--gzip-level requires a level from 0 to 9");
+                            usage();
+                            return Ok(3);
+                        }
+                    }
                     continue;
                 }
                 _ => {
@@ -56,13 +160,162 @@ fn run() -> io::Result<i32> {
         }
     };
 
+    let mut config = Config::new(target)
+        .header_style(header_style)
+        .wrap_width(wrap_width);
+    if let Some(separator) = path_separator {
+        config = config.path_separator(separator);
+    }
+    if let Some(skip) = skip {
+        config = config.skip_documents(skip);
+    }
+    if let Some(limit) = limit {
+        config = config.max_documents(limit);
+    }
+    if let Some(buffer_size) = buffer_size {
+        config = config.buffer_size(buffer_size);
+    }
+    let config = match gzip_level {
+        #[cfg(feature = "gzip")]
+        Some(level) => config.output_compression(level),
+        #[cfg(not(feature = "gzip"))]
+        Some(_) => {
+            eprintln!("--gzip-level: built without the gzip feature");
+            return Ok(3);
+        }
+        None => config,
+    };
+
+    if dump_config {
+        io::stdout().write_all(config.to_string().as_bytes())?;
+        return Ok(0);
+    }
+
     let stdin = io::stdin();
-    let stdin = stdin.lock();
+    let from: Box<dyn Read> = match input {
+        Some(path) => match File::open(&path) {
+            Ok(file) => Box::new(file),
+            Err(e) => {
+                eprintln!("opening {:?}: {}", path, e);
+                return Ok(6);
+            }
+        },
+        None => Box::new(stdin.lock()),
+    };
+    let from = decompressed(from)?;
 
     let stdout = io::stdout();
-    let stdout = stdout.lock();
+    let to: Box<dyn Write> = match output {
+        Some(path) => match File::create(&path) {
+            Ok(file) => Box::new(BufWriter::new(file)),
+            Err(e) => {
+                eprintln!("creating {:?}: {}", path, e);
+                return Ok(7);
+            }
+        },
+        None => Box::new(stdout.lock()),
+    };
+    let mut to = Output {
+        inner: to,
+        failed: false,
+    };
 
-    unnest_to_ndjson(stdin, stdout, target, header_style)?;
+    let result = unnest_to_ndjson_with(from, &mut to, &config).map_err(io::Error::from);
+    match result.and_then(|()| to.flush()) {
+        Err(e) if to.failed => {
+            eprintln!("writing the output: {}", e);
+            return Ok(7);
+        }
+        result => result?,
+    }
 
     Ok(0)
 }
+
+/// Decompress the input, if it starts like a gzip file.
+#[cfg(feature = "gzip")]
+fn decompressed(from: Box<dyn Read>) -> io::Result<Box<dyn Read>> {
+    use std::io::BufRead as _;
+
+    let mut from = io::BufReader::new(from);
+    Ok(if from.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+        Box::new(flate2::read::MultiGzDecoder::new(from))
+    } else {
+        Box::new(from)
+    })
+}
+
+#[cfg(not(feature = "gzip"))]
+fn decompressed(from: Box<dyn Read>) -> io::Result<Box<dyn Read>> {
+    Ok(from)
+}
+
+/// Remember whether writing failed, to tell it apart from a problem with the input.
+struct Output<W> {
+    inner: W,
+    failed: bool,
+}
+
+impl<W: Write> Write for Output<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let result = self.inner.write(buf);
+        self.failed |= result.is_err();
+        result
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let result = self.inner.flush();
+        self.failed |= result.is_err();
+        result
+    }
+}
+
+/// The target, header style and buffer size, from the environment, for containers.
+/// Arguments take precedence.
+fn env_defaults() -> Result<(Option<usize>, HeaderStyle, Option<usize>), String> {
+    Ok((
+        from_env("UNNEST_TARGET", usize::from_str)?,
+        from_env("UNNEST_HEADER_STYLE", header_style_from_str)?.unwrap_or(HeaderStyle::None),
+        from_env("UNNEST_BUFFER_SIZE", usize::from_str)?,
+    ))
+}
+
+/// Read a default from the environment, if it is set.
+fn from_env<T, E: fmt::Display>(
+    name: &str,
+    parse: impl FnOnce(&str) -> Result<T, E>,
+) -> Result<Option<T>, String> {
+    match env::var(name) {
+        Ok(value) => parse(&value)
+            .map(Some)
+            .map_err(|e| format!("invalid {}: {:?}: {}", name, value, e)),
+        Err(env::VarError::NotPresent) => Ok(None),
+        Err(e) => Err(format!("invalid {}: {}", name, e)),
+    }
+}
+
+/// The header style chosen by a flag, and any separator given with `--dotted`.
+fn header_flag(arg: &str) -> Option<(HeaderStyle, Option<&str>)> {
+    Some(match arg {
+        "--path" => (HeaderStyle::PathArray, None),
+        "--leaf" => (HeaderStyle::LeafKey, None),
+        "--line" => (HeaderStyle::WithLine, None),
+        "--dotted" => (HeaderStyle::Dotted, None),
+        "--byte-range" => (HeaderStyle::ByteRange, None),
+        "--seq" => (HeaderStyle::Sequence, None),
+        _ => (HeaderStyle::Dotted, Some(arg.strip_prefix("--dotted=")?)),
+    })
+}
+
+fn header_style_from_str(value: &str) -> Result<HeaderStyle, &'static str> {
+    Ok(match value {
+        "none" => HeaderStyle::None,
+        "path" => HeaderStyle::PathArray,
+        "leaf" => HeaderStyle::LeafKey,
+        "line" => HeaderStyle::WithLine,
+        "dotted" => HeaderStyle::Dotted,
+        "range" => HeaderStyle::ByteRange,
+        "seq" => HeaderStyle::Sequence,
+        _ => return Err("expected none, path, leaf, line, dotted, range or seq"),
+    })
+}
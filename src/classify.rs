@@ -0,0 +1,153 @@
+//! Guessing what a string holds, for [crate::Config::classify_strings].
+
+/// A guess at the content of a string, from its shape alone.
+///
+/// The guesses are cheap, and hence loose: `"2024-13-45"` is a [StringClass::Date].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum StringClass {
+    /// A UUID, like `123e4567-e89b-12d3-a456-426614174000`, in either case.
+    Uuid,
+    /// An ISO 8601 date, like `2024-01-01`, possibly followed by a time.
+    Date,
+    /// A URL with a scheme, like `https://example.com/`.
+    Url,
+    /// An email address, like `a@b.com`.
+    Email,
+    /// At least 16 characters of padded base64.
+    Base64,
+    /// Anything else.
+    Text,
+}
+
+/// Classify the content of a JSON string, without the quotes. Escapes are not decoded.
+pub(crate) fn classify(s: &[u8]) -> StringClass {
+    if is_uuid(s) {
+        StringClass::Uuid
+    } else if is_date(s) {
+        StringClass::Date
+    } else if is_url(s) {
+        StringClass::Url
+    } else if is_email(s) {
+        StringClass::Email
+    } else if is_base64(s) {
+        StringClass::Base64
+    } else {
+        StringClass::Text
+    }
+}
+
+fn is_uuid(s: &[u8]) -> bool {
+    36 == s.len()
+        && s.iter().enumerate().all(|(i, &b)| match i {
+            8 | 13 | 18 | 23 => b'-' == b,
+            _ => b.is_ascii_hexdigit(),
+        })
+}
+
+fn is_date(s: &[u8]) -> bool {
+    let date = match s.get(..10) {
+        Some(date) => date,
+        None => return false,
+    };
+    let shaped = date.iter().enumerate().all(|(i, &b)| match i {
+        4 | 7 => b'-' == b,
+        _ => b.is_ascii_digit(),
+    });
+    shaped && matches!(s.get(10), None | Some(b'T' | b' '))
+}
+
+fn is_url(s: &[u8]) -> bool {
+    let colon = match s.windows(3).position(|w| b"://" == w) {
+        Some(colon) => colon,
+        None => return false,
+    };
+    let (scheme, rest) = (&s[..colon], &s[colon + 3..]);
+    scheme.first().is_some_and(u8::is_ascii_alphabetic)
+        && scheme
+            .iter()
+            .all(|&b| b.is_ascii_alphanumeric() || b"+-.".contains(&b))
+        && !rest.is_empty()
+        && !rest.iter().any(u8::is_ascii_whitespace)
+}
+
+fn is_email(s: &[u8]) -> bool {
+    let at = match s.iter().position(|&b| b'@' == b) {
+        Some(at) => at,
+        None => return false,
+    };
+    let (local, domain) = (&s[..at], &s[at + 1..]);
+    !local.is_empty()
+        && domain.len() > 2
+        && domain[1..domain.len() - 1].contains(&b'.')
+        && !s[at + 1..].contains(&b'@')
+        && !s
+            .iter()
+            .any(|&b| b.is_ascii_whitespace() || b'"' == b || b'\\' == b)
+}
+
+fn is_base64(s: &[u8]) -> bool {
+    let data = s
+        .strip_suffix(b"==")
+        .or_else(|| s.strip_suffix(b"="))
+        .unwrap_or(s);
+    s.len() >= 16
+        && s.len().is_multiple_of(4)
+        && data
+            .iter()
+            .all(|&b| b.is_ascii_alphanumeric() || b'+' == b || b'/' == b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{classify, StringClass};
+
+    #[test]
+    fn classes() {
+        for (class, examples) in [
+            (
+                StringClass::Uuid,
+                &[
+                    "123e4567-e89b-12d3-a456-426614174000",
+                    "123E4567-E89B-12D3-A456-426614174000",
+                ][..],
+            ),
+            (
+                StringClass::Date,
+                &["2024-01-01", "2024-01-01T12:00:00Z", "2024-01-01 12:00"],
+            ),
+            (
+                StringClass::Url,
+                &["https://example.com/", "s3://bucket/key", "git+ssh://host"],
+            ),
+            (
+                StringClass::Email,
+                &["a@b.com", "first.last+tag@example.co.uk"],
+            ),
+            (
+                StringClass::Base64,
+                &["aGVsbG8gd29ybGQh", "aGVsbG8gd29ybGQhIQ=="],
+            ),
+            (
+                StringClass::Text,
+                &[
+                    "",
+                    "hello",
+                    "2024-01",
+                    "20240101",
+                    "a@b",
+                    "@b.com",
+                    "a@b.com c",
+                    "://x",
+                    "http://",
+                    "hello world of strings",
+                    "aGVsbG8gd29ybGQh!",
+                ],
+            ),
+        ] {
+            for example in examples {
+                assert_eq!(class, classify(example.as_bytes()), "{:?}", example);
+            }
+        }
+    }
+}
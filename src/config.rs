@@ -0,0 +1,1042 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::source;
+use crate::{HeaderStyle, NumberRounding};
+
+/// Settings for [crate::unnest_to_ndjson_with].
+///
+/// Start from [Config::new], and adjust with the builder methods.
+/// Everything is off by default, leaving the streaming path described in the [crate] docs.
+/// New settings are added here, rather than as arguments to [crate::unnest_to_ndjson].
+///
+/// ```
+/// use unnest_ndjson::{unnest_to_ndjson_with, Config, HeaderStyle};
+///
+/// let config = Config::new(1).header_style(HeaderStyle::PathArray);
+/// let mut out = Vec::new();
+/// unnest_to_ndjson_with(&br#"{"a": 5}"#[..], &mut out, &config)?;
+/// assert_eq!(&b"{\"key\":[\"a\"],\"value\":5}\n"[..], &out[..]);
+/// # Ok::<(), unnest_ndjson::UnnestError>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub(crate) target: usize,
+    pub(crate) extra_targets: Vec<usize>,
+    pub(crate) leaves: bool,
+    pub(crate) header_style: HeaderStyle,
+    pub(crate) skeleton: bool,
+    pub(crate) verbatim: bool,
+    pub(crate) classify_strings: bool,
+    pub(crate) raw_strings: bool,
+    pub(crate) attach_comments: bool,
+    pub(crate) allow_comments: bool,
+    pub(crate) enforce_uniform_keys: bool,
+    pub(crate) reject_duplicate_keys: bool,
+    pub(crate) continue_on_error: bool,
+    pub(crate) max_documents_per_top_level: Option<u64>,
+    pub(crate) max_documents: Option<u64>,
+    pub(crate) skip_documents: u64,
+    pub(crate) path_separator: Vec<u8>,
+    pub(crate) key_field: Vec<u8>,
+    pub(crate) sequence_width: usize,
+    pub(crate) value_field: Vec<u8>,
+    pub(crate) record_separator: Vec<u8>,
+    pub(crate) trailing_separator: bool,
+    pub(crate) json_seq: bool,
+    pub(crate) observe_paths: bool,
+    pub(crate) pretty: Option<usize>,
+    pub(crate) wrap_width: Option<usize>,
+    pub(crate) path_prefix: Vec<Vec<u8>>,
+    pub(crate) root_path: Vec<Vec<u8>>,
+    pub(crate) document_delimiter: Option<Vec<u8>>,
+    pub(crate) require_document_delimiter: bool,
+    pub(crate) single_document: bool,
+    pub(crate) include_keys: Vec<Vec<u8>>,
+    pub(crate) exclude_keys: Vec<Vec<u8>>,
+    pub(crate) rename_keys: HashMap<Vec<u8>, Vec<u8>>,
+    pub(crate) normalize_path_keys: Option<NormalizeKey>,
+    pub(crate) filter_equals: Option<(Vec<Vec<u8>>, Vec<u8>)>,
+    pub(crate) group_by: Option<Vec<Vec<u8>>>,
+    pub(crate) fingerprint: Option<(Vec<Vec<u8>>, Fingerprint)>,
+    pub(crate) max_groups: usize,
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) buffer_size: usize,
+    pub(crate) heartbeat: Option<Duration>,
+    pub(crate) heartbeat_record: Vec<u8>,
+    pub(crate) progress_every: Option<u64>,
+    pub(crate) scalar_root: ScalarRoot,
+    pub(crate) flatten: Option<Vec<u8>>,
+    pub(crate) collisions: Collisions,
+    pub(crate) validate_numbers: bool,
+    pub(crate) normalize_numbers: bool,
+    pub(crate) number_precision: Option<NumberRounding>,
+    pub(crate) validate_literals: bool,
+    pub(crate) python_literals: bool,
+    pub(crate) validate_surrogates: bool,
+    pub(crate) validate_utf8: bool,
+    pub(crate) reject_control_characters: bool,
+    pub(crate) escape_control_characters: bool,
+    pub(crate) allow_raw_newlines: bool,
+    pub(crate) spec_whitespace: bool,
+    pub(crate) reject_trailing_commas: bool,
+    pub(crate) allow_js_numbers: bool,
+    pub(crate) allow_single_quotes: bool,
+    pub(crate) allow_unquoted_keys: bool,
+    #[cfg(feature = "gzip")]
+    pub(crate) output_compression: Option<u32>,
+}
+
+impl Config {
+    /// Produce every scalar, however deep it is, with its path as a [HeaderStyle::PathArray]
+    /// header, e.g. `{"a":[1,{"b":2}]}` becomes `["a",0]` and `["a",1,"b"]`.
+    ///
+    /// This is like a `target` deeper than the input. Empty objects and arrays have no
+    /// scalars, so produce nothing.
+    pub fn leaves() -> Self {
+        Config {
+            leaves: true,
+            ..Config::new(0).header_style(HeaderStyle::PathArray)
+        }
+    }
+
+    /// Remove `target` levels of nesting, with no header. See [crate::unnest_to_ndjson].
+    pub fn new(target: usize) -> Self {
+        Config {
+            target,
+            extra_targets: Vec::new(),
+            leaves: false,
+            header_style: HeaderStyle::None,
+            skeleton: false,
+            verbatim: false,
+            classify_strings: false,
+            raw_strings: false,
+            attach_comments: false,
+            allow_comments: false,
+            enforce_uniform_keys: false,
+            reject_duplicate_keys: false,
+            continue_on_error: false,
+            max_documents_per_top_level: None,
+            max_documents: None,
+            skip_documents: 0,
+            path_separator: b".".to_vec(),
+            key_field: b"key".to_vec(),
+            sequence_width: 0,
+            value_field: b"value".to_vec(),
+            record_separator: b"\n".to_vec(),
+            trailing_separator: true,
+            json_seq: false,
+            observe_paths: false,
+            pretty: None,
+            wrap_width: None,
+            path_prefix: Vec::new(),
+            root_path: Vec::new(),
+            document_delimiter: None,
+            require_document_delimiter: true,
+            single_document: false,
+            include_keys: Vec::new(),
+            exclude_keys: Vec::new(),
+            rename_keys: HashMap::new(),
+            normalize_path_keys: None,
+            filter_equals: None,
+            group_by: None,
+            fingerprint: None,
+            max_groups: 10_000,
+            max_depth: None,
+            buffer_size: source::DEFAULT_CAPACITY,
+            heartbeat: None,
+            heartbeat_record: br#"{"__heartbeat__":true}"#.to_vec(),
+            progress_every: None,
+            scalar_root: ScalarRoot::Emit,
+            flatten: None,
+            collisions: Collisions::Error,
+            validate_numbers: false,
+            normalize_numbers: false,
+            number_precision: None,
+            validate_literals: false,
+            python_literals: false,
+            validate_surrogates: false,
+            validate_utf8: false,
+            reject_control_characters: false,
+            escape_control_characters: false,
+            allow_raw_newlines: false,
+            spec_whitespace: false,
+            reject_trailing_commas: false,
+            allow_js_numbers: false,
+            allow_single_quotes: false,
+            allow_unquoted_keys: false,
+            #[cfg(feature = "gzip")]
+            output_compression: None,
+        }
+    }
+
+    /// Also produce the documents at each of these depths, in the same pass, e.g. `1` for
+    /// each record, and `3` for each of its fields' fields. Default: empty.
+    ///
+    /// Each has a `"target"` in its header, for which depth produced it, even with
+    /// [HeaderStyle::None]. See [crate::Header::target]. A document is produced after the
+    /// documents inside it, which it still contains. Depths which are not deeper than the
+    /// target are ignored, as are scalars which are shallower than these depths, which are
+    /// produced by the target. Not supported by [crate::documents], [Config::leaves] or
+    /// [Config::flatten_to_object].
+    pub fn extra_targets(mut self, depths: impl IntoIterator<Item = usize>) -> Self {
+        self.extra_targets = depths.into_iter().collect();
+        self
+    }
+
+    /// How much context to retain. See [HeaderStyle].
+    pub fn header_style(mut self, header_style: HeaderStyle) -> Self {
+        self.header_style = header_style;
+        self
+    }
+
+    /// Compute the path to each document, and pass it to [crate::Sinker::observe_new_item],
+    /// whatever the [HeaderStyle], e.g. to route documents without writing their paths.
+    /// Default: `false`, so the path is only computed if the header style includes it.
+    pub fn observe_paths(mut self, observe_paths: bool) -> Self {
+        self.observe_paths = observe_paths;
+        self
+    }
+
+    /// The name of the field holding the path, for [HeaderStyle::PathArray].
+    /// Defaults to `key`.
+    ///
+    /// The name is escaped as necessary to make it a JSON string.
+    pub fn key_field(mut self, name: impl Into<Vec<u8>>) -> Self {
+        self.key_field = name.into();
+        self
+    }
+
+    /// The name of the field holding the document, when it is wrapped in a header.
+    /// Defaults to `value`.
+    ///
+    /// The name is escaped as necessary to make it a JSON string.
+    pub fn value_field(mut self, name: impl Into<Vec<u8>>) -> Self {
+        self.value_field = name.into();
+        self
+    }
+
+    /// The bytes written after each document. Defaults to `\n`, producing ndjson.
+    ///
+    /// For example, `\x1e` (the ASCII record separator) between records.
+    /// This applies to every [crate::HeaderStyle], including [crate::HeaderStyle::None].
+    pub fn record_separator(mut self, separator: impl Into<Vec<u8>>) -> Self {
+        self.record_separator = separator.into();
+        self
+    }
+
+    /// Whether the [Config::record_separator] is written after every document, or only
+    /// between documents, so there is nothing after the last one. Default: `true`.
+    pub fn trailing_separator(mut self, trailing: bool) -> Self {
+        self.trailing_separator = trailing;
+        self
+    }
+
+    /// Produce a JSON text sequence, as in RFC 7464: each document is preceded by an
+    /// ASCII record separator, `\x1e`, and followed by the [Config::record_separator],
+    /// which should be left as `\n`. Default: `false`.
+    pub fn json_seq(mut self, json_seq: bool) -> Self {
+        self.json_seq = json_seq;
+        self
+    }
+
+    /// Indent each document across multiple lines, by `width` spaces per level, for
+    /// reading by eye. Default: `None`, producing each document on one line.
+    ///
+    /// The header, and the [Config::record_separator], are unchanged, so each document
+    /// still ends at a separator, but is no longer a single line.
+    pub fn pretty(mut self, width: Option<usize>) -> Self {
+        self.pretty = width;
+        self
+    }
+
+    /// Break lines longer than `width` bytes, for reading in a terminal. Default: `None`.
+    ///
+    /// Lines are broken after a `,`, `{` or `[` which is not in a string, so a line may
+    /// still be longer than this, e.g. if it contains a long string. The output is no
+    /// longer ndjson, as a document may span several lines. The documents, and their
+    /// headers, are written with the default formatting, even if the sink would not.
+    pub fn wrap_width(mut self, width: Option<usize>) -> Self {
+        self.wrap_width = width;
+        self
+    }
+
+    /// Zero-pad the index to this many digits, for [HeaderStyle::Sequence], so that the
+    /// indexes sort correctly as strings. Defaults to `0`, producing a number instead.
+    pub fn sequence_width(mut self, width: usize) -> Self {
+        self.sequence_width = width;
+        self
+    }
+
+    /// The string between path segments, for [HeaderStyle::Dotted]. Defaults to `.`.
+    pub fn path_separator(mut self, separator: impl Into<Vec<u8>>) -> Self {
+        self.path_separator = separator.into();
+        self
+    }
+
+    /// Expect these bytes between top-level values in the input, e.g. `\n---\n`, as in a
+    /// YAML stream. Default: `None`, where values are only separated by whitespace.
+    ///
+    /// Whitespace around the delimiter is ignored, like any whitespace between values.
+    /// The delimiter may also appear before the first value, and after the last. A missing
+    /// delimiter is an error, unless [Config::require_document_delimiter] is disabled.
+    /// The delimiter must fit in [Config::buffer_size].
+    pub fn document_delimiter(mut self, delimiter: Option<Vec<u8>>) -> Self {
+        self.document_delimiter = delimiter;
+        self
+    }
+
+    /// Whether a [Config::document_delimiter] must appear between top-level values.
+    /// Default: `true`.
+    pub fn require_document_delimiter(mut self, require: bool) -> Self {
+        self.require_document_delimiter = require;
+        self
+    }
+
+    /// Require the input to be exactly one top-level value, followed only by whitespace, as
+    /// a JSON file is, instead of a stream of them. Default: `false`.
+    ///
+    /// Anything after the first value is an error, even another valid value, such as `null`.
+    pub fn single_document(mut self, single_document: bool) -> Self {
+        self.single_document = single_document;
+        self
+    }
+
+    /// Only produce documents whose path starts with these segments. Default: empty,
+    /// producing every document.
+    ///
+    /// Segments are written as they appear in a [HeaderStyle::PathArray] key: keys are
+    /// JSON strings, including the quotes, and indexes are numbers, e.g.
+    /// `[r#""results""#, "0"]`. Keys are compared as written in the input, so escapes
+    /// are not decoded. Anything outside the prefix is skipped without calling the sink.
+    /// A prefix longer than the target matches nothing.
+    pub fn path_prefix<S: Into<Vec<u8>>>(mut self, prefix: impl IntoIterator<Item = S>) -> Self {
+        self.path_prefix = prefix.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Treat the value at this path as the input, and unnest it instead. Default: empty,
+    /// using the whole input.
+    ///
+    /// Segments are written as for [Config::path_prefix]. The [Config::target], the
+    /// [Config::path_prefix], and the paths given to the sink, are all relative to the
+    /// root. Anything outside the root is skipped without calling the sink.
+    pub fn root_path<S: Into<Vec<u8>>>(mut self, root: impl IntoIterator<Item = S>) -> Self {
+        self.root_path = root.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Only produce documents whose key, the last segment of their path, matches one of
+    /// these patterns. Default: empty, producing every document.
+    ///
+    /// In a pattern, `*` matches anything, and `?` matches any single byte, e.g. `*_id`.
+    /// Keys are matched without their quotes, and array indexes as numbers. A document
+    /// with no key, at a target of `0`, has the key `""`. See also [Config::exclude_keys].
+    pub fn include_keys<S: Into<Vec<u8>>>(mut self, patterns: impl IntoIterator<Item = S>) -> Self {
+        self.include_keys = patterns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Do not produce documents whose key matches one of these patterns, even if it is
+    /// also included by [Config::include_keys]. Default: empty.
+    pub fn exclude_keys<S: Into<Vec<u8>>>(mut self, patterns: impl IntoIterator<Item = S>) -> Self {
+        self.exclude_keys = patterns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Fail with [crate::UnnestError::TooDeep] if containers are nested more than
+    /// `max_depth` deep, anywhere in the input. By default, there is no limit.
+    ///
+    /// `[[1]]` has a depth of `2`, and `1` a depth of `0`.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// How much of the input to read at a time, in bytes. The default is 16KiB.
+    ///
+    /// This is the only memory used for the input; a small value suits tiny inputs, and a
+    /// larger value may be faster on fast storage. Values below `1` are treated as `1`.
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = buffer_size.max(1);
+        self
+    }
+
+    /// Produce a [Config::heartbeat_record] if no document has been produced for this long,
+    /// e.g. to keep a streaming connection alive while the input is slow. Default: `None`.
+    ///
+    /// Heartbeats are only produced between documents, and only once some input arrives;
+    /// a reader which blocks forever will not produce any. They are written to a `Write`,
+    /// but other sinks, such as [crate::sinks::KeyedSink], ignore them, unless they
+    /// implement [crate::Sinker::observe_heartbeat].
+    pub fn heartbeat(mut self, heartbeat: Option<Duration>) -> Self {
+        self.heartbeat = heartbeat;
+        self
+    }
+
+    /// Tell the sink how much of the input has been consumed, through
+    /// [crate::Sinker::observe_progress], after a document which ends at least this many
+    /// bytes after the last report, and at the end of the input. Default: `None`.
+    ///
+    /// `Some(0)` reports after every document. Nothing is reported in the middle of a
+    /// document, or while skipping values, so reports may be further apart than this.
+    pub fn progress_every(mut self, bytes: Option<u64>) -> Self {
+        self.progress_every = bytes;
+        self
+    }
+
+    /// The line to produce for [Config::heartbeat], without the newline.
+    /// The default is `{"__heartbeat__":true}`.
+    pub fn heartbeat_record(mut self, record: impl Into<Vec<u8>>) -> Self {
+        self.heartbeat_record = record.into();
+        self
+    }
+
+    /// Rename object keys, e.g. from `userId` to `user_id`, in the documents and in their
+    /// paths. Default: empty, leaving every key as it was.
+    ///
+    /// Keys are matched as they appear between the quotes in the input, so escapes are not
+    /// decoded. The new names are escaped as necessary to make them JSON strings. Other
+    /// options which consider keys, such as [Config::path_prefix], see the new names.
+    pub fn rename_keys(mut self, renames: HashMap<Vec<u8>, Vec<u8>>) -> Self {
+        self.rename_keys = renames;
+        self
+    }
+
+    /// Normalize object keys in the paths of documents, e.g. so `{"A": 1}` and `{"a": 1}`
+    /// have the same path. Default: `None`, leaving the keys as they are.
+    ///
+    /// The documents themselves are unchanged. Other options which consider the path, such
+    /// as [Config::path_prefix], see the normalized keys.
+    pub fn normalize_path_keys(mut self, normalize: Option<NormalizeKey>) -> Self {
+        self.normalize_path_keys = normalize;
+        self
+    }
+
+    /// Only produce documents which have `value` at `path` inside them, e.g. only
+    /// `{"status":"active",..}` for `([r#""status""#], r#""active""#)`. Default: `None`,
+    /// producing every document.
+    ///
+    /// Path segments are keys written as in a [HeaderStyle::PathArray] key, including the
+    /// quotes, following nested objects; array indexes are not supported. The value is
+    /// compared with the compact JSON of the field, as written in the input, so escapes
+    /// are not decoded. Each document is held in memory, to check it before it is produced.
+    /// Only documents which match count towards [Config::max_documents], and are numbered
+    /// by [HeaderStyle::Sequence].
+    ///
+    /// Not supported by [crate::documents] or [crate::Appending].
+    pub fn filter_equals<S: Into<Vec<u8>>>(
+        mut self,
+        path: impl IntoIterator<Item = S>,
+        value: impl Into<Vec<u8>>,
+    ) -> Self {
+        self.filter_equals = Some((path.into_iter().map(Into::into).collect(), value.into()));
+        self
+    }
+
+    /// Instead of the documents, produce a line for each distinct value at `path` inside
+    /// them, with how many documents had it, e.g. `{"value":"active","count":12}`, after
+    /// the input is finished. Default: `None`, producing the documents.
+    ///
+    /// The path is as for [Config::filter_equals], which is applied first. Documents
+    /// without a scalar at the path are not counted. Values are compared as written in the
+    /// input, and the lines are ordered by value. Only the counts are held in memory, for
+    /// up to [Config::max_groups] distinct values.
+    pub fn group_by<S: Into<Vec<u8>>>(mut self, path: impl IntoIterator<Item = S>) -> Self {
+        self.group_by = Some(path.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Fail, rather than count more than this many distinct values, for
+    /// [Config::group_by]. Default: `10000`.
+    pub fn max_groups(mut self, max_groups: usize) -> Self {
+        self.max_groups = max_groups;
+        self
+    }
+
+    /// Add a hash of each document to its header, e.g. `{"fingerprint":"…","value":…}`,
+    /// for noticing which documents have changed. Default: `None`.
+    ///
+    /// The hash is of the document with the keys of every object sorted, and without the
+    /// `exclude`d keys of the document itself, so volatile fields like timestamps can be
+    /// ignored. Keys are JSON strings, including the quotes, as they appear in the input.
+    /// Each document is held in memory. The document is always wrapped in a header.
+    pub fn fingerprint<S: Into<Vec<u8>>>(
+        mut self,
+        exclude: impl IntoIterator<Item = S>,
+        algorithm: Fingerprint,
+    ) -> Self {
+        self.fingerprint = Some((exclude.into_iter().map(Into::into).collect(), algorithm));
+        self
+    }
+
+    /// What to do with a top-level value which is a scalar, like `42`, when the target is
+    /// not `0`, so there is nothing to remove. See [ScalarRoot].
+    pub fn scalar_root(mut self, scalar_root: ScalarRoot) -> Self {
+        self.scalar_root = scalar_root;
+        self
+    }
+
+    /// Produce each document as a single, flat object, with a key for every leaf, made by
+    /// joining its path inside the document with `separator`, e.g. `{"a":{"b":[5]}}`
+    /// becomes `{"a.b.0":5}`. With a target of `0`, the whole input is one object.
+    ///
+    /// Leaves are scalars, and empty objects and arrays. Keys are joined as written in
+    /// the input, so escapes are not decoded. Each document is held in memory. Two leaves
+    /// with the same key are handled according to [Config::collisions].
+    pub fn flatten_to_object(mut self, separator: impl Into<Vec<u8>>) -> Self {
+        self.flatten = Some(separator.into());
+        self
+    }
+
+    /// What to do when two leaves have the same key, for [Config::flatten_to_object].
+    pub fn collisions(mut self, collisions: Collisions) -> Self {
+        self.collisions = collisions;
+        self
+    }
+
+    /// Reject numbers which do not follow the JSON grammar, such as `01`, `1.` or `+1`.
+    ///
+    /// Both `e` and `E` are accepted for the exponent. Otherwise, anything which is not
+    /// a string or container is copied through as-is.
+    pub fn validate_numbers(mut self, validate_numbers: bool) -> Self {
+        self.validate_numbers = validate_numbers;
+        self
+    }
+
+    /// Write numbers in a canonical form: currently, this only lower-cases the exponent
+    /// marker, so `1E10` becomes `1e10`.
+    pub fn normalize_numbers(mut self, normalize_numbers: bool) -> Self {
+        self.normalize_numbers = normalize_numbers;
+        self
+    }
+
+    /// Round numbers with more digits than `rounding` allows. See [NumberRounding].
+    ///
+    /// Numbers without a fraction or exponent are never changed, however large, and nor are
+    /// numbers which do not follow the JSON grammar.
+    pub fn number_precision(mut self, rounding: Option<NumberRounding>) -> Self {
+        self.number_precision = rounding;
+        self
+    }
+
+    /// Reject scalars which are not strings or numbers, unless they are exactly `true`,
+    /// `false` or `null`. Otherwise, barewords like `TRUE` or `nulll` are copied through.
+    pub fn validate_literals(mut self, validate_literals: bool) -> Self {
+        self.validate_literals = validate_literals;
+        self
+    }
+
+    /// Write `true`, `false` and `null` as Python's `True`, `False` and `None`, so each
+    /// document can be read with `ast.literal_eval`. The output is no longer JSON.
+    ///
+    /// Strings are left as JSON strings, which Python reads the same, except for the
+    /// escapes `\/`, and surrogate pairs, like `\ud83d\ude00`. Headers are unchanged.
+    pub fn python_literals(mut self, python_literals: bool) -> Self {
+        self.python_literals = python_literals;
+        self
+    }
+
+    /// Reject `\u` escapes which do not form valid UTF-16: a high surrogate (`\uD800` to
+    /// `\uDBFF`) must be immediately followed by a low surrogate (`\uDC00` to `\uDFFF`),
+    /// and a low surrogate must not appear on its own.
+    pub fn validate_surrogates(mut self, validate_surrogates: bool) -> Self {
+        self.validate_surrogates = validate_surrogates;
+        self
+    }
+
+    /// Reject strings, and keys, which are not valid UTF-8, e.g. with a truncated
+    /// multi-byte character, instead of copying the bytes as they are.
+    pub fn validate_utf8(mut self, validate_utf8: bool) -> Self {
+        self.validate_utf8 = validate_utf8;
+        self
+    }
+
+    /// Reject strings containing raw control characters (below `0x20`), which must be escaped.
+    ///
+    /// Otherwise, line feeds and carriage returns are rejected, unless
+    /// [Config::escape_control_characters] or [Config::allow_raw_newlines] is set, and the
+    /// others are copied as they are, which is not valid JSON, unless they are escaped.
+    pub fn reject_control_characters(mut self, reject_control_characters: bool) -> Self {
+        self.reject_control_characters = reject_control_characters;
+        self
+    }
+
+    /// Escape raw control characters in strings, e.g. a tab becomes `\t`, so the output is
+    /// valid JSON. Default: `false`. See [Config::reject_control_characters].
+    ///
+    /// This has no effect if [Config::reject_control_characters] is set.
+    pub fn escape_control_characters(mut self, escape_control_characters: bool) -> Self {
+        self.escape_control_characters = escape_control_characters;
+        self
+    }
+
+    /// Copy raw line feeds and carriage returns in strings as they are, instead of rejecting
+    /// them. Default: `false`.
+    ///
+    /// A copied line feed splits the document across two lines of the output, so this is
+    /// only useful if the output is not read as ndjson, e.g. with [Config::pretty].
+    /// This has no effect if [Config::reject_control_characters] or
+    /// [Config::escape_control_characters] is set.
+    pub fn allow_raw_newlines(mut self, allow_raw_newlines: bool) -> Self {
+        self.allow_raw_newlines = allow_raw_newlines;
+        self
+    }
+
+    /// Only accept the whitespace allowed by JSON: space, tab, line feed and carriage return.
+    ///
+    /// Otherwise, a form feed is also skipped.
+    pub fn spec_whitespace(mut self, spec_whitespace: bool) -> Self {
+        self.spec_whitespace = spec_whitespace;
+        self
+    }
+
+    /// Reject commas which do not separate two values, as in `[1,]`, `{"a":1,}` or
+    /// `{,"a":1}`. Otherwise, they are dropped, or copied with [Config::verbatim].
+    pub fn reject_trailing_commas(mut self, reject_trailing_commas: bool) -> Self {
+        self.reject_trailing_commas = reject_trailing_commas;
+        self
+    }
+
+    /// Accept the numbers JavaScript has, but JSON does not: `NaN`, `Infinity` and
+    /// `-Infinity` are written as `null`, as `JSON.stringify` does, and a leading `+`
+    /// is dropped.
+    pub fn allow_js_numbers(mut self, allow_js_numbers: bool) -> Self {
+        self.allow_js_numbers = allow_js_numbers;
+        self
+    }
+
+    /// Accept strings, and object keys, in single quotes, as in `{'a': 'b'}`.
+    ///
+    /// They are written with double quotes, as `{"a":"b"}`: a `"` inside is escaped,
+    /// and the escape `\'` is written as a plain `'`.
+    pub fn allow_single_quotes(mut self, allow_single_quotes: bool) -> Self {
+        self.allow_single_quotes = allow_single_quotes;
+        self
+    }
+
+    /// Accept object keys which are bare identifiers, as in `{a: 1, $b: 2}`, and write them
+    /// as strings, `{"a":1,"$b":2}`.
+    ///
+    /// An identifier is an ASCII letter, `_` or `$`, followed by any of those, or digits.
+    pub fn allow_unquoted_keys(mut self, allow_unquoted_keys: bool) -> Self {
+        self.allow_unquoted_keys = allow_unquoted_keys;
+        self
+    }
+
+    /// Turn on every check needed to reject input which is not valid
+    /// [RFC 8259](https://www.rfc-editor.org/rfc/rfc8259) JSON, currently:
+    ///
+    ///  * [Config::validate_numbers], which also rejects a leading `+`
+    ///  * [Config::validate_literals]
+    ///  * [Config::validate_surrogates]
+    ///  * [Config::validate_utf8]
+    ///  * [Config::reject_control_characters]
+    ///  * [Config::spec_whitespace]
+    ///  * [Config::reject_trailing_commas]
+    ///
+    /// Duplicate object keys are permitted by the RFC, so are still accepted.
+    /// Options which extend the syntax, like [Config::attach_comments], are not changed.
+    pub fn strict(self) -> Self {
+        self.validate_numbers(true)
+            .validate_literals(true)
+            .validate_surrogates(true)
+            .validate_utf8(true)
+            .reject_control_characters(true)
+            .spec_whitespace(true)
+            .reject_trailing_commas(true)
+    }
+
+    /// Accept the messy input that permissive JavaScript parsers would, and write it out
+    /// as valid JSON. This turns off the checks enabled by [Config::strict], and enables:
+    ///
+    ///  * [Config::allow_comments]
+    ///  * [Config::allow_js_numbers]
+    ///  * [Config::allow_single_quotes]
+    ///  * [Config::allow_unquoted_keys]
+    ///  * [Config::escape_control_characters]
+    ///
+    /// Trailing commas, as in `[1,2,]` or `{"a":1,}`, are accepted, and dropped.
+    pub fn lenient(self) -> Self {
+        self.validate_numbers(false)
+            .validate_literals(false)
+            .validate_surrogates(false)
+            .validate_utf8(false)
+            .reject_control_characters(false)
+            .spec_whitespace(false)
+            .reject_trailing_commas(false)
+            .allow_comments(true)
+            .allow_js_numbers(true)
+            .allow_single_quotes(true)
+            .allow_unquoted_keys(true)
+            .escape_control_characters(true)
+    }
+
+    /// Copy the whitespace and separators inside each document from the input, instead of
+    /// producing compact JSON, so `{ "a" :  5 }` is produced as it was written.
+    ///
+    /// Whitespace around the document is not included, and comments are still removed.
+    /// Trailing commas, as in `[1,2,]`, are copied too, rather than dropped.
+    /// Options which rewrite values, such as [Config::normalize_numbers], still apply.
+    /// Options which read the produced documents, such as [Config::filter_equals],
+    /// expect compact JSON, and should not be combined with this.
+    pub fn verbatim(mut self, verbatim: bool) -> Self {
+        self.verbatim = verbatim;
+        self
+    }
+
+    /// Replace every scalar in the output with the name of its type.
+    ///
+    /// `{"a":1,"b":{"c":"x"}}` would become `{"a":"number","b":{"c":"string"}}`.
+    /// The names are `"string"`, `"number"`, `"boolean"` and `"null"`.
+    /// Object keys and array structure are retained.
+    pub fn skeleton(mut self, skeleton: bool) -> Self {
+        self.skeleton = skeleton;
+        self
+    }
+
+    /// Guess what each string value holds, such as a date or a URL, and tell the sink,
+    /// through [crate::Sinker::observe_string_class]. Default: `false`.
+    ///
+    /// The default sink ignores the guesses. See [crate::StringClass].
+    pub fn classify_strings(mut self, classify_strings: bool) -> Self {
+        self.classify_strings = classify_strings;
+        self
+    }
+
+    /// Produce a document which is a string as its text, without the quotes, and with
+    /// escapes decoded, e.g. `["a\nb"]` becomes `a`, a newline, then `b`. Default: `false`.
+    ///
+    /// The output is no longer JSON, or even one document per line, unless the strings
+    /// are known not to contain the [Config::record_separator]. Strings inside other
+    /// documents, and documents which are not strings, are unchanged.
+    pub fn raw_strings(mut self, raw_strings: bool) -> Self {
+        self.raw_strings = raw_strings;
+        self
+    }
+
+    /// Accept comments, as [Config::allow_comments], and pass the text of a comment directly
+    /// above a document to the [crate::Sinker], as [crate::Header::comment].
+    ///
+    /// The default output is then `{"comment":"note","value":...}` for such documents.
+    /// Comments inside an output document are dropped.
+    pub fn attach_comments(mut self, attach_comments: bool) -> Self {
+        self.attach_comments = attach_comments;
+        self
+    }
+
+    /// Accept `//` line comments and `/* */` block comments between tokens, and ignore them.
+    ///
+    /// See also [Config::attach_comments], which implies this.
+    pub fn allow_comments(mut self, allow_comments: bool) -> Self {
+        self.allow_comments = allow_comments;
+        self
+    }
+
+    /// Fail with [crate::UnnestError::InconsistentShape] if an output object does not have
+    /// exactly the same set of keys as the first output object.
+    ///
+    /// Output documents which are not objects are not checked.
+    pub fn enforce_uniform_keys(mut self, enforce_uniform_keys: bool) -> Self {
+        self.enforce_uniform_keys = enforce_uniform_keys;
+        self
+    }
+
+    /// Fail with [crate::UnnestError::DuplicateKey] if any object in the input, at any depth,
+    /// has the same key twice, e.g. `{"a": 1, "a": 2}`, which is otherwise passed through.
+    ///
+    /// Keys are compared after [Config::rename_keys]. Inside a flattened document, duplicates
+    /// are handled according to [Config::collisions] instead.
+    pub fn reject_duplicate_keys(mut self, reject_duplicate_keys: bool) -> Self {
+        self.reject_duplicate_keys = reject_duplicate_keys;
+        self
+    }
+
+    /// Instead of failing on invalid input, skip to the next line, and carry on, e.g. for
+    /// ndjson input with the occasional broken line. Default: `false`.
+    ///
+    /// If the problem is only noticed on a later line than the broken value started on,
+    /// such as when a line is unterminated, parsing resumes at the start of that line instead.
+    /// The document which was being produced is dropped, but any which were finished
+    /// before the problem are kept. The errors are in [crate::UnnestStats::errors], from
+    /// [crate::unnest_to_ndjson_stats]. Failures to read or write are still returned.
+    /// Each document is held in memory until it is finished.
+    pub fn continue_on_error(mut self, continue_on_error: bool) -> Self {
+        self.continue_on_error = continue_on_error;
+        self
+    }
+
+    /// Produce at most `max` documents from each top-level value in the input.
+    ///
+    /// Further documents are consumed without output, and reported to
+    /// [crate::Sinker::observe_capped]. The count restarts for every top-level value.
+    pub fn max_documents_per_top_level(mut self, max: u64) -> Self {
+        self.max_documents_per_top_level = Some(max);
+        self
+    }
+
+    /// Stop reading once `max` documents have been produced, in total.
+    ///
+    /// The input is left part way through, and the bytes which had been read, but not
+    /// consumed, are returned in [crate::UnnestStats::remaining] by
+    /// [crate::unnest_to_ndjson_stats]. Pass `&mut reader` to retain the rest of the input,
+    /// and continue with [crate::unnest_to_ndjson_after], given those bytes and the reader.
+    pub fn max_documents(mut self, max: u64) -> Self {
+        self.max_documents = Some(max);
+        self
+    }
+
+    /// Consume the first `skip` documents without producing them, e.g. to page through
+    /// the input with [Config::max_documents], which counts only the documents produced.
+    ///
+    /// Skipped documents are parsed, but not buffered or passed to the sink.
+    pub fn skip_documents(mut self, skip: u64) -> Self {
+        self.skip_documents = skip;
+        self
+    }
+
+    /// Compress the output with gzip, at `level`, from `0` (store only) to `9` (smallest).
+    ///
+    /// `6` is a balanced choice; `9` is slow, and can dominate the time taken on a large input.
+    /// Higher values are treated as `9`.
+    ///
+    /// The compressed bytes are written to the destination with [crate::MiniWrite::write_all];
+    /// its other [crate::Sinker] methods are not called.
+    #[cfg(feature = "gzip")]
+    pub fn output_compression(mut self, level: u32) -> Self {
+        self.output_compression = Some(level.min(9));
+        self
+    }
+
+    /// Whether numbers and literals need to be examined, rather than copied through.
+    pub(crate) fn examines_primitives(&self) -> bool {
+        self.validate_numbers
+            || self.normalize_numbers
+            || self.number_precision.is_some()
+            || self.validate_literals
+            || self.allow_js_numbers
+            || self.python_literals
+    }
+
+    /// Whether comments are skipped, rather than being an error.
+    pub(crate) fn accepts_comments(&self) -> bool {
+        self.allow_comments || self.attach_comments
+    }
+}
+
+/// Every setting, one per line, as `name: value`, for checking what was actually configured.
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "target: {}", self.target)?;
+        writeln!(f, "extra_targets: {:?}", self.extra_targets)?;
+        writeln!(f, "leaves: {}", self.leaves)?;
+        writeln!(f, "header_style: {:?}", self.header_style)?;
+        writeln!(
+            f,
+            "path_separator: {:?}",
+            String::from_utf8_lossy(&self.path_separator)
+        )?;
+        writeln!(
+            f,
+            "key_field: {:?}",
+            String::from_utf8_lossy(&self.key_field)
+        )?;
+        writeln!(f, "sequence_width: {}", self.sequence_width)?;
+        writeln!(
+            f,
+            "value_field: {:?}",
+            String::from_utf8_lossy(&self.value_field)
+        )?;
+        writeln!(
+            f,
+            "record_separator: {:?}",
+            String::from_utf8_lossy(&self.record_separator)
+        )?;
+        writeln!(f, "trailing_separator: {}", self.trailing_separator)?;
+        writeln!(f, "json_seq: {}", self.json_seq)?;
+        writeln!(f, "observe_paths: {}", self.observe_paths)?;
+        writeln!(f, "pretty: {:?}", self.pretty)?;
+        writeln!(f, "wrap_width: {:?}", self.wrap_width)?;
+        writeln!(
+            f,
+            "document_delimiter: {:?}",
+            self.document_delimiter
+                .as_deref()
+                .map(String::from_utf8_lossy)
+        )?;
+        writeln!(
+            f,
+            "require_document_delimiter: {}",
+            self.require_document_delimiter
+        )?;
+        writeln!(f, "single_document: {}", self.single_document)?;
+        writeln!(f, "path_prefix: {:?}", lossy(&self.path_prefix))?;
+        writeln!(f, "root_path: {:?}", lossy(&self.root_path))?;
+        writeln!(f, "include_keys: {:?}", lossy(&self.include_keys))?;
+        writeln!(f, "exclude_keys: {:?}", lossy(&self.exclude_keys))?;
+        let mut renames = self
+            .rename_keys
+            .iter()
+            .map(|(from, to)| (String::from_utf8_lossy(from), String::from_utf8_lossy(to)))
+            .collect::<Vec<_>>();
+        renames.sort();
+        writeln!(f, "rename_keys: {:?}", renames)?;
+        writeln!(f, "normalize_path_keys: {:?}", self.normalize_path_keys)?;
+        match &self.filter_equals {
+            Some((path, value)) => writeln!(
+                f,
+                "filter_equals: {:?} = {:?}",
+                lossy(path),
+                String::from_utf8_lossy(value)
+            )?,
+            None => writeln!(f, "filter_equals: None")?,
+        }
+        writeln!(f, "group_by: {:?}", self.group_by.as_deref().map(lossy))?;
+        writeln!(f, "max_groups: {}", self.max_groups)?;
+        match &self.fingerprint {
+            Some((exclude, algorithm)) => writeln!(
+                f,
+                "fingerprint: {:?} excluding {:?}",
+                algorithm,
+                lossy(exclude)
+            )?,
+            None => writeln!(f, "fingerprint: None")?,
+        }
+        writeln!(f, "skeleton: {}", self.skeleton)?;
+        writeln!(f, "verbatim: {}", self.verbatim)?;
+        writeln!(f, "classify_strings: {}", self.classify_strings)?;
+        writeln!(f, "raw_strings: {}", self.raw_strings)?;
+        writeln!(f, "attach_comments: {}", self.attach_comments)?;
+        writeln!(f, "allow_comments: {}", self.allow_comments)?;
+        writeln!(f, "enforce_uniform_keys: {}", self.enforce_uniform_keys)?;
+        writeln!(f, "reject_duplicate_keys: {}", self.reject_duplicate_keys)?;
+        writeln!(f, "continue_on_error: {}", self.continue_on_error)?;
+        writeln!(
+            f,
+            "max_documents_per_top_level: {:?}",
+            self.max_documents_per_top_level
+        )?;
+        writeln!(f, "max_documents: {:?}", self.max_documents)?;
+        writeln!(f, "skip_documents: {}", self.skip_documents)?;
+        writeln!(f, "max_depth: {:?}", self.max_depth)?;
+        writeln!(f, "buffer_size: {}", self.buffer_size)?;
+        writeln!(f, "heartbeat: {:?}", self.heartbeat)?;
+        writeln!(
+            f,
+            "heartbeat_record: {:?}",
+            String::from_utf8_lossy(&self.heartbeat_record)
+        )?;
+        writeln!(f, "progress_every: {:?}", self.progress_every)?;
+        writeln!(f, "scalar_root: {:?}", self.scalar_root)?;
+        writeln!(
+            f,
+            "flatten: {:?}",
+            self.flatten.as_deref().map(String::from_utf8_lossy)
+        )?;
+        writeln!(f, "collisions: {:?}", self.collisions)?;
+        writeln!(f, "validate_numbers: {}", self.validate_numbers)?;
+        writeln!(f, "normalize_numbers: {}", self.normalize_numbers)?;
+        writeln!(f, "number_precision: {:?}", self.number_precision)?;
+        writeln!(f, "validate_literals: {}", self.validate_literals)?;
+        writeln!(f, "python_literals: {}", self.python_literals)?;
+        writeln!(f, "validate_surrogates: {}", self.validate_surrogates)?;
+        writeln!(f, "validate_utf8: {}", self.validate_utf8)?;
+        writeln!(
+            f,
+            "reject_control_characters: {}",
+            self.reject_control_characters
+        )?;
+        writeln!(
+            f,
+            "escape_control_characters: {}",
+            self.escape_control_characters
+        )?;
+        writeln!(f, "allow_raw_newlines: {}", self.allow_raw_newlines)?;
+        writeln!(f, "spec_whitespace: {}", self.spec_whitespace)?;
+        writeln!(f, "reject_trailing_commas: {}", self.reject_trailing_commas)?;
+        writeln!(f, "allow_js_numbers: {}", self.allow_js_numbers)?;
+        writeln!(f, "allow_single_quotes: {}", self.allow_single_quotes)?;
+        writeln!(f, "allow_unquoted_keys: {}", self.allow_unquoted_keys)?;
+        #[cfg(feature = "gzip")]
+        writeln!(f, "output_compression: {:?}", self.output_compression)?;
+        Ok(())
+    }
+}
+
+fn lossy(list: &[Vec<u8>]) -> Vec<std::borrow::Cow<'_, str>> {
+    list.iter()
+        .map(|item| String::from_utf8_lossy(item))
+        .collect()
+}
+
+/// Compute a normalized key, for [NormalizeKey::With].
+pub type KeyNormalizer = Arc<dyn Fn(&[u8]) -> Vec<u8> + Send + Sync>;
+
+/// How to normalize the keys in paths, for [Config::normalize_path_keys].
+#[derive(Clone)]
+#[non_exhaustive]
+pub enum NormalizeKey {
+    /// Lowercase the ASCII letters of each key.
+    AsciiLowercase,
+
+    /// Call a function with each key, as it appears between the quotes in the input, so
+    /// escapes are not decoded. The result is escaped as necessary to make it a JSON string.
+    With(KeyNormalizer),
+}
+
+impl NormalizeKey {
+    /// Normalize a key, which is a JSON string, including the quotes.
+    pub(crate) fn apply(&self, key: &mut Vec<u8>) {
+        match self {
+            // escapes are never uppercase, except in hex, where case doesn't matter
+            NormalizeKey::AsciiLowercase => key.make_ascii_lowercase(),
+            NormalizeKey::With(f) => {
+                let normalized = f(&key[1..key.len() - 1]);
+                key.truncate(1);
+                crate::sink::write_json_string_content(key, &normalized).expect("writing to a Vec");
+                key.push(b'"');
+            }
+        }
+    }
+}
+
+impl fmt::Debug for NormalizeKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NormalizeKey::AsciiLowercase => write!(f, "AsciiLowercase"),
+            NormalizeKey::With(_) => write!(f, "With(..)"),
+        }
+    }
+}
+
+/// What to do with a scalar at the top level, for [Config::scalar_root].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum ScalarRoot {
+    /// Produce it as a document, as if it was the target. This is the default.
+    Emit,
+    /// Fail with [crate::UnnestError::ScalarRoot].
+    Error,
+    /// Consume it without producing anything.
+    Skip,
+}
+
+/// How to hash documents, for [Config::fingerprint].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Fingerprint {
+    /// 64-bit FNV-1a, written as 16 hex digits. This is fast, but not cryptographic.
+    Fnv1a,
+}
+
+/// What to do with duplicate keys, for [Config::collisions].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Collisions {
+    /// Fail with [crate::UnnestError::DuplicateKey]. This is the default.
+    Error,
+    /// Keep the value seen last, in the position of the first.
+    LastWins,
+}
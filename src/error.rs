@@ -0,0 +1,170 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Everything that can go wrong during [crate::unnest_to_ndjson_with].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum UnnestError {
+    /// Reading or writing failed.
+    Io(io::Error),
+
+    /// The input was not valid JSON.
+    Syntax {
+        /// Where the problem was noticed.
+        position: Position,
+        /// What was wrong, e.g. "expected ',' or '}'".
+        message: &'static str,
+    },
+
+    /// The input ended part way through a document.
+    ///
+    /// Input which is empty, or only whitespace, has no documents, so is not truncated.
+    Truncated {
+        /// The end of the input.
+        position: Position,
+    },
+
+    /// An output object did not have the same keys as the first, for
+    /// [crate::Config::enforce_uniform_keys].
+    ///
+    /// Keys are JSON strings, including the quotes, as they appeared in the input.
+    InconsistentShape {
+        /// Keys which were in the first object, but not this one.
+        missing: Vec<Vec<u8>>,
+        /// Keys which are in this object, but were not in the first one.
+        extra: Vec<Vec<u8>>,
+    },
+
+    /// A top-level value was a scalar, not a container, for [crate::ScalarRoot::Error].
+    ScalarRoot {
+        /// The start of the value.
+        position: Position,
+    },
+
+    /// A key appeared twice in an output object, for [crate::Collisions::Error], or in
+    /// any object, for [crate::Config::reject_duplicate_keys].
+    DuplicateKey {
+        /// The key, as a JSON string, including the quotes.
+        key: Vec<u8>,
+        /// The end of the second value, or, for [crate::Config::reject_duplicate_keys],
+        /// of the second key.
+        position: Position,
+    },
+
+    /// The input was nested more deeply than [crate::Config::max_depth].
+    TooDeep {
+        /// The configured limit.
+        max_depth: usize,
+    },
+}
+
+/// A location in the input, for [UnnestError::Syntax].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Position {
+    /// The 0-based byte offset.
+    pub offset: u64,
+    /// The 1-based line number.
+    pub line: u64,
+    /// The 1-based column, in bytes, not characters.
+    pub column: u64,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {} (byte {})",
+            self.line, self.column, self.offset
+        )
+    }
+}
+
+impl UnnestError {
+    /// Where in the input the problem was noticed, if it is known.
+    pub fn position(&self) -> Option<Position> {
+        match self {
+            UnnestError::Syntax { position, .. }
+            | UnnestError::Truncated { position }
+            | UnnestError::ScalarRoot { position }
+            | UnnestError::DuplicateKey { position, .. } => Some(*position),
+            UnnestError::Io(_)
+            | UnnestError::InconsistentShape { .. }
+            | UnnestError::TooDeep { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for UnnestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnnestError::Io(e) => e.fmt(f),
+            UnnestError::Syntax { position, message } => write!(f, "{} at {}", message, position),
+            UnnestError::Truncated { position } => {
+                write!(f, "unexpected end of input at {}", position)
+            }
+            UnnestError::InconsistentShape { missing, extra } => {
+                write!(f, "inconsistent shape: missing [")?;
+                write_keys(f, missing)?;
+                write!(f, "], extra [")?;
+                write_keys(f, extra)?;
+                write!(f, "]")
+            }
+            UnnestError::ScalarRoot { position } => {
+                write!(f, "expected an object or array at {}", position)
+            }
+            UnnestError::DuplicateKey { key, position } => write!(
+                f,
+                "duplicate key {} at {}",
+                String::from_utf8_lossy(key),
+                position
+            ),
+            UnnestError::TooDeep { max_depth } => {
+                write!(f, "nesting exceeds the maximum depth of {}", max_depth)
+            }
+        }
+    }
+}
+
+fn write_keys(f: &mut fmt::Formatter<'_>, keys: &[Vec<u8>]) -> fmt::Result {
+    for (pos, key) in keys.iter().enumerate() {
+        if 0 != pos {
+            write!(f, ",")?;
+        }
+        write!(f, "{}", String::from_utf8_lossy(key))?;
+    }
+    Ok(())
+}
+
+impl Error for UnnestError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            UnnestError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for UnnestError {
+    fn from(e: io::Error) -> Self {
+        UnnestError::Io(e)
+    }
+}
+
+impl From<io::ErrorKind> for UnnestError {
+    fn from(kind: io::ErrorKind) -> Self {
+        UnnestError::Io(kind.into())
+    }
+}
+
+impl From<UnnestError> for io::Error {
+    fn from(e: UnnestError) -> Self {
+        match e {
+            UnnestError::Io(e) => e,
+            truncated @ UnnestError::Truncated { .. } => {
+                io::Error::new(io::ErrorKind::UnexpectedEof, truncated)
+            }
+            other => io::Error::new(io::ErrorKind::InvalidData, other),
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! Picking apart documents which we have produced, and hence are compact, valid JSON.
+
+/// The raw value of the top-level field `name` of an object, if present.
+///
+/// `name` is compared with the key as it appears between the quotes, without decoding escapes.
+pub(crate) fn find_field<'d>(doc: &'d [u8], name: &[u8]) -> Option<&'d [u8]> {
+    let mut pos = 1;
+    if b'{' != *doc.first()? {
+        return None;
+    }
+    while b'"' == *doc.get(pos)? {
+        let key_end = skip_value(doc, pos)?;
+        let key = &doc[pos + 1..key_end - 1];
+        // the colon
+        let value_start = key_end + 1;
+        let value_end = skip_value(doc, value_start)?;
+        if key == name {
+            return Some(&doc[value_start..value_end]);
+        }
+        // the comma, or the closing brace
+        pos = value_end + 1;
+    }
+    None
+}
+
+/// The raw value at `path` in a document, following the fields of nested objects.
+///
+/// Segments are keys as they appear in a [crate::HeaderStyle::PathArray] key, including
+/// the quotes. Array indexes are not supported, and match nothing.
+pub(crate) fn find_path<'d>(doc: &'d [u8], path: &[Vec<u8>]) -> Option<&'d [u8]> {
+    path.iter()
+        .try_fold(doc, |doc, segment| match &segment[..] {
+            [b'"', name @ .., b'"'] => find_field(doc, name),
+            _ => None,
+        })
+}
+
+/// The position after the value starting at `pos`.
+pub(crate) fn skip_value(doc: &[u8], pos: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, &b) in doc.iter().enumerate().skip(pos) {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if b'\\' == b {
+                escaped = true;
+            } else if b'"' == b {
+                in_string = false;
+                if 0 == depth {
+                    return Some(i + 1);
+                }
+            }
+            continue;
+        }
+        match b {
+            b'"' => in_string = true,
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' if 0 == depth => return Some(i),
+            b'}' | b']' => {
+                depth -= 1;
+                if 0 == depth {
+                    return Some(i + 1);
+                }
+            }
+            b',' if 0 == depth => return Some(i),
+            _ => (),
+        }
+    }
+    if 0 == depth && !in_string {
+        Some(doc.len())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_field, find_path};
+
+    #[test]
+    fn fields() {
+        let doc = br#"{"a":[1,{"b":"}"}],"b":"x\"y","c":{"a":5},"d":-2.5}"#;
+        assert_eq!(Some(&br#"[1,{"b":"}"}]"#[..]), find_field(doc, b"a"));
+        assert_eq!(Some(&br#""x\"y""#[..]), find_field(doc, b"b"));
+        assert_eq!(Some(&br#"{"a":5}"#[..]), find_field(doc, b"c"));
+        assert_eq!(Some(&b"-2.5"[..]), find_field(doc, b"d"));
+        assert_eq!(None, find_field(doc, b"e"));
+        assert_eq!(None, find_field(b"[1]", b"a"));
+        assert_eq!(None, find_field(b"{}", b"a"));
+    }
+
+    #[test]
+    fn paths() {
+        let doc = br#"{"a":{"b":{"c":true}},"d":[5]}"#;
+        let path = |p: &[&str]| p.iter().map(|s| s.as_bytes().to_vec()).collect::<Vec<_>>();
+        assert_eq!(Some(&doc[..]), find_path(doc, &[]));
+        assert_eq!(
+            Some(&b"true"[..]),
+            find_path(doc, &path(&[r#""a""#, r#""b""#, r#""c""#]))
+        );
+        assert_eq!(Some(&b"[5]"[..]), find_path(doc, &path(&[r#""d""#])));
+        assert_eq!(None, find_path(doc, &path(&[r#""d""#, "0"])));
+        assert_eq!(None, find_path(doc, &path(&["a"])));
+    }
+}
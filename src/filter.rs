@@ -0,0 +1,78 @@
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::field::find_path;
+use crate::sink::{forward_hooks, Forward};
+use crate::{Header, MiniWrite, Sinker};
+
+/// Buffer each document, and only pass on those whose field has the wanted value,
+/// for [crate::Config::filter_equals].
+pub(crate) struct Filtered<'c, S> {
+    inner: S,
+    field: &'c [Vec<u8>],
+    wanted: &'c [u8],
+    /// The path of the current document, if [Sinker::observe_new_item_with] was called.
+    path: Option<Vec<Vec<u8>>>,
+    value: Vec<u8>,
+    /// How many documents were dropped, so they are not counted as produced.
+    dropped: Arc<AtomicU64>,
+}
+
+impl<'c, S: Sinker> Filtered<'c, S> {
+    pub(crate) fn new(
+        inner: S,
+        field: &'c [Vec<u8>],
+        wanted: &'c [u8],
+        dropped: Arc<AtomicU64>,
+    ) -> Self {
+        Filtered {
+            inner,
+            field,
+            wanted,
+            path: None,
+            value: Vec::with_capacity(1024),
+            dropped,
+        }
+    }
+}
+
+impl<S> MiniWrite for Filtered<'_, S> {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.value.extend_from_slice(buf);
+        Ok(())
+    }
+}
+
+impl<S: Sinker> Sinker for Filtered<'_, S> {
+    fn observe_new_item_with(&mut self, path: &[Vec<u8>], _: &Header) -> io::Result<()> {
+        self.path = Some(path.to_vec());
+        Ok(())
+    }
+
+    fn observe_end_with(&mut self, header: &Header) -> io::Result<()> {
+        let path = self.path.take();
+        if Some(self.wanted) == find_path(&self.value, self.field) {
+            // the header is complete at the end, so it is also good for the start
+            if let Some(path) = path {
+                self.inner.observe_new_item_with(&path, header)?;
+            }
+            self.inner.write_all(&self.value)?;
+            self.inner.observe_end_with(header)?;
+        } else {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        self.value.clear();
+        Ok(())
+    }
+
+    forward_hooks!();
+}
+
+impl<S: Sinker> Forward for Filtered<'_, S> {
+    type Inner = S;
+
+    fn inner(&mut self) -> &mut S {
+        &mut self.inner
+    }
+}
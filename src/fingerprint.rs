@@ -0,0 +1,154 @@
+use std::io;
+
+use crate::field::skip_value;
+use crate::sink::{forward_hooks, Forward};
+use crate::sinks::fnv1a;
+use crate::{Fingerprint, Header, MiniWrite, Sinker};
+
+/// Buffer each document, and add a hash of it to the header, for
+/// [crate::Config::fingerprint].
+pub(crate) struct Fingerprinted<'c, S> {
+    inner: S,
+    exclude: &'c [Vec<u8>],
+    algorithm: Fingerprint,
+    /// The path of the current document, if [Sinker::observe_new_item_with] was called.
+    path: Option<Vec<Vec<u8>>>,
+    value: Vec<u8>,
+    canonical: Vec<u8>,
+}
+
+impl<'c, S: Sinker> Fingerprinted<'c, S> {
+    pub(crate) fn new(inner: S, exclude: &'c [Vec<u8>], algorithm: Fingerprint) -> Self {
+        Fingerprinted {
+            inner,
+            exclude,
+            algorithm,
+            path: None,
+            value: Vec::with_capacity(1024),
+            canonical: Vec::with_capacity(1024),
+        }
+    }
+}
+
+impl<S> MiniWrite for Fingerprinted<'_, S> {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.value.extend_from_slice(buf);
+        Ok(())
+    }
+}
+
+impl<S: Sinker> Sinker for Fingerprinted<'_, S> {
+    fn observe_new_item_with(&mut self, path: &[Vec<u8>], _: &Header) -> io::Result<()> {
+        self.path = Some(path.to_vec());
+        Ok(())
+    }
+
+    fn observe_end_with(&mut self, header: &Header) -> io::Result<()> {
+        self.canonical.clear();
+        canonical(&self.value, self.exclude, &mut self.canonical)
+            .ok_or(io::ErrorKind::InvalidData)?;
+        let fingerprint = match self.algorithm {
+            Fingerprint::Fnv1a => format!("{:016x}", fnv1a(&self.canonical)),
+        };
+        let header = Header {
+            fingerprint: Some(fingerprint.as_bytes()),
+            ..header.clone()
+        };
+        // the fingerprint wraps the document in a header, even if it otherwise wouldn't be
+        let path = self.path.take().unwrap_or_default();
+        self.inner.observe_new_item_with(&path, &header)?;
+        self.inner.write_all(&self.value)?;
+        self.value.clear();
+        self.inner.observe_end_with(&header)
+    }
+
+    forward_hooks!();
+}
+
+impl<S: Sinker> Forward for Fingerprinted<'_, S> {
+    type Inner = S;
+
+    fn inner(&mut self) -> &mut S {
+        &mut self.inner
+    }
+}
+
+/// Write a compact JSON value with the keys of every object sorted, and without the
+/// `exclude`d keys of the outermost object. `None` if the value is not compact JSON.
+fn canonical(value: &[u8], exclude: &[Vec<u8>], out: &mut Vec<u8>) -> Option<()> {
+    let (open, close) = match value.first() {
+        Some(b'{') => (b'{', b'}'),
+        Some(b'[') => (b'[', b']'),
+        _ => {
+            out.extend_from_slice(value);
+            return Some(());
+        }
+    };
+    let mut members = Vec::new();
+    let mut pos = 1;
+    while close != *value.get(pos)? {
+        let start = pos;
+        if b'{' == open {
+            // the key, and the colon
+            pos = skip_value(value, pos)? + 1;
+        }
+        let value_start = pos;
+        pos = skip_value(value, pos)?;
+        if pos == value_start {
+            // a closer, or a comma, where a member should be
+            return None;
+        }
+        members.push((&value[start..value_start], &value[value_start..pos]));
+        match *value.get(pos)? {
+            b',' => pos += 1,
+            b if close == b => (),
+            _ => return None,
+        }
+    }
+    if b'{' == open {
+        members.sort_by_key(|&(key, _)| key);
+    }
+    out.push(open);
+    let mut first = true;
+    for (key, member) in members {
+        // the key, without its colon
+        if let Some(key) = key.strip_suffix(b":") {
+            if exclude.iter().any(|e| e[..] == *key) {
+                continue;
+            }
+        }
+        if !first {
+            out.push(b',');
+        }
+        first = false;
+        out.extend_from_slice(key);
+        canonical(member, &[], out)?;
+    }
+    out.push(close);
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::canonical;
+
+    #[test]
+    fn sorted() {
+        let mut out = Vec::new();
+        let exclude = [br#""t""#.to_vec()];
+        canonical(
+            br#"{"b":[{"y":1,"x":"}"}],"t":5,"a":{}}"#,
+            &exclude,
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(br#"{"a":{},"b":[{"x":"}","y":1}]}"#[..], out[..]);
+    }
+
+    #[test]
+    fn malformed() {
+        for value in [&b"[4,}5]"[..], b"[,]", b"[1}", b"{\"a\":1]"] {
+            assert_eq!(None, canonical(value, &[], &mut Vec::new()), "{:?}", value);
+        }
+    }
+}
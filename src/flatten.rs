@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::io::Read;
+
+use iowrap::Ignore;
+
+use crate::sink::write_json_string_content;
+use crate::source::Source;
+use crate::{drop_whitespace, handle_scalar, invalid, parse_key, Collisions, Frame, Loc};
+use crate::{MiniWrite, UnnestError};
+
+/// The leaves of one document, in the order they were first seen.
+struct Leaves {
+    entries: Vec<(Vec<u8>, Vec<u8>)>,
+    index: HashMap<Vec<u8>, usize>,
+}
+
+impl Leaves {
+    fn insert<R: Read>(
+        &mut self,
+        key: Vec<u8>,
+        value: Vec<u8>,
+        from: &Source<R>,
+        loc: &Loc,
+    ) -> Result<(), UnnestError> {
+        match self.index.get(&key) {
+            None => {
+                self.index.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+            }
+            Some(&pos) => match loc.config.collisions {
+                Collisions::LastWins => self.entries[pos].1 = value,
+                Collisions::Error => {
+                    return Err(UnnestError::DuplicateKey {
+                        key,
+                        position: from.last_position(),
+                    })
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+/// Consume one value, for [crate::Config::flatten_to_object], and write it as a flat object.
+pub(crate) fn flatten_value<R: Read>(
+    from: &mut Source<R>,
+    into: &mut impl MiniWrite,
+    loc: &mut Loc,
+) -> Result<(), UnnestError> {
+    // inside an output document, so no headers, heartbeats or comments are considered
+    let depth = loc.depth;
+    loc.depth = 1;
+    let leaves = collect_leaves(from, loc);
+    loc.depth = depth;
+
+    into.write_all(b"{")?;
+    for (pos, (key, value)) in leaves?.entries.iter().enumerate() {
+        if 0 != pos {
+            into.write_all(b",")?;
+        }
+        into.write_all(key)?;
+        into.write_all(b":")?;
+        into.write_all(value)?;
+    }
+    into.write_all(b"}")?;
+    Ok(())
+}
+
+fn collect_leaves<R: Read>(from: &mut Source<R>, loc: &mut Loc) -> Result<Leaves, UnnestError> {
+    let mut leaves = Leaves {
+        entries: Vec::new(),
+        index: HashMap::new(),
+    };
+    let mut stack = Vec::new();
+    // segments are the contents of JSON strings, without the quotes
+    let mut path = Vec::new();
+
+    begin_leaf(from, &mut stack, &path, &mut leaves, loc)?;
+    while !stack.is_empty() {
+        loc.deepest = loc.deepest.max(loc.stack.len() + stack.len());
+        drop_whitespace(from, &mut Ignore {}, loc)?;
+        let frame = stack.last_mut().expect("non-empty stack");
+        match *frame {
+            Frame::ObjectKey(keys) => {
+                let start = match from.next()? {
+                    b',' if !loc.config.reject_trailing_commas => continue,
+                    b'"' => b'"',
+                    b'\'' if loc.config.allow_single_quotes => b'\'',
+                    b if loc.config.allow_unquoted_keys && crate::is_identifier_start(b) => b,
+                    b'}' if 0 != keys && loc.config.reject_trailing_commas => {
+                        return Err(invalid(from, "expected a key"));
+                    }
+                    b'}' => {
+                        let _ = stack.pop();
+                        if 0 == keys {
+                            leaves.insert(key(&path, loc), b"{}".to_vec(), from, loc)?;
+                        }
+                        continue;
+                    }
+                    _ => return Err(invalid(from, "expected a key or '}'")),
+                };
+                *frame = Frame::ObjectNext(keys + 1);
+                let mut key = Vec::with_capacity(32);
+                parse_key(start, from, &mut key, &loc.config)?;
+                path.push(key[1..key.len() - 1].to_vec());
+                drop_whitespace(from, &mut Ignore {}, loc)?;
+                if b':' != from.next()? {
+                    return Err(invalid(from, "expected ':'"));
+                }
+                drop_whitespace(from, &mut Ignore {}, loc)?;
+                begin_leaf(from, &mut stack, &path, &mut leaves, loc)?;
+            }
+            Frame::ObjectNext(keys) => {
+                *frame = Frame::ObjectKey(keys);
+                let _ = path.pop();
+                match from.next()? {
+                    b'}' => {
+                        let _ = stack.pop();
+                    }
+                    b',' => (),
+                    _ => return Err(invalid(from, "expected ',' or '}'")),
+                }
+            }
+            Frame::ArrayValue(idx) => {
+                if b']' == from.peek()? {
+                    let _infallible = from.next()?;
+                    if 0 != idx && loc.config.reject_trailing_commas {
+                        return Err(invalid(from, "expected a value"));
+                    }
+                    let _ = stack.pop();
+                    if 0 == idx {
+                        leaves.insert(key(&path, loc), b"[]".to_vec(), from, loc)?;
+                    }
+                    continue;
+                }
+                *frame = Frame::ArrayNext(idx);
+                path.push(format!("{}", idx).into_bytes());
+                begin_leaf(from, &mut stack, &path, &mut leaves, loc)?;
+            }
+            Frame::ArrayNext(idx) => {
+                *frame = Frame::ArrayValue(idx + 1);
+                let _ = path.pop();
+                match from.next()? {
+                    b']' => {
+                        let _ = stack.pop();
+                    }
+                    b',' => (),
+                    _ => return Err(invalid(from, "expected ',' or ']'")),
+                }
+            }
+        }
+    }
+    Ok(leaves)
+}
+
+/// Start a value: containers are pushed onto the stack, scalars are recorded as leaves.
+fn begin_leaf<R: Read>(
+    from: &mut Source<R>,
+    stack: &mut Vec<Frame>,
+    path: &[Vec<u8>],
+    leaves: &mut Leaves,
+    loc: &Loc,
+) -> Result<(), UnnestError> {
+    let frame = match from.next()? {
+        b'{' => Frame::ObjectKey(0),
+        b'[' => Frame::ArrayValue(0),
+        c => {
+            let mut value = Vec::with_capacity(32);
+            handle_scalar(c, from, &mut value, loc)?;
+            return leaves.insert(key(path, loc), value, from, loc);
+        }
+    };
+    if let Some(max_depth) = loc.config.max_depth {
+        if loc.stack.len() + stack.len() >= max_depth {
+            return Err(UnnestError::TooDeep { max_depth });
+        }
+    }
+    stack.push(frame);
+    Ok(())
+}
+
+/// The key for a leaf: its path, joined with the separator, as a JSON string.
+fn key(path: &[Vec<u8>], loc: &Loc) -> Vec<u8> {
+    let separator = loc.config.flatten.as_deref().unwrap_or_default();
+    let mut key = b"\"".to_vec();
+    for (pos, segment) in path.iter().enumerate() {
+        if 0 != pos {
+            write_json_string_content(&mut key, separator).expect("writing to a Vec");
+        }
+        key.extend_from_slice(segment);
+    }
+    key.push(b'"');
+    key
+}
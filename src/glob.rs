@@ -0,0 +1,53 @@
+//! Matching keys against simple patterns, for [crate::Config::include_keys].
+
+/// Whether `text` matches `pattern`, where `*` matches any run of bytes, and `?` any one byte.
+///
+/// There is no escaping, or character classes.
+pub(crate) fn glob_matches(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // where to resume after the most recent `*`: the pattern after it, and the text
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                p += 1;
+                backtrack = Some((p, t));
+            }
+            Some(&c) if b'?' == c || text[t] == c => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((bp, bt)) => {
+                    p = bp;
+                    t = bt + 1;
+                    backtrack = Some((bp, bt + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| b'*' == c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::glob_matches;
+
+    #[test]
+    fn globs() {
+        assert!(glob_matches(b"*_id", b"user_id"));
+        assert!(glob_matches(b"*_id", b"_id"));
+        assert!(!glob_matches(b"*_id", b"id"));
+        assert!(!glob_matches(b"*_id", b"user_idx"));
+        assert!(glob_matches(b"pass*", b"password"));
+        assert!(glob_matches(b"a?c", b"abc"));
+        assert!(!glob_matches(b"a?c", b"ac"));
+        assert!(glob_matches(b"*a*b*", b"xxaxxbxx"));
+        assert!(!glob_matches(b"*a*b*", b"xxbxxaxx"));
+        assert!(glob_matches(b"*", b""));
+        assert!(glob_matches(b"", b""));
+        assert!(!glob_matches(b"", b"a"));
+        assert!(glob_matches(b"name", b"name"));
+    }
+}
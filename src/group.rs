@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+use std::io;
+
+use crate::field::find_path;
+use crate::sink::{forward_hooks, Forward};
+use crate::{Config, Header, MiniWrite, Sinker};
+
+/// Count each document by the value of a field, instead of producing it, for
+/// [Config::group_by]. The counts are produced when it is [Sinker::flush]ed.
+pub(crate) struct Grouped<'c, S> {
+    inner: S,
+    config: &'c Config,
+    field: &'c [Vec<u8>],
+    counts: HashMap<Vec<u8>, u64>,
+    value: Vec<u8>,
+}
+
+impl<'c, S: Sinker> Grouped<'c, S> {
+    pub(crate) fn new(inner: S, field: &'c [Vec<u8>], config: &'c Config) -> Self {
+        Grouped {
+            inner,
+            config,
+            field,
+            counts: HashMap::new(),
+            value: Vec::with_capacity(1024),
+        }
+    }
+}
+
+/// Whether a value, in compact JSON, is a scalar, not an object or an array.
+fn is_scalar(value: &[u8]) -> bool {
+    !matches!(value.first(), Some(b'{' | b'['))
+}
+
+impl<S> MiniWrite for Grouped<'_, S> {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.value.extend_from_slice(buf);
+        Ok(())
+    }
+}
+
+impl<S: Sinker> Sinker for Grouped<'_, S> {
+    fn observe_new_item_with(&mut self, _: &[Vec<u8>], _: &Header) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn observe_end_with(&mut self, _: &Header) -> io::Result<()> {
+        let key = match find_path(&self.value, self.field) {
+            Some(key) if is_scalar(key) => key,
+            _ => {
+                self.value.clear();
+                return Ok(());
+            }
+        };
+        if let Some(count) = self.counts.get_mut(key) {
+            *count += 1;
+        } else if self.counts.len() < self.config.max_groups {
+            self.counts.insert(key.to_vec(), 1);
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "more than {} distinct values to group by",
+                    self.config.max_groups
+                ),
+            ));
+        }
+        self.value.clear();
+        Ok(())
+    }
+
+    forward_hooks!();
+}
+
+impl<S: Sinker> Forward for Grouped<'_, S> {
+    type Inner = S;
+
+    fn inner(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Produce a summary line for each distinct value, ordered by the value.
+    fn flush(&mut self) -> io::Result<()> {
+        let mut counts = std::mem::take(&mut self.counts)
+            .into_iter()
+            .collect::<Vec<_>>();
+        counts.sort_unstable();
+        for (pos, (value, count)) in counts.into_iter().enumerate() {
+            if 0 != pos && !self.config.trailing_separator {
+                self.inner.write_all(&self.config.record_separator)?;
+            }
+            if self.config.json_seq {
+                self.inner.write_all(b"\x1e")?;
+            }
+            self.inner.write_all(br#"{"value":"#)?;
+            self.inner.write_all(&value)?;
+            self.inner
+                .write_all(format!(r#","count":{}}}"#, count).as_bytes())?;
+            if self.config.trailing_separator {
+                self.inner.write_all(&self.config.record_separator)?;
+            }
+        }
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,95 @@
+use std::io::{self, Read, Write};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::measured::Measured;
+use crate::sink::{write_end, write_start};
+use crate::source::Source;
+use crate::{run, Config, Header, MiniWrite, Sinker, UnnestError, UnnestStats};
+
+/// Present a [MiniWrite] as a `Write`, for the encoder.
+struct Writer<W>(W);
+
+impl<W: MiniWrite> Write for Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Produce the default formatting through a gzip encoder, for [Config::output_compression].
+pub(crate) fn run_compressed<R: Read>(
+    from: Source<R>,
+    to: impl Sinker,
+    config: &Config,
+    level: u32,
+) -> Result<UnnestStats, UnnestError> {
+    let mut encoder = GzEncoder::new(Writer(to), Compression::new(level));
+    let stats = run(from, Measured::new(&mut encoder), config)?;
+    let Writer(mut to) = encoder.finish()?;
+    to.flush()?;
+    Ok(stats)
+}
+
+/// Compress each document separately, as its own gzip stream, for protocols which frame
+/// records individually. This is unlike [Config::output_compression], which compresses
+/// the whole output as one stream.
+///
+/// Each document, with its header and newline, is buffered, compressed, and written
+/// after its length, in bytes, as a big-endian `u32`. A heartbeat is framed as a record.
+pub struct GzipRecords<W> {
+    inner: W,
+    level: Compression,
+    record: Vec<u8>,
+}
+
+impl<W: MiniWrite> GzipRecords<W> {
+    /// Compress each record at `level`, from `0` (fastest) to `9` (smallest).
+    pub fn new(inner: W, level: u32) -> Self {
+        GzipRecords {
+            inner,
+            level: Compression::new(level),
+            record: Vec::with_capacity(1024),
+        }
+    }
+
+    fn frame(&mut self) -> io::Result<()> {
+        let mut encoder = GzEncoder::new(Vec::with_capacity(self.record.len() / 2), self.level);
+        Write::write_all(&mut encoder, &self.record)?;
+        let compressed = encoder.finish()?;
+        self.record.clear();
+        let len = u32::try_from(compressed.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "record too large"))?;
+        self.inner.write_all(&len.to_be_bytes())?;
+        self.inner.write_all(&compressed)
+    }
+}
+
+impl<W> MiniWrite for GzipRecords<W> {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.record.extend_from_slice(buf);
+        Ok(())
+    }
+}
+
+impl<W: MiniWrite> Sinker for GzipRecords<W> {
+    fn observe_new_item_with(&mut self, path: &[Vec<u8>], header: &Header) -> io::Result<()> {
+        write_start(self, path, header)
+    }
+
+    fn observe_end_with(&mut self, header: &Header) -> io::Result<()> {
+        write_end(self, header)?;
+        self.frame()
+    }
+
+    fn observe_heartbeat(&mut self, record: &[u8]) -> io::Result<()> {
+        self.record.extend_from_slice(record);
+        self.record.push(b'\n');
+        self.frame()
+    }
+}
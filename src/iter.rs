@@ -0,0 +1,166 @@
+use std::io::{self, Read};
+
+use crate::source::Source;
+use crate::{step, Config, Header, HeaderStyle, Loc, MiniWrite, Sinker};
+
+/// A document produced by [unnest_iter].
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct Document {
+    /// The path to the document, as JSON values, e.g. `"a"` or `5`.
+    ///
+    /// This is empty unless the header style computes the path.
+    pub path: Vec<Vec<u8>>,
+
+    /// The document itself, as compact JSON, without any header.
+    pub value: Vec<u8>,
+}
+
+/// The documents in an input, one at a time. See [unnest_iter].
+pub struct Documents<R: Read> {
+    from: Source<R>,
+    /// `None` once finished.
+    loc: Option<Loc>,
+    sink: Collect,
+    /// A problem with the [Config], reported by the first call to `next()`.
+    invalid: Option<io::Error>,
+}
+
+/// Like [crate::unnest_to_ndjson], but pull the documents out, instead of providing a [Sinker].
+///
+/// Only one document is held in memory at a time. After an error, the iterator is finished.
+///
+/// ```
+/// use unnest_ndjson::{unnest_iter, HeaderStyle};
+///
+/// let mut docs = unnest_iter(&br#"{"a": [1], "b": 2}"#[..], 1, HeaderStyle::PathArray);
+/// let first = docs.next().unwrap()?;
+/// assert_eq!(vec![b"\"a\"".to_vec()], first.path);
+/// assert_eq!(b"[1]".to_vec(), first.value);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn unnest_iter<R: Read>(from: R, target: usize, header_style: HeaderStyle) -> Documents<R> {
+    documents(from, &Config::new(target).header_style(header_style))
+}
+
+/// [unnest_iter], with the settings from a [Config].
+///
+/// The header style only affects whether [Document::path] is computed. Options which
+/// change how the documents are produced, rather than found, are an error from the first
+/// call to `next()`: [Config::continue_on_error], [Config::extra_targets],
+/// [Config::filter_equals], [Config::fingerprint], [Config::group_by], [Config::pretty]
+/// and [Config::wrap_width].
+pub fn documents<R: Read>(from: R, config: &Config) -> Documents<R> {
+    documents_after(&[], from, config)
+}
+
+/// [documents], for input which starts with `prefix`, and continues with `from`, e.g.
+/// when some has already been read to detect what it is.
+///
+/// ```
+/// use unnest_ndjson::{documents_after, Config};
+///
+/// let mut docs = documents_after(b"[1, 2", &b"2, 3]"[..], &Config::new(1));
+/// assert_eq!(b"22".to_vec(), docs.nth(1).unwrap()?.value);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn documents_after<R: Read>(prefix: &[u8], from: R, config: &Config) -> Documents<R> {
+    let loc = check_supported(config, "documents()")
+        .and_then(|()| Ok(Loc::new(config.clone())?))
+        .map(|mut loc| {
+            loc.pause = true;
+            loc
+        });
+    let (loc, invalid) = match loc {
+        Ok(loc) => (Some(loc), None),
+        Err(e) => (None, Some(e)),
+    };
+    Documents {
+        from: Source::with_initial_buffer(prefix, from, config.buffer_size),
+        loc,
+        sink: Collect::default(),
+        invalid,
+    }
+}
+
+/// Fail if an option is set which only [crate::unnest_to_ndjson_with] can apply, as it
+/// wraps the [Sinker], so cannot be applied one document at a time by `by`.
+pub(crate) fn check_supported(config: &Config, by: &str) -> io::Result<()> {
+    let options = [
+        (config.continue_on_error, "continue_on_error"),
+        (!config.extra_targets.is_empty(), "extra_targets"),
+        (config.filter_equals.is_some(), "filter_equals"),
+        (config.fingerprint.is_some(), "fingerprint"),
+        (config.group_by.is_some(), "group_by"),
+        (config.pretty.is_some(), "pretty"),
+        (config.wrap_width.is_some(), "wrap_width"),
+    ];
+    match options.into_iter().find(|&(set, _)| set) {
+        Some((_, option)) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Config::{} is not supported by {}", option, by),
+        )),
+        None => Ok(()),
+    }
+}
+
+impl<R: Read> Iterator for Documents<R> {
+    type Item = io::Result<Document>;
+
+    fn next(&mut self) -> Option<io::Result<Document>> {
+        if let Some(e) = self.invalid.take() {
+            return Some(Err(e));
+        }
+        let loc = self.loc.as_mut()?;
+        loc.paused = false;
+        loop {
+            if let Some(doc) = self.sink.ready.take() {
+                return Some(Ok(doc));
+            }
+            match step(&mut self.from, &mut self.sink, loc) {
+                Ok(true) => (),
+                Ok(false) => break,
+                Err(e) => {
+                    self.loc = None;
+                    return Some(Err(e.into()));
+                }
+            }
+            if loc.finished() && self.sink.ready.is_none() {
+                break;
+            }
+        }
+        self.loc = None;
+        None
+    }
+}
+
+/// Buffer each document, for [Documents].
+#[derive(Default)]
+pub(crate) struct Collect {
+    path: Vec<Vec<u8>>,
+    value: Vec<u8>,
+    pub(crate) ready: Option<Document>,
+}
+
+impl MiniWrite for Collect {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.value.extend_from_slice(buf);
+        Ok(())
+    }
+}
+
+impl Sinker for Collect {
+    fn observe_new_item_with(&mut self, path: &[Vec<u8>], _: &Header) -> io::Result<()> {
+        self.path.clear();
+        self.path.extend_from_slice(path);
+        Ok(())
+    }
+
+    fn observe_end_with(&mut self, _: &Header) -> io::Result<()> {
+        self.ready = Some(Document {
+            path: self.path.clone(),
+            value: std::mem::take(&mut self.value),
+        });
+        Ok(())
+    }
+}
@@ -2,22 +2,73 @@
 //!
 //! Call [unnest_to_ndjson] on your stream, and receive a much nicer stream, or some
 //! callbacks.
+//!
+//! ## Streaming
+//!
+//! By default, the bytes of each output document are passed to the [Sinker] as they are
+//! read, and no document is ever held in memory, regardless of its size. Options on [Config]
+//! are all off by default. An option which needs to see a whole document before it can be
+//! written will say so in its documentation, and only costs anything when it is enabled.
+//! `cargo bench --bench streaming` compares the two approaches.
 
+use std::collections::{BTreeSet, HashSet};
 use std::convert::TryFrom;
 use std::io;
-use std::io::Read;
+use std::io::{Read, Write as _};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
 
 use iowrap::Ignore;
-use memchr::memchr;
+use memchr::{memchr2, memchr3};
 
+mod appending;
+#[cfg(feature = "async")]
+mod async_io;
+mod classify;
+mod config;
+mod error;
+mod field;
+mod filter;
+mod fingerprint;
+mod flatten;
+mod glob;
+mod group;
+#[cfg(feature = "gzip")]
+mod gzip;
+mod iter;
+mod measured;
+mod number;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod pretty;
+mod recover;
+#[cfg(any(feature = "yaml", feature = "toml"))]
+mod reencode;
+mod separated;
 mod sink;
+pub mod sinks;
 mod source;
+mod stats;
+mod unescape;
+mod wrap;
 
-pub use crate::sink::{MiniWrite, Sinker};
+pub use crate::appending::{Appending, Progress};
+#[cfg(feature = "async")]
+pub use crate::async_io::unnest_to_ndjson_async;
+pub use crate::classify::StringClass;
+pub use crate::config::{Collisions, Config, Fingerprint, KeyNormalizer, NormalizeKey, ScalarRoot};
+pub use crate::error::{Position, UnnestError};
+pub use crate::iter::{documents, documents_after, unnest_iter, Document, Documents};
+pub use crate::number::NumberRounding;
+#[cfg(any(feature = "yaml", feature = "toml"))]
+pub use crate::reencode::{Format, Reencode};
+pub use crate::sink::{Header, ItemKind, ItemMeta, MiniWrite, Sinker};
+pub use crate::stats::UnnestStats;
 use source::Source;
 
 /// Control what information is retained for individual result documents
-#[derive(Copy, Clone, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 #[non_exhaustive]
 pub enum HeaderStyle {
     /// No information is retained.
@@ -29,21 +80,204 @@ pub enum HeaderStyle {
     /// `{"key":["a"],"value":{"H":6}}` and
     /// `{"key":["b"],"value":{"H":6}}`
     PathArray,
+    /// The 1-based line of the input on which the child document started is retained.
+    ///
+    /// `{"a": 5,\n "b": 6}` would become,
+    /// with the default formatter and a target of `1`,
+    /// `{"line":1,"value":5}` and
+    /// `{"line":2,"value":6}`
+    WithLine,
+    /// The path to the child document is retained, as a single string.
+    ///
+    /// The segments are joined with [Config::path_separator], which defaults to `.`.
+    /// `{"a": {"H": 6}, "b": {"H": [7]}}` would become,
+    /// with the default formatter and a target of `2`,
+    /// `{"path":"a.H","value":6}` and
+    /// `{"path":"b.H","value":[7]}`
+    ///
+    /// A separator inside a key is escaped with a backslash, as is a backslash, so
+    /// the key `c.d` appears as `c\.d` (that is, `"c\\.d"` in the JSON).
+    /// `\u` escapes in keys are not decoded, and are not considered.
+    Dotted,
+    /// The byte offsets of the start of the child document, and just after its end, in
+    /// the input, including any whitespace inside it, are retained.
+    ///
+    /// `{"a": 5, "b": [6]}` would become,
+    /// with the default formatter and a target of `1`,
+    /// `{"start":6,"value":5,"end":7}` and
+    /// `{"start":14,"value":[6],"end":17}`
+    ByteRange,
+    /// The 0-based index of the child document in the output is retained.
+    ///
+    /// `[5, 6]` would become,
+    /// with the default formatter and a target of `1`,
+    /// `{"seq":0,"value":5}` and
+    /// `{"seq":1,"value":6}`
+    ///
+    /// With [Config::sequence_width], the index is a zero-padded string, e.g. `"000001"`.
+    Sequence,
+    /// The last segment of the path to the child document, its key or index, is retained,
+    /// as a string.
+    ///
+    /// `{"a": {"H": 6}, "b": [7]}` would become,
+    /// with the default formatter and a target of `2`,
+    /// `{"key":"H","value":6}` and
+    /// `{"key":"0","value":7}`
+    ///
+    /// A document with an empty path, such as with a target of `0`, has a `null` key.
+    LeafKey,
 }
 
+#[derive(Clone)]
 struct Loc {
     depth: isize,
+    /// The depths of the [Config::extra_targets] below the target, in order, deepest last.
+    extra_targets: Vec<isize>,
+    /// The documents we are inside, while producing one of the [Config::extra_targets].
+    enclosing: Vec<Enclosing>,
     path: Vec<Vec<u8>>,
-    header_style: HeaderStyle,
+    /// Segments which have been removed from the `path`, to be reused.
+    spare: Vec<Vec<u8>>,
+    config: Config,
+    /// The comment seen since the last document, for [Config::attach_comments].
+    pending_comment: Option<Vec<u8>>,
+    /// The comment attached to the current document.
+    comment: Option<Vec<u8>>,
+    /// The line on which the current document started, for [HeaderStyle::WithLine].
+    line: Option<u64>,
+    /// The index of the current document, for [HeaderStyle::Sequence].
+    sequence: Option<u64>,
+    /// The offsets of the current document, for [HeaderStyle::ByteRange].
+    start: Option<u64>,
+    end: Option<u64>,
+    /// The keys of the first output object, for [Config::enforce_uniform_keys].
+    shape: Option<BTreeSet<Vec<u8>>>,
+    /// The keys of the current output object, for [Config::enforce_uniform_keys].
+    keys: BTreeSet<Vec<u8>>,
+    /// Documents started in the current top-level value.
+    documents_in_top_level: u64,
+    /// Whether a [Config::document_delimiter] is due before the next top-level value.
+    needs_delimiter: bool,
+    /// Whether any top-level value has been started, for [Config::single_document].
+    started: bool,
+    /// The line on which the current top-level value started, if it has, for
+    /// [Config::continue_on_error].
+    value_line: Option<u64>,
+    /// The containers we are inside.
+    stack: Vec<Frame>,
+    /// The keys of each object we are inside, for [Config::reject_duplicate_keys].
+    seen_keys: Vec<HashSet<Vec<u8>>>,
+    /// What the current document is, for [Header::item].
+    kind: ItemKind,
+    /// Documents finished so far, including any dropped by [Config::filter_equals].
+    documents: u64,
+    /// Documents dropped by [Config::filter_equals], as counted by [filter::Filtered].
+    filtered_out: Arc<AtomicU64>,
+    /// Documents consumed without output so far, for [Config::skip_documents].
+    skipped: u64,
+    /// The most containers we have been inside at once, for [UnnestStats::max_depth_seen].
+    deepest: usize,
+    /// Whether to stop after each document, for [iter::Documents].
+    pause: bool,
+    /// Whether we have stopped after a document, and not been resumed.
+    paused: bool,
+    /// When we last produced anything, for [Config::heartbeat].
+    last_output: Instant,
+    /// The offset we last reported, for [Config::progress_every].
+    last_progress: u64,
+}
+
+/// What [Loc] knows about a document, while a document inside it is being produced,
+/// for [Config::extra_targets].
+#[derive(Clone)]
+struct Enclosing {
+    /// The depth of the document inside it.
+    depth: isize,
+    comment: Option<Vec<u8>>,
+    line: Option<u64>,
+    start: Option<u64>,
+    kind: ItemKind,
+}
+
+/// How far above the target the top level is, so the target is at `0`.
+fn start_depth(config: &Config) -> Option<isize> {
+    if config.leaves {
+        // never reached, so every scalar is shallower than the target
+        return Some(isize::MIN);
+    }
+    isize::try_from(config.target).ok().map(|target| -target)
 }
 
 impl Loc {
+    fn new(mut config: Config) -> Result<Loc, UnnestError> {
+        if !config.root_path.is_empty() {
+            // everything below is relative to the root, so look through it
+            config.target += config.root_path.len();
+            let relative = std::mem::take(&mut config.path_prefix);
+            config.path_prefix = config.root_path.iter().cloned().chain(relative).collect();
+        }
+        let depth = start_depth(&config).ok_or(io::ErrorKind::InvalidData)?;
+        let mut extra_targets: Vec<isize> = if config.leaves || config.flatten.is_some() {
+            Vec::new()
+        } else {
+            let target = config.target - config.root_path.len();
+            (config.extra_targets.iter())
+                .filter_map(|&extra| extra.checked_sub(target))
+                .filter(|&below| below > 0)
+                .filter_map(|below| isize::try_from(below).ok())
+                .collect()
+        };
+        extra_targets.sort_unstable();
+        extra_targets.dedup();
+        Ok(Loc {
+            depth,
+            extra_targets,
+            enclosing: Vec::new(),
+            path: Vec::with_capacity(config.target),
+            spare: Vec::new(),
+            pending_comment: None,
+            comment: None,
+            line: None,
+            sequence: None,
+            start: None,
+            end: None,
+            shape: None,
+            keys: BTreeSet::new(),
+            documents_in_top_level: 0,
+            needs_delimiter: false,
+            started: false,
+            value_line: None,
+            stack: Vec::with_capacity(config.target + 16),
+            seen_keys: Vec::new(),
+            kind: ItemKind::Scalar,
+            documents: 0,
+            filtered_out: Arc::default(),
+            skipped: 0,
+            deepest: 0,
+            pause: false,
+            paused: false,
+            last_output: Instant::now(),
+            last_progress: 0,
+            config,
+        })
+    }
+
     fn at_target(&self) -> bool {
         0 == self.depth
     }
 
+    /// Whether we are at one of the [Config::extra_targets], inside a document.
+    fn at_extra_target(&self) -> bool {
+        self.depth > 0 && self.extra_targets.contains(&self.depth)
+    }
+
+    /// The depth of the deepest target, below which nothing is produced.
+    fn deepest_target(&self) -> isize {
+        self.extra_targets.last().copied().unwrap_or(0)
+    }
+
     fn collecting_keys(&self) -> bool {
-        self.depth <= 0
+        self.depth <= self.deepest_target()
     }
 
     fn producing_regular_output(&self) -> bool {
@@ -54,14 +288,287 @@ impl Loc {
         self.depth < 0
     }
 
-    fn write_suffix(&self, into: &mut impl Sinker) -> io::Result<()> {
-        into.observe_end(self.header_style)
+    /// The path to where we are, from the [Config::root_path], for the sink.
+    fn relative_path(&self) -> &[Vec<u8>] {
+        let root = self.config.root_path.len().min(self.path.len());
+        &self.path[root..]
+    }
+
+    fn header(&self) -> Header<'_> {
+        Header {
+            style: self.config.header_style,
+            comment: self.comment.as_deref(),
+            line: self.line,
+            item: ItemMeta {
+                depth: self.stack.len(),
+                kind: self.kind,
+            },
+            index: self.produced(),
+            sequence: self.sequence,
+            sequence_width: self.config.sequence_width,
+            start: self.start,
+            end: self.end,
+            target: self.document_target(),
+            fingerprint: None,
+            separator: &self.config.path_separator,
+            key_field: &self.config.key_field,
+            value_field: &self.config.value_field,
+            record_separator: &self.config.record_separator,
+            json_seq: self.config.json_seq,
+        }
+    }
+
+    /// Which target produced the current document, for [Header::target].
+    fn document_target(&self) -> Option<usize> {
+        if self.extra_targets.is_empty() {
+            return None;
+        }
+        let target = self.config.target - self.config.root_path.len();
+        // scalars above the target are produced by it
+        Some(target + self.depth.max(0).unsigned_abs())
+    }
+
+    /// Start producing one of the [Config::extra_targets], inside the current document,
+    /// keeping what we know about the current document until it is finished.
+    fn enter_extra_target(&mut self) {
+        self.enclosing.push(Enclosing {
+            depth: self.depth,
+            comment: self.comment.take(),
+            line: self.line,
+            start: self.start,
+            kind: self.kind,
+        });
+    }
+
+    /// Whether the value which has just finished is one of the [Config::extra_targets].
+    fn at_enclosed_end(&self) -> bool {
+        matches!(self.enclosing.last(), Some(enclosing) if enclosing.depth == self.depth)
+    }
+
+    /// Return to the document which the finished one was inside.
+    fn leave_extra_target(&mut self) {
+        let enclosing = self.enclosing.pop().expect("an enclosing document");
+        self.comment = enclosing.comment;
+        self.line = enclosing.line;
+        self.start = enclosing.start;
+        self.kind = enclosing.kind;
+    }
+
+    /// Start a document, which started at the offset `start`.
+    fn write_prefix<R: Read>(
+        &mut self,
+        start: u64,
+        from: &mut Source<R>,
+        into: &mut impl Sinker,
+    ) -> io::Result<()> {
+        self.comment = self.pending_comment.take();
+        match self.config.header_style {
+            HeaderStyle::WithLine => self.line = Some(from.line()),
+            HeaderStyle::ByteRange => self.start = Some(start),
+            HeaderStyle::Sequence => self.sequence = Some(self.produced()),
+            _ => (),
+        }
+        into.observe_new_item_with(self.relative_path(), &self.header())
+    }
+
+    fn write_suffix<R: Read>(
+        &mut self,
+        from: &Source<R>,
+        into: &mut impl Sinker,
+    ) -> io::Result<()> {
+        if HeaderStyle::ByteRange == self.config.header_style {
+            self.end = Some(from.offset());
+        }
+        if self.sequence.is_some() {
+            // any documents inside this one, for [Config::extra_targets], were finished first
+            self.sequence = Some(self.produced());
+        }
+        into.observe_end_with(&self.header())?;
+        if let Some(every) = self.config.progress_every {
+            let offset = from.offset();
+            if offset - self.last_progress >= every {
+                into.observe_progress(offset)?;
+                self.last_progress = offset;
+            }
+        }
+        self.comment = None;
+        self.documents += 1;
+        self.paused = self.pause;
+        if self.config.heartbeat.is_some() {
+            self.last_output = Instant::now();
+        }
+        Ok(())
+    }
+
+    /// Produce a [Config::heartbeat] record, if one is due, and we are between documents.
+    fn heartbeat(&mut self, into: &mut impl Sinker) -> io::Result<()> {
+        let interval = match self.config.heartbeat {
+            Some(interval) => interval,
+            None => return Ok(()),
+        };
+        if self.producing_regular_output() || self.last_output.elapsed() < interval {
+            return Ok(());
+        }
+        into.observe_heartbeat(&self.config.heartbeat_record)?;
+        self.last_output = Instant::now();
+        Ok(())
+    }
+
+    /// Forget everything about the value we were in, to start again at the top level,
+    /// for [Config::continue_on_error].
+    fn reset(&mut self) {
+        self.depth = start_depth(&self.config).expect("checked in new");
+        self.stack.clear();
+        self.enclosing.clear();
+        self.seen_keys.clear();
+        self.spare.append(&mut self.path);
+        self.keys.clear();
+        self.pending_comment = None;
+        self.comment = None;
+        self.needs_delimiter = false;
+    }
+
+    /// Documents produced so far, for [Config::max_documents].
+    fn produced(&self) -> u64 {
+        self.documents - self.filtered_out.load(Ordering::Relaxed)
+    }
+
+    /// Whether [Config::max_documents] have been produced, and we should stop.
+    fn finished(&self) -> bool {
+        matches!(self.config.max_documents, Some(max) if self.produced() >= max)
+    }
+
+    /// Whether to leave the parse loop, and return to the caller of [step].
+    fn stopping(&self) -> bool {
+        self.paused || self.finished()
+    }
+
+    /// Enter a container.
+    fn push(&mut self, frame: Frame) -> Result<(), UnnestError> {
+        if let Some(max_depth) = self.config.max_depth {
+            if self.stack.len() >= max_depth {
+                return Err(UnnestError::TooDeep { max_depth });
+            }
+        }
+        self.depth += 1;
+        self.stack.push(frame);
+        self.deepest = self.deepest.max(self.stack.len());
+        Ok(())
+    }
+
+    /// An empty buffer for a new segment of the `path`, reusing an old one, if possible.
+    fn spare_segment(&mut self) -> Vec<u8> {
+        match self.spare.pop() {
+            Some(mut segment) => {
+                segment.clear();
+                segment
+            }
+            None => Vec::with_capacity(32),
+        }
+    }
+
+    /// Remove the last segment of the `path`, keeping it to be reused.
+    fn pop_segment(&mut self) {
+        let segment = self.path.pop().expect("a segment for the value");
+        self.spare.push(segment);
+    }
+
+    /// Whether the current path could lead to a document, for [Config::path_prefix].
+    fn on_prefix(&self) -> bool {
+        let prefix = &self.config.path_prefix;
+        self.path.iter().zip(prefix).all(|(seg, want)| seg == want)
+    }
+
+    /// Whether a document at the current path should be produced, for [Config::path_prefix],
+    /// [Config::include_keys] and [Config::exclude_keys].
+    fn wanted(&self) -> bool {
+        if self.path.len() < self.config.path_prefix.len() || !self.on_prefix() {
+            return false;
+        }
+        let include = &self.config.include_keys;
+        let exclude = &self.config.exclude_keys;
+        if include.is_empty() && exclude.is_empty() {
+            return true;
+        }
+        let key = match self.path.last() {
+            Some(key) if key.starts_with(b"\"") => &key[1..key.len() - 1],
+            Some(index) => &index[..],
+            None => &[],
+        };
+        (include.is_empty() || include.iter().any(|p| glob::glob_matches(p, key)))
+            && !exclude.iter().any(|p| glob::glob_matches(p, key))
+    }
+
+    /// Count a new document, and decide whether it should be produced.
+    fn admit_document(&mut self, into: &mut impl Sinker) -> io::Result<bool> {
+        self.documents_in_top_level += 1;
+        match self.config.max_documents_per_top_level {
+            Some(max) if self.documents_in_top_level > max => {
+                into.observe_capped(self.relative_path())?;
+                Ok(false)
+            }
+            _ if self.skipped < self.config.skip_documents => {
+                self.skipped += 1;
+                Ok(false)
+            }
+            _ => Ok(true),
+        }
+    }
+
+    fn check_shape(&mut self) -> Result<(), UnnestError> {
+        let keys = std::mem::take(&mut self.keys);
+        let shape = match &self.shape {
+            Some(shape) => shape,
+            None => {
+                self.shape = Some(keys);
+                return Ok(());
+            }
+        };
+        if *shape == keys {
+            return Ok(());
+        }
+        Err(UnnestError::InconsistentShape {
+            missing: shape.difference(&keys).cloned().collect(),
+            extra: keys.difference(shape).cloned().collect(),
+        })
+    }
+
+    /// Whether separators and whitespace are copied from the input, for [Config::verbatim].
+    fn verbatim(&self) -> bool {
+        self.config.verbatim && self.producing_regular_output()
     }
 
     fn compute_header(&self) -> bool {
-        match self.header_style {
-            HeaderStyle::None => false,
-            HeaderStyle::PathArray => true,
+        match self.config.header_style {
+            HeaderStyle::None => {
+                self.config.attach_comments
+                    || self.config.json_seq
+                    || self.config.observe_paths
+                    || !self.extra_targets.is_empty()
+            }
+            HeaderStyle::PathArray
+            | HeaderStyle::WithLine
+            | HeaderStyle::Dotted
+            | HeaderStyle::ByteRange
+            | HeaderStyle::Sequence
+            | HeaderStyle::LeafKey => true,
+        }
+    }
+
+    fn compute_path(&self) -> bool {
+        if self.config.observe_paths
+            || !self.config.path_prefix.is_empty()
+            || !self.config.include_keys.is_empty()
+            || !self.config.exclude_keys.is_empty()
+        {
+            return true;
+        }
+        match self.config.header_style {
+            HeaderStyle::None
+            | HeaderStyle::WithLine
+            | HeaderStyle::ByteRange
+            | HeaderStyle::Sequence => false,
+            HeaderStyle::PathArray | HeaderStyle::Dotted | HeaderStyle::LeafKey => true,
         }
     }
 }
@@ -76,189 +583,876 @@ impl Loc {
 /// of nesting, such as converting `[{"a":5}, {"a":6}]` into `{"a":5}` and `{"a":6}`.
 ///
 /// `header_style` controls how much context to retain. See [HeaderStyle].
+///
+/// Further settings are available through [unnest_to_ndjson_with], which this wraps.
 pub fn unnest_to_ndjson<R: Read>(
     from: R,
-    mut to: impl Sinker,
+    to: impl Sinker,
     target: usize,
     header_style: HeaderStyle,
 ) -> io::Result<()> {
-    let mut iter = Source::new(from);
-    let depth = -isize::try_from(target).map_err(|_| io::ErrorKind::InvalidData)?;
-    let mut loc = Loc {
-        depth,
-        path: Vec::with_capacity(target),
-        header_style,
+    Ok(unnest_to_ndjson_with(
+        from,
+        to,
+        &Config::new(target).header_style(header_style),
+    )?)
+}
+
+/// [unnest_to_ndjson], with the settings from a [Config].
+pub fn unnest_to_ndjson_with<R: Read>(
+    from: R,
+    to: impl Sinker,
+    config: &Config,
+) -> Result<(), UnnestError> {
+    unnest_to_ndjson_stats(from, to, config).map(|_| ())
+}
+
+/// [unnest_to_ndjson_with], also returning some information about the run.
+/// See [UnnestStats].
+pub fn unnest_to_ndjson_stats<R: Read>(
+    from: R,
+    to: impl Sinker,
+    config: &Config,
+) -> Result<UnnestStats, UnnestError> {
+    unnest_to_ndjson_after(&[], from, to, config)
+}
+
+/// [unnest_to_ndjson_stats], for input which starts with `prefix`, and continues with
+/// `from`, e.g. when some has already been read to detect what it is.
+///
+/// ```
+/// use unnest_ndjson::{unnest_to_ndjson_after, Config};
+///
+/// let mut out = Vec::new();
+/// unnest_to_ndjson_after(b"[1, 2", &b"2, 3]"[..], &mut out, &Config::new(1))?;
+/// assert_eq!(&b"1\n22\n3\n"[..], &out[..]);
+/// # Ok::<(), unnest_ndjson::UnnestError>(())
+/// ```
+pub fn unnest_to_ndjson_after<R: Read>(
+    prefix: &[u8],
+    from: R,
+    to: impl Sinker,
+    config: &Config,
+) -> Result<UnnestStats, UnnestError> {
+    let from = Source::with_initial_buffer(prefix, from, config.buffer_size);
+    #[cfg(feature = "gzip")]
+    if let Some(level) = config.output_compression {
+        return gzip::run_compressed(from, to, config, level);
+    }
+    run(from, measured::Measured::new(to), config)
+}
+
+/// How many documents [unnest_to_ndjson] would produce for this `target`, without
+/// producing them.
+///
+/// The input is still entirely parsed, and must be valid, but no output or paths are built.
+pub fn unnest_count<R: Read>(from: R, target: usize) -> io::Result<usize> {
+    let config = Config::new(target);
+    let from = Source::with_capacity(from, config.buffer_size);
+    let stats = run(from, Ignore {}, &config)?;
+    usize::try_from(stats.documents).map_err(|_| io::ErrorKind::InvalidData.into())
+}
+
+/// Like [unnest_to_ndjson], but keep all of the documents in memory, as their path and their
+/// value, without any header. See [sinks::CollectSink].
+///
+/// ```
+/// use unnest_ndjson::{unnest_collect, HeaderStyle};
+///
+/// let docs = unnest_collect(&br#"{"a": [1]}"#[..], 1, HeaderStyle::PathArray)?;
+/// assert_eq!(vec![(vec![b"\"a\"".to_vec()], b"[1]".to_vec())], docs);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn unnest_collect<R: Read>(
+    from: R,
+    target: usize,
+    header_style: HeaderStyle,
+) -> io::Result<Vec<sinks::Collected>> {
+    let mut sink = sinks::CollectSink::new();
+    unnest_to_ndjson(from, &mut sink, target, header_style)?;
+    Ok(sink.into_inner())
+}
+
+fn run<R: Read>(
+    from: Source<R>,
+    to: impl Sinker,
+    config: &Config,
+) -> Result<UnnestStats, UnnestError> {
+    if let Some(width) = config.wrap_width {
+        return run_separated(from, wrap::Wrapped::new(to, width), config);
+    }
+    run_separated(from, to, config)
+}
+
+fn run_separated<R: Read>(
+    from: Source<R>,
+    to: impl Sinker,
+    config: &Config,
+) -> Result<UnnestStats, UnnestError> {
+    if !config.trailing_separator {
+        let to = separated::Separated::new(to, &config.record_separator);
+        return run_pretty(from, to, config);
+    }
+    run_pretty(from, to, config)
+}
+
+fn run_pretty<R: Read>(
+    from: Source<R>,
+    to: impl Sinker,
+    config: &Config,
+) -> Result<UnnestStats, UnnestError> {
+    if let Some(width) = config.pretty {
+        return run_fingerprinted(from, pretty::Pretty::new(to, width), config);
+    }
+    run_fingerprinted(from, to, config)
+}
+
+fn run_fingerprinted<R: Read>(
+    from: Source<R>,
+    to: impl Sinker,
+    config: &Config,
+) -> Result<UnnestStats, UnnestError> {
+    if let Some((exclude, algorithm)) = &config.fingerprint {
+        let to = fingerprint::Fingerprinted::new(to, exclude, *algorithm);
+        return run_grouped(from, to, config);
+    }
+    run_grouped(from, to, config)
+}
+
+fn run_grouped<R: Read>(
+    from: Source<R>,
+    to: impl Sinker,
+    config: &Config,
+) -> Result<UnnestStats, UnnestError> {
+    if let Some(field) = &config.group_by {
+        return run_filtered(from, group::Grouped::new(to, field, config), config);
+    }
+    run_filtered(from, to, config)
+}
+
+fn run_filtered<R: Read>(
+    from: Source<R>,
+    to: impl Sinker,
+    config: &Config,
+) -> Result<UnnestStats, UnnestError> {
+    let filtered_out = Arc::default();
+    if let Some((field, wanted)) = &config.filter_equals {
+        let to = filter::Filtered::new(to, field, wanted, Arc::clone(&filtered_out));
+        return run_unfiltered(from, to, config, filtered_out);
+    }
+    run_unfiltered(from, to, config, filtered_out)
+}
+
+fn run_unfiltered<R: Read>(
+    from: Source<R>,
+    to: impl Sinker,
+    config: &Config,
+    filtered_out: Arc<AtomicU64>,
+) -> Result<UnnestStats, UnnestError> {
+    let mut iter = from.patience(config.heartbeat);
+    let hold = config.continue_on_error || !config.extra_targets.is_empty();
+    let mut to = recover::Recovering::new(to, hold);
+    let mut loc = Loc::new(config.clone())?;
+    loc.filtered_out = filtered_out;
+    let mut errors = Vec::new();
+    loop {
+        if loc.finished() {
+            to.flush()?;
+            // only here, when max_documents stopped the parse, is anything left unparsed
+            return Ok(UnnestStats {
+                documents: loc.produced(),
+                bytes_read: iter.bytes_read(),
+                max_depth_seen: loc.deepest,
+                errors,
+                remaining: Some(iter.remaining().to_vec()),
+            });
+        }
+        match step(&mut iter, &mut to, &mut loc) {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(UnnestError::Io(e)) => return Err(UnnestError::Io(e)),
+            Err(e) if loc.config.continue_on_error => {
+                // the problem may only be noticed on a later line, which could be fine
+                let later = matches!(loc.value_line, Some(line) if iter.position().line > line);
+                errors.push(e);
+                to.discard();
+                loc.reset();
+                if !later || !iter.rewind_to_line_start() {
+                    skip_line(&mut iter)?;
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    if loc.config.progress_every.is_some() {
+        to.observe_progress(iter.offset())?;
+    }
+    to.flush()?;
+    Ok(UnnestStats {
+        documents: loc.produced(),
+        bytes_read: iter.bytes_read(),
+        max_depth_seen: loc.deepest,
+        errors,
+        remaining: None,
+    })
+}
+
+/// Consume the rest of the line, after an error, for [Config::continue_on_error].
+fn skip_line<R: Read>(from: &mut Source<R>) -> Result<(), UnnestError> {
+    loop {
+        match from.next() {
+            Ok(b'\n') => return Ok(()),
+            Ok(_) => (),
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Make some progress through the input: until the end of the top-level value, or until
+/// we are asked to stop, e.g. by [Loc::pause]. `false` if the input was already finished.
+///
+/// The input may only end between top-level values; anywhere else is [UnnestError::Truncated].
+fn step<R: Read>(
+    from: &mut Source<R>,
+    into: &mut impl Sinker,
+    loc: &mut Loc,
+) -> Result<bool, UnnestError> {
+    let result = if loc.stack.is_empty() {
+        loc.value_line = None;
+        match between_top_level(from, into, loc) {
+            Ok(true) => return Ok(false),
+            Ok(false) => {
+                loc.documents_in_top_level = 0;
+                loc.needs_delimiter = true;
+                loc.started = true;
+                if loc.config.continue_on_error {
+                    loc.value_line = Some(from.line());
+                }
+                into.observe_top_level(item_kind(from.peek()?))?;
+                handle_one(from, into, loc)
+            }
+            Err(e) => Err(e),
+        }
+    } else {
+        continue_one(from, into, loc, 0)
+    };
+    result.map_err(|e| match e {
+        UnnestError::Io(e) if e.kind() == io::ErrorKind::UnexpectedEof => UnnestError::Truncated {
+            position: from.position(),
+        },
+        e => e,
+    })?;
+    Ok(true)
+}
+
+/// What a value is, from its first byte.
+fn item_kind(first: u8) -> ItemKind {
+    match first {
+        b'{' => ItemKind::Object,
+        b'[' => ItemKind::Array,
+        _ => ItemKind::Scalar,
+    }
+}
+
+/// Written at the start of some files, which are otherwise valid.
+pub(crate) const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// Skip whitespace, and any [Config::document_delimiter], between top-level values,
+/// and a [UTF8_BOM] at the start of the input. `true` if the input is finished.
+fn between_top_level<R: Read>(
+    from: &mut Source<R>,
+    into: &mut impl Sinker,
+    loc: &mut Loc,
+) -> Result<bool, UnnestError> {
+    drop_whitespace(from, into, loc)?;
+    // only at the very start, as anywhere else it is part of a value, or invalid
+    if 0 == from.offset() && from.starts_with(UTF8_BOM)? {
+        from.consume(UTF8_BOM.len());
+        drop_whitespace(from, into, loc)?;
+    }
+    if loc.config.single_document && loc.started && !from.at_eof()? {
+        return Err(UnnestError::Syntax {
+            position: from.position(),
+            message: "trailing data after the document",
+        });
+    }
+    let delimiter = match &loc.config.document_delimiter {
+        Some(delimiter) => delimiter.trim_ascii(),
+        None => return Ok(from.at_eof()?),
     };
+    if from.starts_with(delimiter)? {
+        from.consume(delimiter.len());
+        loc.needs_delimiter = false;
+        drop_whitespace(from, into, loc)?;
+    }
+    let finished = from.at_eof()?;
+    if !finished && loc.needs_delimiter && loc.config.require_document_delimiter {
+        return Err(UnnestError::Syntax {
+            position: from.position(),
+            message: "expected a document delimiter",
+        });
+    }
+    Ok(finished)
+}
+
+/// Skip whitespace, and any comments, if they are enabled.
+fn drop_whitespace<R: Read>(
+    from: &mut Source<R>,
+    into: &mut impl Sinker,
+    loc: &mut Loc,
+) -> Result<(), UnnestError> {
     loop {
-        match drop_whitespace(&mut iter) {
-            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
-            Err(e) => Err(e)?,
-            Ok(()) => (),
+        skip_whitespace(from, into, loc)?;
+        if !loc.config.accepts_comments() || from.at_eof()? || b'/' != from.peek()? {
+            return Ok(());
+        }
+        let _slash = from.next()?;
+        let comment = match from.next()? {
+            b'/' => line_comment(from)?,
+            b'*' => block_comment(from)?,
+            _ => return Err(invalid(from, "expected a comment")),
+        };
+        if loc.config.attach_comments && !loc.producing_regular_output() {
+            loc.pending_comment = Some(comment.trim_ascii().to_vec());
         }
-        handle_one(&mut iter, &mut to, &mut loc)?;
     }
-    Ok(())
 }
 
-fn drop_whitespace<R: Read>(from: &mut Source<R>) -> io::Result<()> {
+/// The text of a `//` comment, whose `//` has been consumed.
+fn line_comment<R: Read>(from: &mut Source<R>) -> Result<Vec<u8>, UnnestError> {
+    let mut comment = Vec::new();
     loop {
-        match from.buf().iter().position(|&b| !b.is_ascii_whitespace()) {
+        match from.next() {
+            Ok(b'\n') => return Ok(comment),
+            Ok(b) => comment.push(b),
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(comment),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// The text of a `/* */` comment, whose `/*` has been consumed.
+fn block_comment<R: Read>(from: &mut Source<R>) -> Result<Vec<u8>, UnnestError> {
+    let mut comment = Vec::new();
+    loop {
+        match from.next() {
+            // any error from the peek will be seen again by the next read
+            Ok(b'*') if matches!(from.peek(), Ok(b'/')) => {
+                let _slash = from.next()?;
+                return Ok(comment);
+            }
+            Ok(b) => comment.push(b),
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(UnnestError::Truncated {
+                    position: from.position(),
+                })
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Skip whitespace, checking for a [Config::heartbeat] whenever we have to wait for input.
+///
+/// Stops cleanly at the end of the input; the caller decides whether that is an error.
+fn skip_whitespace<R: Read>(
+    from: &mut Source<R>,
+    into: &mut impl Sinker,
+    loc: &mut Loc,
+) -> Result<(), UnnestError> {
+    let spec_whitespace = loc.config.spec_whitespace;
+    let keep = loc.verbatim();
+    loop {
+        let buf = from.buf();
+        let run = whitespace_run(buf);
+        let end = buf[run..]
+            .iter()
+            .position(|&b| !b.is_ascii_whitespace() || (spec_whitespace && b'\x0c' == b))
+            .map(|end| run + end);
+        match end {
+            Some(end) if b'\x0c' == from.buf()[end] => {
+                from.consume(end + 1);
+                return Err(invalid(from, "form feed is not whitespace in JSON"));
+            }
             Some(end) => {
+                if keep {
+                    into.write_all(&from.buf()[..end])?;
+                }
                 from.consume(end);
                 return Ok(());
             }
             None => {
+                if keep {
+                    into.write_all(from.buf())?;
+                }
                 from.all_useless();
-                from.fill()?;
+                if from.at_eof()? {
+                    return Ok(());
+                }
+                loc.heartbeat(into)?;
             }
         }
     }
 }
 
+/// How many bytes at the start of `buf` are whole chunks of JSON whitespace, which
+/// can be skipped without looking at them individually.
+///
+/// Each chunk is checked without branching, so the compiler can vectorise it, which is
+/// much faster for the long runs of indentation in pretty-printed input.
+fn whitespace_run(buf: &[u8]) -> usize {
+    const CHUNK: usize = 16;
+    let mut run = 0;
+    for chunk in buf.chunks_exact(CHUNK) {
+        let mut other = false;
+        for &b in chunk {
+            other |= (b' ' != b) & (b'\n' != b) & (b'\r' != b) & (b'\t' != b);
+        }
+        if other {
+            break;
+        }
+        run += CHUNK;
+    }
+    run
+}
+
+/// Where we are inside a container, for each level of nesting.
+///
+/// Separating commas are written when the next key or element starts, so that trailing
+/// commas in the input do not reach the output.
+#[derive(Copy, Clone)]
+enum Frame {
+    /// Expecting a key, or the end of the object, after this many keys.
+    ObjectKey(usize),
+    /// After a value, expecting a `,` or the end of the object.
+    ObjectNext(usize),
+    /// Expecting the element at this index, or the end of the array.
+    ArrayValue(usize),
+    /// After the element at this index, expecting a `,` or the end of the array.
+    ArrayNext(usize),
+}
+
+/// Consume one complete value, however deeply nested, using [Loc::stack] and not recursion.
 fn handle_one<R: Read>(
     from: &mut Source<R>,
     into: &mut impl Sinker,
     loc: &mut Loc,
-) -> io::Result<()> {
-    if loc.compute_header() && loc.at_target() {
-        into.observe_new_item(&loc.path, loc.header_style)?;
-    }
-    match from.next()? {
-        b'{' => handle_object(from, into, loc)?,
-        b'[' => handle_array(from, into, loc)?,
-        c => {
-            if loc.compute_header() && loc.shallower_than_target() {
-                into.observe_new_item(&loc.path, loc.header_style)?;
+) -> Result<(), UnnestError> {
+    let base = loc.stack.len();
+    begin_value(from, into, loc)?;
+    continue_one(from, into, loc, base)
+}
+
+/// Continue consuming the value which started when the stack was `base` deep.
+fn continue_one<R: Read>(
+    from: &mut Source<R>,
+    into: &mut impl Sinker,
+    loc: &mut Loc,
+    base: usize,
+) -> Result<(), UnnestError> {
+    while loc.stack.len() > base {
+        if loc.stopping() {
+            return Ok(());
+        }
+        drop_whitespace(from, into, loc)?;
+        let frame = loc.stack.last_mut().expect("non-empty stack");
+        match *frame {
+            Frame::ObjectKey(keys) => {
+                let start = match from.next()? {
+                    b',' if !loc.config.reject_trailing_commas => {
+                        if loc.verbatim() {
+                            into.write_all(b",")?;
+                        }
+                        continue;
+                    }
+                    b'"' => b'"',
+                    b'\'' if loc.config.allow_single_quotes => b'\'',
+                    b if loc.config.allow_unquoted_keys && is_identifier_start(b) => b,
+                    b'}' if 0 != keys && loc.config.reject_trailing_commas => {
+                        return Err(invalid(from, "expected a key"));
+                    }
+                    b'}' => {
+                        end_container(b'}', from, into, loc)?;
+                        continue;
+                    }
+                    _ => return Err(invalid(from, "expected a key or '}'")),
+                };
+                *frame = Frame::ObjectNext(keys + 1);
+                if 0 != keys && loc.producing_regular_output() && !loc.config.verbatim {
+                    into.write_all(b",")?;
+                }
+                handle_key(start, from, into, loc)?;
+                drop_whitespace(from, into, loc)?;
+                let colon = from.next()?;
+                if b':' != colon {
+                    return Err(invalid(from, "expected ':'"));
+                }
+                if loc.producing_regular_output() {
+                    into.write_all(b":")?;
+                }
+                drop_whitespace(from, into, loc)?;
+                begin_value(from, into, loc)?;
             }
-            if b'"' == c {
-                parse_string(from, into)?;
-            } else {
-                scan_primitive(c, from, into)?
+            Frame::ObjectNext(keys) => {
+                *frame = Frame::ObjectKey(keys);
+                if loc.compute_path() && loc.collecting_keys() {
+                    loc.pop_segment();
+                }
+
+                let delim = from.next()?;
+                match delim {
+                    b'}' => end_container(b'}', from, into, loc)?,
+                    b',' if loc.verbatim() => into.write_all(b",")?,
+                    b',' => (),
+                    _ => return Err(invalid(from, "expected ',' or '}'")),
+                }
             }
-            if loc.shallower_than_target() {
-                loc.write_suffix(into)?;
+            Frame::ArrayValue(idx) => {
+                if b']' == from.peek()? {
+                    let _infallible = from.next()?;
+                    if 0 != idx && loc.config.reject_trailing_commas {
+                        return Err(invalid(from, "expected a value"));
+                    }
+                    end_container(b']', from, into, loc)?;
+                    continue;
+                }
+                *frame = Frame::ArrayNext(idx);
+                if 0 != idx && loc.producing_regular_output() && !loc.config.verbatim {
+                    into.write_all(b",")?;
+                }
+                if loc.compute_path() && loc.collecting_keys() {
+                    let mut index = loc.spare_segment();
+                    write!(index, "{}", idx)?;
+                    loc.path.push(index);
+                }
+                begin_value(from, into, loc)?;
+            }
+            Frame::ArrayNext(idx) => {
+                *frame = Frame::ArrayValue(idx + 1);
+                if loc.compute_path() && loc.collecting_keys() {
+                    loc.pop_segment();
+                }
+
+                let delim = from.next()?;
+                match delim {
+                    b']' => end_container(b']', from, into, loc)?,
+                    b',' if loc.verbatim() => into.write_all(b",")?,
+                    b',' => (),
+                    _ => return Err(invalid(from, "expected ',' or ']'")),
+                }
             }
         }
     }
-    if loc.at_target() {
-        loc.write_suffix(into)?;
-    }
     Ok(())
 }
 
-fn handle_object<R: Read>(
+/// Start a value: scalars are consumed entirely, containers are pushed onto the stack.
+fn begin_value<R: Read>(
     from: &mut Source<R>,
     into: &mut impl Sinker,
     loc: &mut Loc,
-) -> io::Result<()> {
-    loc.depth += 1;
-
-    if loc.producing_regular_output() {
-        into.write_all(b"{")?;
+) -> Result<(), UnnestError> {
+    if loc.collecting_keys() && !loc.on_prefix() {
+        return skip_value(from, loc);
     }
-    loop {
-        drop_whitespace(from)?;
-        let s = from.next()?;
-        match s {
-            b',' => continue,
-            b'"' => (),
-            b'}' => break,
-            _ => return Err(io::ErrorKind::InvalidData.into()),
-        }
-        if loc.producing_regular_output() {
-            parse_string(from, into)?;
-        } else {
-            assert!(loc.collecting_keys());
-            if loc.compute_header() {
-                let mut key = Vec::with_capacity(32);
-                parse_string(from, &mut key)?;
-                loc.path.push(key);
-            } else {
-                parse_string(from, &mut Ignore {})?;
-            }
+    let start = from.offset();
+    if loc.at_target() {
+        if !loc.wanted() || !loc.admit_document(into)? {
+            return skip_value(from, loc);
         }
-        drop_whitespace(from)?;
-        let colon = from.next()?;
-        if b':' != colon {
-            return Err(io::ErrorKind::InvalidData.into());
+        loc.kind = item_kind(from.peek()?);
+        if loc.compute_header() {
+            loc.write_prefix(start, from, into)?;
         }
-        if loc.producing_regular_output() {
-            into.write_all(b":")?;
+        if loc.config.flatten.is_some() {
+            flatten::flatten_value(from, into, loc)?;
+            return end_value(from, into, loc);
         }
-        drop_whitespace(from)?;
-        handle_one(from, into, loc)?;
-        drop_whitespace(from)?;
-
-        if loc.compute_header() && loc.collecting_keys() {
-            let _ = loc.path.pop().unwrap();
+    } else if loc.at_extra_target() && loc.wanted() && loc.admit_document(into)? {
+        // otherwise, it is only part of the document it is inside
+        loc.enter_extra_target();
+        loc.kind = item_kind(from.peek()?);
+        loc.write_prefix(start, from, into)?;
+    }
+    match from.next()? {
+        b'{' => {
+            loc.push(Frame::ObjectKey(0))?;
+            if loc.config.reject_duplicate_keys {
+                loc.seen_keys.push(HashSet::new());
+            }
+            if loc.producing_regular_output() {
+                into.write_all(b"{")?;
+            }
         }
-
-        let delim = from.next()?;
-        match delim {
-            b'}' => break,
-            b',' => (),
-            _ => return Err(io::ErrorKind::InvalidData.into()),
+        b'[' => {
+            loc.push(Frame::ArrayValue(0))?;
+            if loc.producing_regular_output() {
+                into.write_all(b"[")?;
+            }
         }
-        if loc.producing_regular_output() {
-            into.write_all(b",")?;
+        c => {
+            if loc.shallower_than_target() && loc.stack.is_empty() {
+                match loc.config.scalar_root {
+                    ScalarRoot::Emit => (),
+                    ScalarRoot::Skip => return handle_scalar(c, from, &mut Ignore {}, loc),
+                    ScalarRoot::Error => {
+                        return Err(UnnestError::ScalarRoot {
+                            position: from.last_position(),
+                        })
+                    }
+                }
+            }
+            if loc.shallower_than_target() {
+                if !loc.wanted() || !loc.admit_document(into)? {
+                    return handle_scalar(c, from, &mut Ignore {}, loc);
+                }
+                loc.kind = ItemKind::Scalar;
+                if loc.compute_header() {
+                    loc.write_prefix(start, from, into)?;
+                }
+            }
+            let string = is_quote(c, &loc.config) && !loc.config.skeleton;
+            let raw = loc.config.raw_strings && !loc.producing_regular_output();
+            if string && (loc.config.classify_strings || raw) {
+                let mut value = Vec::with_capacity(32);
+                handle_scalar(c, from, &mut value, loc)?;
+                let content = &value[1..value.len() - 1];
+                if loc.config.classify_strings {
+                    let class = classify::classify(content);
+                    into.observe_string_class(loc.relative_path(), class)?;
+                }
+                if raw {
+                    into.write_all(&unescape::unescape(content))?;
+                } else {
+                    into.write_all(&value)?;
+                }
+            } else {
+                handle_scalar(c, from, into, loc)?;
+            }
+            if loc.shallower_than_target() {
+                loc.write_suffix(from, into)?;
+            }
+            end_value(from, into, loc)?;
         }
     }
+    Ok(())
+}
+
+/// Finish the container on the top of the stack, whose closing bracket has been consumed.
+fn end_container<R: Read>(
+    close: u8,
+    from: &Source<R>,
+    into: &mut impl Sinker,
+    loc: &mut Loc,
+) -> Result<(), UnnestError> {
     if loc.producing_regular_output() {
-        into.write_all(b"}")?;
+        into.write_all(&[close])?;
     }
 
-    loc.depth -= 1;
+    if b'}' == close && loc.config.enforce_uniform_keys && 1 == loc.depth {
+        loc.check_shape()?;
+    }
 
-    Ok(())
+    if b'}' == close && loc.config.reject_duplicate_keys {
+        let _ = loc.seen_keys.pop().expect("keys for the object");
+    }
+
+    loc.depth -= 1;
+    let _ = loc.stack.pop().expect("container on the stack");
+    end_value(from, into, loc)
 }
 
-fn handle_array<R: Read>(
-    from: &mut Source<R>,
+/// A value has been entirely consumed.
+fn end_value<R: Read>(
+    from: &Source<R>,
     into: &mut impl Sinker,
     loc: &mut Loc,
-) -> io::Result<()> {
-    loc.depth += 1;
+) -> Result<(), UnnestError> {
+    if loc.at_target() {
+        loc.write_suffix(from, into)?;
+    } else if loc.at_enclosed_end() {
+        loc.write_suffix(from, into)?;
+        loc.leave_extra_target();
+    }
+    Ok(())
+}
 
-    if loc.producing_regular_output() {
-        into.write_all(b"[")?;
+/// Consume a value without producing any output.
+fn skip_value<R: Read>(from: &mut Source<R>, loc: &mut Loc) -> Result<(), UnnestError> {
+    let depth = loc.depth;
+    // inside an output document, below every target, so no headers or paths are considered
+    loc.depth = loc.deepest_target() + 1;
+    let result = handle_one(from, &mut Ignore {}, loc);
+    loc.depth = depth;
+    result
+}
+
+fn handle_scalar<R: Read, W: sink::MiniWrite>(
+    start: u8,
+    from: &mut Source<R>,
+    into: &mut W,
+    loc: &Loc,
+) -> Result<(), UnnestError> {
+    if loc.config.skeleton {
+        write_type_name(start, from, into, &loc.config)
+    } else if is_quote(start, &loc.config) {
+        parse_string(start, from, into, &loc.config)
+    } else if loc.config.examines_primitives() {
+        let position = from.last_position();
+        let mut token = Vec::with_capacity(32);
+        scan_primitive(start, from, &mut token)?;
+        rewrite_primitive(&mut token, &loc.config)
+            .map_err(|message| UnnestError::Syntax { position, message })?;
+        Ok(into.write_all(&token)?)
+    } else {
+        Ok(scan_primitive(start, from, into)?)
     }
+}
 
-    for idx in 0usize.. {
-        drop_whitespace(from)?;
-        if let Ok(b']') = from.peek() {
-            let _infallible = from.next()?;
-            break;
+/// Check, and possibly rewrite, a number or literal, according to the [Config].
+fn rewrite_primitive(token: &mut Vec<u8>, config: &Config) -> Result<(), &'static str> {
+    if config.allow_js_numbers {
+        if let Some(b'+') = token.first() {
+            let _plus = token.remove(0);
+        }
+        if matches!(&token[..], b"NaN" | b"Infinity" | b"-Infinity") {
+            // as JSON.stringify would
+            *token = b"null".to_vec();
         }
+    }
 
-        if loc.compute_header() && loc.collecting_keys() {
-            loc.path.push(format!("{}", idx).into_bytes());
+    let start = *token.first().ok_or("invalid number")?;
+    if !number::is_numeric_start(start) {
+        if config.validate_literals && !matches!(&token[..], b"true" | b"false" | b"null") {
+            return Err("invalid literal");
         }
-        handle_one(from, into, loc)?;
-        if loc.compute_header() && loc.collecting_keys() {
-            let _ = loc.path.pop().unwrap();
+        if config.python_literals {
+            let python: &[u8] = match &token[..] {
+                b"true" => b"True",
+                b"false" => b"False",
+                b"null" => b"None",
+                _ => return Ok(()),
+            };
+            *token = python.to_vec();
         }
+        return Ok(());
+    }
 
-        drop_whitespace(from)?;
-
-        let delim = from.next()?;
-        match delim {
-            b']' => break,
-            b',' => (),
-            _ => return Err(io::ErrorKind::InvalidData.into()),
+    if config.validate_numbers && !number::is_valid(token) {
+        return Err("invalid number");
+    }
+    if let Some(rounding) = &config.number_precision {
+        if let Some(rounded) = number::round(token, rounding) {
+            *token = rounded;
         }
+    }
+    if config.normalize_numbers {
+        number::normalize(token);
+    }
+    Ok(())
+}
+
+/// Consume an object key, whose first byte, `start`, has been consumed.
+fn handle_key<R: Read>(
+    start: u8,
+    from: &mut Source<R>,
+    into: &mut impl Sinker,
+    loc: &mut Loc,
+) -> Result<(), UnnestError> {
+    let shape_key = loc.config.enforce_uniform_keys && 1 == loc.depth;
+    let path_key = loc.collecting_keys() && loc.compute_path();
+    if !shape_key && !path_key && !loc.config.reject_duplicate_keys {
         if loc.producing_regular_output() {
-            into.write_all(b",")?;
+            parse_key(start, from, into, &loc.config)?;
+        } else {
+            parse_key(start, from, &mut Ignore {}, &loc.config)?;
+        }
+        return Ok(());
+    }
+
+    let mut key = loc.spare_segment();
+    parse_key(start, from, &mut key, &loc.config)?;
+    if loc.config.reject_duplicate_keys {
+        let seen = loc.seen_keys.last_mut().expect("keys for the object");
+        if !seen.insert(key.clone()) {
+            return Err(UnnestError::DuplicateKey {
+                key,
+                position: from.position(),
+            });
         }
     }
     if loc.producing_regular_output() {
-        into.write_all(b"]")?;
+        into.write_all(&key)?;
     }
+    // with [Config::extra_targets], a key in the document can also be a segment of the path
+    if shape_key {
+        loc.keys.insert(key.clone());
+    }
+    if path_key {
+        if let Some(normalize) = &loc.config.normalize_path_keys {
+            normalize.apply(&mut key);
+        }
+        loc.path.push(key);
+    }
+    Ok(())
+}
 
-    loc.depth -= 1;
-
+/// Consume a key, which is a string starting with a quote, or an unquoted identifier,
+/// for [Config::allow_unquoted_keys], and write it as a string, after [Config::rename_keys].
+fn parse_key<R: Read, W: sink::MiniWrite>(
+    start: u8,
+    from: &mut Source<R>,
+    into: &mut W,
+    config: &Config,
+) -> Result<(), UnnestError> {
+    if config.rename_keys.is_empty() {
+        return parse_key_as_written(start, from, into, config);
+    }
+    let mut key = Vec::with_capacity(32);
+    parse_key_as_written(start, from, &mut key, config)?;
+    match config.rename_keys.get(&key[1..key.len() - 1]) {
+        Some(renamed) => {
+            into.write_all(b"\"")?;
+            sink::write_json_string_content(into, renamed)?;
+            into.write_all(b"\"")?;
+        }
+        None => into.write_all(&key)?,
+    }
     Ok(())
 }
 
+fn parse_key_as_written<R: Read, W: sink::MiniWrite>(
+    start: u8,
+    from: &mut Source<R>,
+    into: &mut W,
+    config: &Config,
+) -> Result<(), UnnestError> {
+    if b'"' == start || b'\'' == start {
+        return parse_string(start, from, into, config);
+    }
+    into.write_all(&[b'"', start])?;
+    while let Ok(b) = from.peek() {
+        if !is_identifier_start(b) && !b.is_ascii_digit() {
+            break;
+        }
+        // infalliable, as we just peeked it
+        let b = from.next()?;
+        into.write_all(&[b])?;
+    }
+    Ok(into.write_all(b"\"")?)
+}
+
+/// Whether `b` starts a string, possibly a [Config::allow_single_quotes] one.
+fn is_quote(b: u8, config: &Config) -> bool {
+    b'"' == b || (b'\'' == b && config.allow_single_quotes)
+}
+
+/// Whether a byte can start an unquoted key; digits may follow.
+fn is_identifier_start(b: u8) -> bool {
+    b.is_ascii_alphabetic() || b'_' == b || b'$' == b
+}
+
 fn scan_primitive<R: Read, W: sink::MiniWrite>(
     start: u8,
     from: &mut Source<R>,
@@ -271,6 +1465,7 @@ fn scan_primitive<R: Read, W: sink::MiniWrite>(
             || b']' == b
             || b'}' == b
             || b':' == b
+            || b'/' == b
             || b.is_ascii_control()
         {
             break;
@@ -283,38 +1478,108 @@ fn scan_primitive<R: Read, W: sink::MiniWrite>(
     Ok(())
 }
 
-fn parse_string<R: Read, W: sink::MiniWrite>(from: &mut Source<R>, into: &mut W) -> io::Result<()> {
+/// Consume a scalar, and write the name of its type in its place, for [Config::skeleton].
+fn write_type_name<R: Read, W: sink::MiniWrite>(
+    start: u8,
+    from: &mut Source<R>,
+    into: &mut W,
+    config: &Config,
+) -> Result<(), UnnestError> {
+    let is_string = is_quote(start, config);
+    let name: &[u8] = match start {
+        _ if is_string => {
+            parse_string(start, from, &mut Ignore {}, config)?;
+            br#""string""#
+        }
+        b't' | b'f' => br#""boolean""#,
+        b'n' => br#""null""#,
+        _ => br#""number""#,
+    };
+    if !is_string {
+        scan_primitive(start, from, &mut Ignore {})?;
+    }
+    Ok(into.write_all(name)?)
+}
+
+/// Consume a string, whose opening `quote` has been consumed, and write it with double quotes.
+///
+/// Escapes are copied as they are; in particular, `\u` escapes are checked, but not decoded,
+/// so a surrogate pair stays as two escapes. `quote` is `'` only for
+/// [Config::allow_single_quotes].
+fn parse_string<R: Read, W: sink::MiniWrite>(
+    quote: u8,
+    from: &mut Source<R>,
+    into: &mut W,
+    config: &Config,
+) -> Result<(), UnnestError> {
     into.write_all(b"\"")?;
     loop {
         let buf = from.buf();
-        let quote = memchr(b'"', buf).unwrap_or(buf.len());
-        let escape = memchr(b'\\', buf).unwrap_or(buf.len());
-        let safe = quote.min(escape);
+        let special = if b'"' == quote {
+            memchr2(quote, b'\\', buf)
+        } else {
+            memchr3(quote, b'"', b'\\', buf)
+        };
+        let mut safe = special.unwrap_or(buf.len());
+        if config.reject_control_characters {
+            if let Some(control) = buf[..safe].iter().position(|&b| b < 0x20) {
+                from.consume(control + 1);
+                return Err(invalid(from, "control character in string"));
+            }
+        } else if config.escape_control_characters {
+            // stop there, for it to be escaped below
+            if let Some(control) = buf[..safe].iter().position(|&b| b < 0x20) {
+                safe = control;
+            }
+        } else if !config.allow_raw_newlines {
+            if let Some(newline) = memchr2(b'\n', b'\r', &buf[..safe]) {
+                from.consume(newline + 1);
+                return Err(invalid(from, "unescaped newline in string"));
+            }
+        }
+        if config.validate_utf8 && !buf[..safe].is_ascii() {
+            if let Err(e) = std::str::from_utf8(&buf[..safe]) {
+                if e.error_len().is_some() {
+                    from.consume(e.valid_up_to() + 1);
+                    return Err(invalid(from, "invalid UTF-8 in string"));
+                }
+                // a character split by the end of the buffer, which is checked below
+                safe = e.valid_up_to();
+            }
+        }
         into.write_all(&buf[..safe])?;
         from.consume(safe);
         let b = from.next()?;
         match b {
-            b'"' => break,
-            b'\r' | b'\n' => return Err(io::ErrorKind::InvalidData.into()),
+            b if quote == b => break,
+            // only in a single-quoted string
+            b'"' => into.write_all(b"\\\"")?,
             b'\\' => {
                 let e = from.next()?;
                 match e {
                     b'"' | b'/' | b'\\' | b'b' | b'f' | b'r' | b'n' | b't' => {
                         into.write_all(&[b'\\', e])?;
                     }
+                    b'\'' if b'\'' == quote => into.write_all(b"'")?,
                     b'u' => {
-                        into.write_all(&[b'\\', b'u'])?;
-                        for _ in 0..4 {
-                            let h: u8 = from.next()?;
-                            if !h.is_ascii_hexdigit() {
-                                return Err(io::ErrorKind::InvalidData.into());
-                            }
-                            into.write_all(&[h])?
+                        let code = unicode_escape(from, into)?;
+                        if config.validate_surrogates {
+                            check_surrogates(code, from, into)?;
                         }
                     }
-                    _ => return Err(io::ErrorKind::InvalidData.into()),
+                    _ => return Err(invalid(from, "invalid escape")),
                 }
             }
+            o if o < 0x20 && config.reject_control_characters => {
+                return Err(invalid(from, "control character in string"));
+            }
+            o if o < 0x20 && config.escape_control_characters => {
+                sink::write_json_string_content(into, &[o])?;
+            }
+            b'\r' | b'\n' if !config.allow_raw_newlines => {
+                return Err(invalid(from, "unescaped newline in string"));
+            }
+            o if o >= 0x80 && config.validate_utf8 => utf8_char(o, from, into)?,
             o => into.write_all(&[o])?,
         }
     }
@@ -322,19 +1587,96 @@ fn parse_string<R: Read, W: sink::MiniWrite>(from: &mut Source<R>, into: &mut W)
     Ok(())
 }
 
+/// Copy a multi-byte character, whose first byte, `lead`, has been consumed, checking it
+/// is valid UTF-8, for [Config::validate_utf8].
+fn utf8_char<R: Read, W: sink::MiniWrite>(
+    lead: u8,
+    from: &mut Source<R>,
+    into: &mut W,
+) -> Result<(), UnnestError> {
+    let len = match lead {
+        0xC2..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF4 => 4,
+        _ => return Err(invalid(from, "invalid UTF-8 in string")),
+    };
+    let mut bytes = [lead, 0, 0, 0];
+    for b in &mut bytes[1..len] {
+        *b = from.next()?;
+        // stop at the end of the string, instead of taking it as part of the character
+        if 0x80 != *b & 0xC0 {
+            return Err(invalid(from, "invalid UTF-8 in string"));
+        }
+    }
+    // overlong forms, and surrogates
+    if std::str::from_utf8(&bytes[..len]).is_err() {
+        return Err(invalid(from, "invalid UTF-8 in string"));
+    }
+    Ok(into.write_all(&bytes[..len])?)
+}
+
+/// Copy the four hex digits of a `\u` escape, whose `\u` has been consumed.
+fn unicode_escape<R: Read, W: sink::MiniWrite>(
+    from: &mut Source<R>,
+    into: &mut W,
+) -> Result<u16, UnnestError> {
+    into.write_all(b"\\u")?;
+    let mut code = 0u16;
+    for _ in 0..4 {
+        let h: u8 = from.next()?;
+        let digit = match (h as char).to_digit(16) {
+            Some(digit) => digit as u16,
+            None => return Err(invalid(from, "invalid unicode escape")),
+        };
+        code = code << 4 | digit;
+        into.write_all(&[h])?
+    }
+    Ok(code)
+}
+
+/// Require that a high surrogate is followed by a low one, for [Config::validate_surrogates].
+fn check_surrogates<R: Read, W: sink::MiniWrite>(
+    code: u16,
+    from: &mut Source<R>,
+    into: &mut W,
+) -> Result<(), UnnestError> {
+    match code {
+        0xD800..=0xDBFF => {
+            if b'\\' != from.next()? || b'u' != from.next()? {
+                return Err(invalid(from, "unpaired surrogate"));
+            }
+            match unicode_escape(from, into)? {
+                0xDC00..=0xDFFF => Ok(()),
+                _ => Err(invalid(from, "unpaired surrogate")),
+            }
+        }
+        0xDC00..=0xDFFF => Err(invalid(from, "unpaired surrogate")),
+        _ => Ok(()),
+    }
+}
+
+/// The byte we just consumed is not allowed here.
+fn invalid<R: Read>(from: &Source<R>, message: &'static str) -> UnnestError {
+    UnnestError::Syntax {
+        position: from.last_position(),
+        message,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::io;
 
     use super::parse_string;
     use super::Source;
+    use crate::Config;
 
     fn ps(buf: &str) -> io::Result<String> {
         let mut v = Vec::with_capacity(buf.len());
-        let mut buf = Source::new(io::Cursor::new(buf.as_bytes()));
+        let mut buf = Source::with_capacity(io::Cursor::new(buf.as_bytes()), 16);
         // remove leading quote, as scan_one does
         buf.next()?;
-        parse_string(&mut buf, &mut v)?;
+        parse_string(b'"', &mut buf, &mut v, &Config::new(0))?;
         Ok(String::from_utf8(v).unwrap())
     }
 
@@ -343,4 +1685,29 @@ mod tests {
         assert_eq!(r#""hello world""#, ps(r#""hello world""#)?);
         Ok(())
     }
+
+    #[test]
+    fn unicode_escapes() -> io::Result<()> {
+        assert_eq!(r#""\u0041\u0042""#, ps(r#""\u0041\u0042""#)?);
+        assert_eq!(r#""\u0041\n\u0042""#, ps(r#""\u0041\n\u0042""#)?);
+        // longer than the buffer
+        let many = format!(r#""{}""#, r"\u00eb".repeat(10));
+        assert_eq!(many, ps(&many)?);
+        Ok(())
+    }
+
+    #[test]
+    fn initial_buffer() -> io::Result<()> {
+        let mut v = Vec::new();
+        let mut buf = Source::with_initial_buffer(br#""hello"#, &b" world\"\n5"[..], 4);
+        buf.next()?;
+        parse_string(b'"', &mut buf, &mut v, &Config::new(0))?;
+        assert_eq!(r#""hello world""#, String::from_utf8(v).unwrap());
+        assert_eq!(b'\n', buf.next()?);
+        assert_eq!(b'5', buf.next()?);
+        assert_eq!(15, buf.offset());
+        assert_eq!(2, buf.line());
+        assert!(buf.at_eof()?);
+        Ok(())
+    }
 }
@@ -0,0 +1,63 @@
+use std::io;
+
+use crate::sink::{forward_hooks, write_end, write_start, Forward};
+use crate::{Header, MiniWrite, Sinker};
+
+/// Count the bytes of each document, for [Sinker::observe_document_len].
+///
+/// This is the innermost sink, so it sees what the other wrappers produce. The header
+/// and the end are counted as the default implementation would write them.
+pub(crate) struct Measured<S> {
+    inner: S,
+    len: usize,
+}
+
+impl<S: Sinker> Measured<S> {
+    pub(crate) fn new(inner: S) -> Self {
+        Measured { inner, len: 0 }
+    }
+}
+
+/// Count what would be written, without writing it anywhere.
+struct Counter(usize);
+
+impl MiniWrite for Counter {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.0 += buf.len();
+        Ok(())
+    }
+}
+
+impl<S: Sinker> MiniWrite for Measured<S> {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.len += buf.len();
+        self.inner.write_all(buf)
+    }
+}
+
+impl<S: Sinker> Sinker for Measured<S> {
+    fn observe_new_item_with(&mut self, path: &[Vec<u8>], header: &Header) -> io::Result<()> {
+        let mut counter = Counter(0);
+        write_start(&mut counter, path, header)?;
+        self.len = counter.0;
+        self.inner.observe_new_item_with(path, header)
+    }
+
+    fn observe_end_with(&mut self, header: &Header) -> io::Result<()> {
+        let mut counter = Counter(self.len);
+        write_end(&mut counter, header)?;
+        self.len = 0;
+        self.inner.observe_end_with(header)?;
+        self.inner.observe_document_len(counter.0)
+    }
+
+    forward_hooks!();
+}
+
+impl<S: Sinker> Forward for Measured<S> {
+    type Inner = S;
+
+    fn inner(&mut self) -> &mut S {
+        &mut self.inner
+    }
+}
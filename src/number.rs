@@ -0,0 +1,287 @@
+//! Checking and rewriting numbers, which are otherwise copied through untouched.
+
+use std::cmp::Ordering;
+
+/// How to round numbers, for [crate::Config::number_precision].
+///
+/// Rounding is done on the decimal digits, so `0.15` to one place is `0.2`, not the
+/// `0.1` that a binary floating point conversion would give.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct NumberRounding {
+    digits: Digits,
+    half_even: bool,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Digits {
+    DecimalPlaces(u32),
+    Significant(u32),
+}
+
+impl NumberRounding {
+    /// Keep at most `places` digits after the decimal point.
+    pub fn decimal_places(places: u32) -> Self {
+        NumberRounding {
+            digits: Digits::DecimalPlaces(places),
+            half_even: false,
+        }
+    }
+
+    /// Keep at most `digits` significant digits; at least one is always kept.
+    pub fn significant_digits(digits: u32) -> Self {
+        NumberRounding {
+            digits: Digits::Significant(digits.max(1)),
+            half_even: false,
+        }
+    }
+
+    /// Round exact halves to the even neighbour (banker's rounding), so `2.5` becomes `2`.
+    ///
+    /// By default, halves are rounded up, away from zero, so `2.5` becomes `3`,
+    /// and `-2.5` becomes `-3`.
+    pub fn half_even(mut self, half_even: bool) -> Self {
+        self.half_even = half_even;
+        self
+    }
+}
+
+/// Whether a primitive looks like it was meant to be a number, rather than a literal.
+pub(crate) fn is_numeric_start(start: u8) -> bool {
+    start.is_ascii_digit() || b'-' == start || b'+' == start || b'.' == start
+}
+
+/// Check a number against the JSON grammar:
+/// an optional minus, an integer part with no leading zeros, an optional fraction,
+/// and an optional exponent, introduced by either `e` or `E`.
+pub(crate) fn is_valid(token: &[u8]) -> bool {
+    let mut rest = token.strip_prefix(b"-").unwrap_or(token);
+
+    rest = match rest {
+        [b'0', tail @ ..] => tail,
+        [b'1'..=b'9', ..] => skip_digits(rest),
+        _ => return false,
+    };
+
+    if let [b'.', tail @ ..] = rest {
+        let after = skip_digits(tail);
+        if after.len() == tail.len() {
+            return false;
+        }
+        rest = after;
+    }
+
+    if let [b'e' | b'E', tail @ ..] = rest {
+        let tail = match tail {
+            [b'+' | b'-', tail @ ..] => tail,
+            _ => tail,
+        };
+        let after = skip_digits(tail);
+        if after.len() == tail.len() {
+            return false;
+        }
+        rest = after;
+    }
+
+    rest.is_empty()
+}
+
+fn skip_digits(buf: &[u8]) -> &[u8] {
+    let digits = buf.iter().take_while(|b| b.is_ascii_digit()).count();
+    &buf[digits..]
+}
+
+/// Rewrite a valid number into a canonical form: the exponent marker is lower-cased.
+pub(crate) fn normalize(token: &mut [u8]) {
+    if let Some(e) = token.iter_mut().find(|b| b'E' == **b) {
+        *e = b'e';
+    }
+}
+
+/// Round a number, if it has more digits than `rounding` allows.
+///
+/// Integers (without a fraction or exponent) and invalid numbers are left alone, as is
+/// anything which already fits, returning `None`. A number with an exponent keeps one.
+/// So is a number whose exponent is too large to adjust, such as `1.5e9223372036854775807`.
+pub(crate) fn round(token: &[u8], rounding: &NumberRounding) -> Option<Vec<u8>> {
+    if !is_valid(token) {
+        return None;
+    }
+    let (negative, rest) = match token.strip_prefix(b"-") {
+        Some(rest) => (true, rest),
+        None => (false, token),
+    };
+    let mantissa_end = rest
+        .iter()
+        .position(|b| b'e' == *b || b'E' == *b)
+        .unwrap_or(rest.len());
+    let (mantissa, exponent) = rest.split_at(mantissa_end);
+    let (int, frac) = match mantissa.iter().position(|b| b'.' == *b) {
+        Some(dot) => (&mantissa[..dot], &mantissa[dot + 1..]),
+        None => (mantissa, &b""[..]),
+    };
+    if frac.is_empty() && exponent.is_empty() {
+        return None;
+    }
+
+    let has_exponent = !exponent.is_empty();
+    let exponent: i64 = match exponent {
+        [] => 0,
+        [_e, digits @ ..] => std::str::from_utf8(digits).ok()?.parse().ok()?,
+    };
+
+    let mut digits: Vec<u8> = int.iter().chain(frac).map(|b| b - b'0').collect();
+    // the number of digits before the decimal point
+    let mut point = exponent.checked_add(int.len() as i64)?;
+
+    let keep = match rounding.digits {
+        Digits::DecimalPlaces(places) => point.checked_add(i64::from(places))?,
+        Digits::Significant(wanted) => {
+            // zero has nothing to round
+            let first = digits.iter().position(|&d| 0 != d)?;
+            first as i64 + i64::from(wanted)
+        }
+    };
+    if keep >= digits.len() as i64 {
+        return None;
+    }
+
+    // a negative keep is more than half a unit below the last kept place
+    let up = keep >= 0 && {
+        let keep = keep as usize;
+        match digits[keep].cmp(&5) {
+            Ordering::Greater => true,
+            Ordering::Less => false,
+            Ordering::Equal => {
+                let exact_half = digits[keep + 1..].iter().all(|&d| 0 == d);
+                let odd = keep > 0 && 1 == digits[keep - 1] % 2;
+                !rounding.half_even || !exact_half || odd
+            }
+        }
+    };
+    digits.truncate(keep.max(0) as usize);
+    if up {
+        match digits.iter().rposition(|&d| 9 != d) {
+            Some(last) => {
+                digits[last] += 1;
+                digits.truncate(last + 1);
+            }
+            None => {
+                // every kept digit was a nine, so this is the next power of ten
+                digits.clear();
+                digits.push(1);
+                point = point.checked_add(1)?;
+            }
+        }
+    }
+
+    format(negative, digits, point, has_exponent)
+}
+
+/// Write out decimal digits, with the decimal point after `point` of them.
+///
+/// `None` if the exponent to write does not fit in an `i64`.
+fn format(negative: bool, mut digits: Vec<u8>, mut point: i64, exponent: bool) -> Option<Vec<u8>> {
+    while let Some(0) = digits.first() {
+        digits.remove(0);
+        point = point.checked_sub(1)?;
+    }
+    while let Some(0) = digits.last() {
+        digits.pop();
+    }
+    if digits.is_empty() {
+        return Some(b"0".to_vec());
+    }
+
+    let mut out = Vec::with_capacity(digits.len() + 8);
+    if negative {
+        out.push(b'-');
+    }
+    let digits: Vec<u8> = digits.into_iter().map(|d| b'0' + d).collect();
+    if exponent {
+        out.push(digits[0]);
+        if digits.len() > 1 {
+            out.push(b'.');
+            out.extend_from_slice(&digits[1..]);
+        }
+        out.extend_from_slice(format!("e{}", point.checked_sub(1)?).as_bytes());
+    } else if point <= 0 {
+        out.extend_from_slice(b"0.");
+        out.resize(out.len() + (-point) as usize, b'0');
+        out.extend_from_slice(&digits);
+    } else if point as usize >= digits.len() {
+        out.extend_from_slice(&digits);
+        out.resize(out.len() + point as usize - digits.len(), b'0');
+    } else {
+        let (int, frac) = digits.split_at(point as usize);
+        out.extend_from_slice(int);
+        out.push(b'.');
+        out.extend_from_slice(frac);
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_valid, round, NumberRounding};
+
+    #[test]
+    fn grammar() {
+        for valid in [
+            "0", "-0", "5", "10", "0.5", "-1.25", "1e10", "1E10", "1.5E-3", "1e+2",
+        ] {
+            assert!(is_valid(valid.as_bytes()), "{}", valid);
+        }
+        for invalid in [
+            "", "-", "01", "1.", ".5", "1e", "1E+", "+1", "1.2.3", "--5", "0x10",
+        ] {
+            assert!(!is_valid(invalid.as_bytes()), "{}", invalid);
+        }
+    }
+
+    fn rounded(token: &str, rounding: NumberRounding) -> String {
+        match round(token.as_bytes(), &rounding) {
+            Some(out) => String::from_utf8(out).unwrap(),
+            None => token.to_string(),
+        }
+    }
+
+    #[test]
+    fn rounding() {
+        let two = NumberRounding::decimal_places(2);
+        assert_eq!("3.14", rounded("3.14159", two));
+        assert_eq!("-3.15", rounded("-3.14659", two));
+        assert_eq!("10", rounded("9.999", two));
+        assert_eq!("0", rounded("-0.001", two));
+        assert_eq!("1.5", rounded("1.5", two));
+        assert_eq!("12345678901234567890", rounded("12345678901234567890", two));
+        assert_eq!(
+            "1.23e2",
+            rounded("1.23456E2", NumberRounding::decimal_places(0))
+        );
+
+        let zero = NumberRounding::decimal_places(0);
+        assert_eq!("3", rounded("2.5", zero));
+        assert_eq!("2", rounded("2.5", zero.half_even(true)));
+        assert_eq!("4", rounded("3.5", zero.half_even(true)));
+        assert_eq!("3", rounded("2.500001", zero.half_even(true)));
+        assert_eq!("1e0", rounded("6e-1", zero));
+
+        let sig = NumberRounding::significant_digits(2);
+        assert_eq!("0.0012", rounded("0.001234", sig));
+        assert_eq!("12000", rounded("12345.6", sig));
+        assert_eq!("1e3", rounded("9.96e2", sig));
+    }
+
+    #[test]
+    fn huge_exponents() {
+        let two = NumberRounding::decimal_places(2);
+        let sig = NumberRounding::significant_digits(2);
+        for token in ["1.2345e9223372036854775807", "9.99e9223372036854775806"] {
+            assert_eq!(token, rounded(token, two));
+            assert_eq!(token, rounded(token, sig));
+        }
+        let tiny = "0.00123e-9223372036854775807";
+        assert_eq!("0", rounded(tiny, two));
+        assert_eq!(tiny, rounded(tiny, sig));
+    }
+}
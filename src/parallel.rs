@@ -0,0 +1,99 @@
+use std::io;
+
+use rayon::prelude::*;
+
+use crate::{Header, MiniWrite, Sinker};
+
+/// A document waiting to be processed, as its path and its value.
+type Pending = (Vec<Vec<u8>>, Vec<u8>);
+
+/// Process documents on the [rayon] thread pool, for when the work done on each document
+/// is slower than finding it.
+///
+/// Documents are collected into batches, without any header. `work` is called on each
+/// document in a batch in parallel, then `emit` is called with the results, in the order
+/// of the input, before the next batch is started. The last batch is processed by
+/// [Sinker::flush], which happens at the end of the input.
+///
+/// ```
+/// use unnest_ndjson::sinks::ParallelSink;
+/// use unnest_ndjson::{unnest_to_ndjson_with, Config};
+///
+/// let mut lens = Vec::new();
+/// let sink = ParallelSink::new(2, |_, value| value.len(), |len| Ok(lens.push(len)));
+/// unnest_to_ndjson_with(&br#"[1, "two", [3]]"#[..], sink, &Config::new(1))?;
+/// assert_eq!(vec![1, 5, 3], lens);
+/// # Ok::<(), unnest_ndjson::UnnestError>(())
+/// ```
+pub struct ParallelSink<W, E> {
+    work: W,
+    emit: E,
+    batch_size: usize,
+    batch: Vec<Pending>,
+    path: Vec<Vec<u8>>,
+    value: Vec<u8>,
+}
+
+impl<T, W, E> ParallelSink<W, E>
+where
+    T: Send,
+    W: Fn(&[Vec<u8>], &[u8]) -> T + Sync,
+    E: FnMut(T) -> io::Result<()>,
+{
+    /// Process `batch_size` documents at a time, which must be at least one. Larger batches
+    /// keep the threads busier, but hold more documents in memory.
+    pub fn new(batch_size: usize, work: W, emit: E) -> Self {
+        assert!(batch_size > 0, "a batch must have a document");
+        ParallelSink {
+            work,
+            emit,
+            batch_size,
+            batch: Vec::with_capacity(batch_size),
+            path: Vec::new(),
+            value: Vec::with_capacity(1024),
+        }
+    }
+
+    fn process(&mut self) -> io::Result<()> {
+        let work = &self.work;
+        let results: Vec<T> = self
+            .batch
+            .par_iter()
+            .map(|(path, value)| work(path, value))
+            .collect();
+        self.batch.clear();
+        results.into_iter().try_for_each(&mut self.emit)
+    }
+}
+
+impl<W, E> MiniWrite for ParallelSink<W, E> {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.value.extend_from_slice(buf);
+        Ok(())
+    }
+}
+
+impl<T, W, E> Sinker for ParallelSink<W, E>
+where
+    T: Send,
+    W: Fn(&[Vec<u8>], &[u8]) -> T + Sync,
+    E: FnMut(T) -> io::Result<()>,
+{
+    fn observe_new_item_with(&mut self, path: &[Vec<u8>], _: &Header) -> io::Result<()> {
+        self.path = path.to_vec();
+        Ok(())
+    }
+
+    fn observe_end_with(&mut self, _: &Header) -> io::Result<()> {
+        let value = std::mem::replace(&mut self.value, Vec::with_capacity(1024));
+        self.batch.push((std::mem::take(&mut self.path), value));
+        if self.batch.len() >= self.batch_size {
+            self.process()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.process()
+    }
+}
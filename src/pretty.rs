@@ -0,0 +1,116 @@
+use std::io;
+
+use crate::sink::{forward_hooks, Forward};
+use crate::{Header, MiniWrite, Sinker};
+
+/// Re-indent each document as it is written, for [crate::Config::pretty].
+///
+/// The document is compact JSON, so only the structure needs to be tracked, not any
+/// whitespace. The header, and anything after the document, is passed through unchanged.
+pub(crate) struct Pretty<S> {
+    inner: S,
+    width: usize,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    /// A container has been opened, and we don't yet know if it is empty.
+    opened: bool,
+}
+
+impl<S: Sinker> Pretty<S> {
+    pub(crate) fn new(inner: S, width: usize) -> Self {
+        Pretty {
+            inner,
+            width,
+            depth: 0,
+            in_string: false,
+            escaped: false,
+            opened: false,
+        }
+    }
+
+    fn newline(&mut self) -> io::Result<()> {
+        let indent = self.depth * self.width;
+        self.inner
+            .write_all(format!("\n{:indent$}", "", indent = indent).as_bytes())
+    }
+}
+
+impl<S: Sinker> MiniWrite for Pretty<S> {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        let mut start = 0;
+        for (pos, &b) in buf.iter().enumerate() {
+            if self.in_string {
+                if self.escaped {
+                    self.escaped = false;
+                } else if b'\\' == b {
+                    self.escaped = true;
+                } else if b'"' == b {
+                    self.in_string = false;
+                }
+                continue;
+            }
+            if self.opened {
+                self.opened = false;
+                if b'}' != b && b']' != b {
+                    self.inner.write_all(&buf[start..pos])?;
+                    start = pos;
+                    self.newline()?;
+                } else {
+                    self.depth -= 1;
+                    continue;
+                }
+            }
+            match b {
+                b'"' => self.in_string = true,
+                b'{' | b'[' => {
+                    self.depth += 1;
+                    self.opened = true;
+                }
+                b'}' | b']' => {
+                    self.inner.write_all(&buf[start..pos])?;
+                    start = pos;
+                    // the lax parser lets a stray closer through, which has nothing to close
+                    self.depth = self.depth.saturating_sub(1);
+                    self.newline()?;
+                }
+                b',' => {
+                    self.inner.write_all(&buf[start..=pos])?;
+                    start = pos + 1;
+                    self.newline()?;
+                }
+                b':' => {
+                    self.inner.write_all(&buf[start..=pos])?;
+                    start = pos + 1;
+                    self.inner.write_all(b" ")?;
+                }
+                _ => (),
+            }
+        }
+        self.inner.write_all(&buf[start..])
+    }
+}
+
+impl<S: Sinker> Sinker for Pretty<S> {
+    fn observe_new_item_with(&mut self, path: &[Vec<u8>], header: &Header) -> io::Result<()> {
+        self.inner.observe_new_item_with(path, header)
+    }
+
+    fn observe_end_with(&mut self, header: &Header) -> io::Result<()> {
+        self.depth = 0;
+        self.in_string = false;
+        self.escaped = false;
+        self.opened = false;
+        self.inner.observe_end_with(header)
+    }
+
+    forward_hooks!();
+}
+
+impl<S: Sinker> Forward for Pretty<S> {
+    type Inner = S;
+
+    fn inner(&mut self) -> &mut S {
+        &mut self.inner
+    }
+}
@@ -0,0 +1,105 @@
+use std::io;
+
+use crate::sink::{forward_hooks, Forward};
+use crate::{Header, MiniWrite, Sinker};
+
+/// Hold each document until it is finished, so it can be dropped if the input turns out
+/// to be invalid part way through it, for [crate::Config::continue_on_error], or so the
+/// documents inside it can be produced first, for [crate::Config::extra_targets].
+///
+/// If not `enabled`, everything is passed straight through.
+pub(crate) struct Recovering<S> {
+    inner: S,
+    enabled: bool,
+    /// The documents which have been started, outermost first, and spares after the
+    /// `open` ones, to be reused.
+    held: Vec<Held>,
+    open: usize,
+}
+
+/// A document which has been started, but not finished.
+#[derive(Default)]
+struct Held {
+    /// The path of the document, if [Sinker::observe_new_item_with] was called.
+    path: Option<Vec<Vec<u8>>>,
+    value: Vec<u8>,
+}
+
+impl<S: Sinker> Recovering<S> {
+    pub(crate) fn new(inner: S, enabled: bool) -> Self {
+        Recovering {
+            inner,
+            enabled,
+            held: Vec::new(),
+            open: 0,
+        }
+    }
+
+    /// Forget the current documents, which will never be finished.
+    pub(crate) fn discard(&mut self) {
+        self.open = 0;
+    }
+
+    fn start(&mut self, path: Option<Vec<Vec<u8>>>) {
+        if self.held.len() == self.open {
+            self.held.push(Held::default());
+        }
+        let held = &mut self.held[self.open];
+        held.path = path;
+        held.value.clear();
+        self.open += 1;
+    }
+}
+
+impl<S: Sinker> MiniWrite for Recovering<S> {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        if !self.enabled {
+            return self.inner.write_all(buf);
+        }
+        if 0 == self.open {
+            self.start(None);
+        }
+        // the outer documents contain the inner ones
+        for held in &mut self.held[..self.open] {
+            held.value.extend_from_slice(buf);
+        }
+        Ok(())
+    }
+}
+
+impl<S: Sinker> Sinker for Recovering<S> {
+    fn observe_new_item_with(&mut self, path: &[Vec<u8>], header: &Header) -> io::Result<()> {
+        if !self.enabled {
+            return self.inner.observe_new_item_with(path, header);
+        }
+        self.start(Some(path.to_vec()));
+        Ok(())
+    }
+
+    fn observe_end_with(&mut self, header: &Header) -> io::Result<()> {
+        if !self.enabled {
+            return self.inner.observe_end_with(header);
+        }
+        if 0 == self.open {
+            self.start(None);
+        }
+        self.open -= 1;
+        let held = &mut self.held[self.open];
+        // the header at the end has everything it had at the start
+        if let Some(path) = held.path.take() {
+            self.inner.observe_new_item_with(&path, header)?;
+        }
+        self.inner.write_all(&held.value)?;
+        self.inner.observe_end_with(header)
+    }
+
+    forward_hooks!();
+}
+
+impl<S: Sinker> Forward for Recovering<S> {
+    type Inner = S;
+
+    fn inner(&mut self) -> &mut S {
+        &mut self.inner
+    }
+}
@@ -0,0 +1,80 @@
+//! Writing documents in formats other than JSON, behind the `yaml` and `toml` features.
+
+use std::io::{self, Write};
+
+use crate::{Header, MiniWrite, Sinker};
+
+/// The format to write documents in, for [Reencode].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Format {
+    /// A YAML stream, with each document introduced by `---`.
+    #[cfg(feature = "yaml")]
+    Yaml,
+
+    /// TOML, which can only represent objects, and has no `null`. Anything else is an error.
+    ///
+    /// TOML has no way to separate documents, so each is introduced by a `# ---` comment.
+    #[cfg(feature = "toml")]
+    Toml,
+}
+
+/// Write each document to a `Write` in another [Format], instead of as JSON.
+///
+/// Each document is buffered, then parsed with `serde_json`, then written out.
+/// Any header is dropped; object keys stay in their original order.
+pub struct Reencode<W> {
+    format: Format,
+    inner: W,
+    value: Vec<u8>,
+}
+
+impl<W: Write> Reencode<W> {
+    /// Write each document to `inner` in `format`, as it is finished.
+    pub fn new(format: Format, inner: W) -> Self {
+        Reencode {
+            format,
+            inner,
+            value: Vec::with_capacity(1024),
+        }
+    }
+}
+
+impl<W> MiniWrite for Reencode<W> {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.value.extend_from_slice(buf);
+        Ok(())
+    }
+}
+
+impl<W: Write> Sinker for Reencode<W> {
+    fn observe_new_item_with(&mut self, _: &[Vec<u8>], _: &Header) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn observe_end_with(&mut self, _: &Header) -> io::Result<()> {
+        let value: serde_json::Value = serde_json::from_slice(&self.value)?;
+        self.value.clear();
+        match self.format {
+            #[cfg(feature = "yaml")]
+            Format::Yaml => {
+                self.inner.write_all(b"---\n")?;
+                serde_yaml::to_writer(&mut self.inner, &value).map_err(invalid)?;
+            }
+            #[cfg(feature = "toml")]
+            Format::Toml => {
+                if !value.is_object() {
+                    return Err(invalid("TOML documents must be objects"));
+                }
+                let text = toml::to_string(&value).map_err(invalid)?;
+                self.inner.write_all(b"# ---\n")?;
+                self.inner.write_all(text.as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn invalid(e: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
@@ -0,0 +1,73 @@
+use std::io;
+
+use crate::sink::{forward_hooks, Forward};
+use crate::{Header, MiniWrite, Sinker};
+
+/// Write the record separator before every document but the first, instead of after
+/// every document, for [crate::Config::trailing_separator].
+pub(crate) struct Separated<'c, S> {
+    inner: S,
+    separator: &'c [u8],
+    /// Whether a document has been started.
+    any: bool,
+    /// Whether the current document has been started.
+    started: bool,
+}
+
+impl<'c, S: Sinker> Separated<'c, S> {
+    pub(crate) fn new(inner: S, separator: &'c [u8]) -> Self {
+        Separated {
+            inner,
+            separator,
+            any: false,
+            started: false,
+        }
+    }
+
+    /// Called before anything is written for a document, which may not have a header.
+    fn start(&mut self) -> io::Result<()> {
+        if self.started {
+            return Ok(());
+        }
+        if self.any {
+            self.inner.write_all(self.separator)?;
+        }
+        self.any = true;
+        self.started = true;
+        Ok(())
+    }
+}
+
+impl<S: Sinker> MiniWrite for Separated<'_, S> {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.start()?;
+        self.inner.write_all(buf)
+    }
+}
+
+impl<S: Sinker> Sinker for Separated<'_, S> {
+    fn observe_new_item_with(&mut self, path: &[Vec<u8>], header: &Header) -> io::Result<()> {
+        self.start()?;
+        self.inner.observe_new_item_with(path, header)
+    }
+
+    fn observe_end_with(&mut self, header: &Header) -> io::Result<()> {
+        self.start()?;
+        self.started = false;
+        let header = Header {
+            record_separator: b"",
+            ..header.clone()
+        };
+        self.inner.observe_end_with(&header)
+    }
+
+    forward_hooks!();
+}
+
+impl<S: Sinker> Forward for Separated<'_, S> {
+    type Inner = S;
+
+    fn inner(&mut self) -> &mut S {
+        &mut self.inner
+    }
+}
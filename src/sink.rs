@@ -12,40 +12,497 @@ impl<T: Write> MiniWrite for T {
     }
 }
 
+/// The context of an individual document, passed to the [Sinker].
+#[derive(Clone)]
+#[non_exhaustive]
+pub struct Header<'a> {
+    /// How much context was requested. See [HeaderStyle].
+    pub style: HeaderStyle,
+
+    /// The text of the line comment directly above the document, without the `//`,
+    /// if [crate::Config::attach_comments] is enabled.
+    pub comment: Option<&'a [u8]>,
+
+    /// The 1-based line of the input on which the document started,
+    /// for [HeaderStyle::WithLine].
+    pub line: Option<u64>,
+
+    /// The string between path segments, for [HeaderStyle::Dotted].
+    pub separator: &'a [u8],
+
+    /// The name of the field holding the path, for [HeaderStyle::PathArray].
+    /// See [crate::Config::key_field].
+    pub key_field: &'a [u8],
+
+    /// The name of the field holding the document. See [crate::Config::value_field].
+    pub value_field: &'a [u8],
+
+    /// The bytes written after the document. See [crate::Config::record_separator].
+    pub record_separator: &'a [u8],
+
+    /// Whether each document is preceded by `\x1e`. See [crate::Config::json_seq].
+    pub json_seq: bool,
+
+    /// Where the document is, and what it is, whatever the [HeaderStyle].
+    pub item: ItemMeta,
+
+    /// The 0-based index of the document, among those produced, whatever the [HeaderStyle],
+    /// e.g. to correlate records in a custom [Sinker]. The default implementation ignores it.
+    pub index: u64,
+
+    /// The 0-based index of the document, for [HeaderStyle::Sequence].
+    pub sequence: Option<u64>,
+
+    /// How many digits to pad [Header::sequence] to. See [crate::Config::sequence_width].
+    pub sequence_width: usize,
+
+    /// A hash of the document, as hex. See [crate::Config::fingerprint].
+    pub fingerprint: Option<&'a [u8]>,
+
+    /// The offset of the first byte of the document, for [HeaderStyle::ByteRange].
+    pub start: Option<u64>,
+
+    /// The offset after the last byte of the document, for [HeaderStyle::ByteRange].
+    /// This is only available in [Sinker::observe_end_with].
+    pub end: Option<u64>,
+
+    /// Which target depth produced the document, if there are [crate::Config::extra_targets].
+    pub target: Option<usize>,
+}
+
+/// Where a document is, and what it is, for [Header::item].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub struct ItemMeta {
+    /// How many objects and arrays the document is inside. This is the target depth,
+    /// unless the document is a scalar which was found before reaching it.
+    pub depth: usize,
+
+    /// Whether the document is an object, an array or a scalar.
+    pub kind: ItemKind,
+}
+
+/// What sort of value a document is, for [ItemMeta::kind].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ItemKind {
+    /// `{..}`
+    Object,
+    /// `[..]`
+    Array,
+    /// A string, number, `true`, `false` or `null`.
+    Scalar,
+}
+
+impl Header<'_> {
+    /// All that the default [Sinker::observe_new_item] and [Sinker::observe_end] know: the
+    /// style, with the default field names and separators.
+    fn from_style(style: HeaderStyle) -> Header<'static> {
+        Header {
+            style,
+            comment: None,
+            line: None,
+            separator: b".",
+            key_field: b"key",
+            value_field: b"value",
+            record_separator: b"\n",
+            json_seq: false,
+            item: ItemMeta {
+                depth: 0,
+                kind: ItemKind::Scalar,
+            },
+            index: 0,
+            sequence: None,
+            sequence_width: 0,
+            fingerprint: None,
+            start: None,
+            end: None,
+            target: None,
+        }
+    }
+
+    /// Write what the default implementation writes for a `Write` before the document, e.g.
+    /// from a [Sinker::observe_new_item_with] which is not a `Write`.
+    pub fn write_start<W: MiniWrite + ?Sized>(
+        &self,
+        into: &mut W,
+        path: &[Vec<u8>],
+    ) -> io::Result<()> {
+        write_start(into, path, self)
+    }
+
+    /// Write what the default implementation writes for a `Write` after the document,
+    /// including the record separator.
+    pub fn write_end<W: MiniWrite + ?Sized>(&self, into: &mut W) -> io::Result<()> {
+        write_end(into, self)
+    }
+
+    /// Whether the default implementation wraps the document in an object.
+    fn wrapped(&self) -> bool {
+        self.style != HeaderStyle::None
+            || self.comment.is_some()
+            || self.fingerprint.is_some()
+            || self.target.is_some()
+    }
+}
+
 /// Consume the individual JSON documents.
 ///
 /// For each document the following will be called, in this order:
-///  * `observe_new_item`, with the path if it was computed
+///  * `observe_new_item_with`, with the path if it was computed
 ///  * `write_all` will be called repeatedly with the contents of the item
-///  * `observe_end`, when the item is finished
+///  * `observe_end_with`, when the item is finished
+///
+/// By default, these call `observe_new_item` and `observe_end`, with only the [HeaderStyle].
 ///
 /// The default implementation is to produce a stream of ndjson on an existing `Write` impl.
 pub trait Sinker: MiniWrite {
-    /// Called when a new item is started.
+    /// Called when a new item is started, by the default [Sinker::observe_new_item_with].
     ///
-    /// `path` will be empty if it is not being computed.
+    /// `path` will be empty if it is not being computed. The default implementation writes
+    /// the header, but only knows the style, so anything else in the [Header], such as
+    /// [crate::Config::key_field], is left at its default. A `Write` gets the whole header;
+    /// other sinks can call [Header::write_start] from [Sinker::observe_new_item_with].
     fn observe_new_item(&mut self, path: &[Vec<u8>], header_style: HeaderStyle) -> io::Result<()> {
-        if header_style == HeaderStyle::None {
-            return Ok(());
-        }
-        self.write_all(br#"{"key":["#)?;
-        for (pos, path_segment) in path.iter().enumerate() {
-            self.write_all(path_segment)?;
-            if pos != path.len() - 1 {
-                self.write_all(b",")?;
+        write_start(self, path, &Header::from_style(header_style))
+    }
+
+    /// Called when an item is finished, by the default [Sinker::observe_end_with].
+    fn observe_end(&mut self, header_style: HeaderStyle) -> io::Result<()> {
+        write_end(self, &Header::from_style(header_style))
+    }
+
+    /// Called when a new item is started, with everything known about it.
+    ///
+    /// `path` will be empty if it is not being computed.
+    fn observe_new_item_with(&mut self, path: &[Vec<u8>], header: &Header) -> io::Result<()> {
+        self.observe_new_item(path, header.style)
+    }
+
+    /// Called when an item is finished, with everything known about it, including
+    /// [Header::end].
+    fn observe_end_with(&mut self, header: &Header) -> io::Result<()> {
+        self.observe_end(header.style)
+    }
+
+    /// Called instead of the above, for a document which is not produced, as its
+    /// top-level value has reached [crate::Config::max_documents_per_top_level].
+    ///
+    /// `path` will be empty if it is not being computed.
+    fn observe_capped(&mut self, path: &[Vec<u8>]) -> io::Result<()> {
+        let _ = path;
+        Ok(())
+    }
+
+    /// Called before each string value in a document is written, with a guess at what it
+    /// holds, when [crate::Config::classify_strings] is enabled. Keys are not classified.
+    ///
+    /// `path` is the path to the document, if it is being computed, not to the string.
+    fn observe_string_class(
+        &mut self,
+        path: &[Vec<u8>],
+        class: crate::StringClass,
+    ) -> io::Result<()> {
+        let _ = (path, class);
+        Ok(())
+    }
+
+    /// Called with how many bytes of the input have been consumed, after a document, when
+    /// [crate::Config::progress_every] is enabled, and once at the end of the input.
+    ///
+    /// The offset is the same as [HeaderStyle::ByteRange]'s, so it is the end of the
+    /// document, not including any whitespace after it. See [crate::sinks::WithProgress].
+    fn observe_progress(&mut self, offset: u64) -> io::Result<()> {
+        let _ = offset;
+        Ok(())
+    }
+
+    /// Called as each top-level value of the input starts, before any documents in it, with
+    /// whether it is an object, an array, or a scalar, e.g. to decide how to label them.
+    fn observe_top_level(&mut self, kind: ItemKind) -> io::Result<()> {
+        let _ = kind;
+        Ok(())
+    }
+
+    /// Called after [Sinker::observe_end_with], with how many bytes the default implementation
+    /// produced for the document, including its header and the record separator, e.g. to
+    /// frame each document as an HTTP chunk.
+    ///
+    /// This is the length after [crate::Config::pretty], but it is not called at all with
+    /// [crate::Config::wrap_width] or [crate::Config::group_by], which don't produce
+    /// individual documents.
+    fn observe_document_len(&mut self, len: usize) -> io::Result<()> {
+        let _ = len;
+        Ok(())
+    }
+
+    /// Called once, after everything has been produced, e.g. to flush a buffer. Not called
+    /// if the input was invalid. For a `Write`, this is [Write::flush].
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Called between documents, if the input is slow, when [crate::Config::heartbeat]
+    /// is enabled. For a `Write`, this writes the `record` as a line; otherwise, it is
+    /// ignored, as writing it with [MiniWrite::write_all] would put it inside a document.
+    fn observe_heartbeat(&mut self, record: &[u8]) -> io::Result<()> {
+        let _ = record;
+        Ok(())
+    }
+}
+
+impl<T: Write> Sinker for T {
+    fn observe_new_item_with(&mut self, path: &[Vec<u8>], header: &Header) -> io::Result<()> {
+        write_start(self, path, header)
+    }
+
+    fn observe_end_with(&mut self, header: &Header) -> io::Result<()> {
+        write_end(self, header)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Write::flush(self)
+    }
+
+    fn observe_heartbeat(&mut self, record: &[u8]) -> io::Result<()> {
+        Write::write_all(self, record)?;
+        Write::write_all(self, b"\n")
+    }
+}
+
+/// A sink which wraps another, and passes on every hook it doesn't handle itself.
+///
+/// Its `impl Sinker` implements [Sinker::observe_new_item_with] and [Sinker::observe_end_with],
+/// and calls [forward_hooks] for everything else, so a new hook only needs adding here.
+pub(crate) trait Forward {
+    type Inner: Sinker;
+
+    fn inner(&mut self) -> &mut Self::Inner;
+
+    fn observe_capped(&mut self, path: &[Vec<u8>]) -> io::Result<()> {
+        self.inner().observe_capped(path)
+    }
+
+    fn observe_string_class(
+        &mut self,
+        path: &[Vec<u8>],
+        class: crate::StringClass,
+    ) -> io::Result<()> {
+        self.inner().observe_string_class(path, class)
+    }
+
+    fn observe_progress(&mut self, offset: u64) -> io::Result<()> {
+        self.inner().observe_progress(offset)
+    }
+
+    fn observe_top_level(&mut self, kind: ItemKind) -> io::Result<()> {
+        self.inner().observe_top_level(kind)
+    }
+
+    fn observe_document_len(&mut self, len: usize) -> io::Result<()> {
+        self.inner().observe_document_len(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner().flush()
+    }
+
+    fn observe_heartbeat(&mut self, record: &[u8]) -> io::Result<()> {
+        self.inner().observe_heartbeat(record)
+    }
+}
+
+/// Implement the rest of [Sinker], inside its `impl`, with the type's [Forward].
+macro_rules! forward_hooks {
+    () => {
+        fn observe_capped(&mut self, path: &[Vec<u8>]) -> std::io::Result<()> {
+            $crate::sink::Forward::observe_capped(self, path)
+        }
+
+        fn observe_string_class(
+            &mut self,
+            path: &[Vec<u8>],
+            class: $crate::StringClass,
+        ) -> std::io::Result<()> {
+            $crate::sink::Forward::observe_string_class(self, path, class)
+        }
+
+        fn observe_progress(&mut self, offset: u64) -> std::io::Result<()> {
+            $crate::sink::Forward::observe_progress(self, offset)
+        }
+
+        fn observe_top_level(&mut self, kind: $crate::ItemKind) -> std::io::Result<()> {
+            $crate::sink::Forward::observe_top_level(self, kind)
+        }
+
+        fn observe_document_len(&mut self, len: usize) -> std::io::Result<()> {
+            $crate::sink::Forward::observe_document_len(self, len)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            $crate::sink::Forward::flush(self)
+        }
+
+        fn observe_heartbeat(&mut self, record: &[u8]) -> std::io::Result<()> {
+            $crate::sink::Forward::observe_heartbeat(self, record)
+        }
+    };
+}
+
+pub(crate) use forward_hooks;
+
+/// The default [Sinker::observe_new_item_with] for a `Write`, for sinks which wrap it.
+pub(crate) fn write_start<W: MiniWrite + ?Sized>(
+    into: &mut W,
+    path: &[Vec<u8>],
+    header: &Header,
+) -> io::Result<()> {
+    if header.json_seq {
+        into.write_all(b"\x1e")?;
+    }
+    if !header.wrapped() {
+        return Ok(());
+    }
+    into.write_all(b"{")?;
+    if let Some(comment) = header.comment {
+        into.write_all(br#""comment":"#)?;
+        write_json_string(into, comment)?;
+        into.write_all(b",")?;
+    }
+    if let Some(line) = header.line {
+        into.write_all(format!(r#""line":{},"#, line).as_bytes())?;
+    }
+    if let Some(sequence) = header.sequence {
+        let sequence = match header.sequence_width {
+            0 => format!(r#""seq":{},"#, sequence),
+            width => format!(r#""seq":"{:0width$}","#, sequence, width = width),
+        };
+        into.write_all(sequence.as_bytes())?;
+    }
+    if let Some(start) = header.start {
+        into.write_all(format!(r#""start":{},"#, start).as_bytes())?;
+    }
+    if let Some(target) = header.target {
+        into.write_all(format!(r#""target":{},"#, target).as_bytes())?;
+    }
+    if let Some(fingerprint) = header.fingerprint {
+        into.write_all(br#""fingerprint":"#)?;
+        write_json_string(into, fingerprint)?;
+        into.write_all(b",")?;
+    }
+    match header.style {
+        HeaderStyle::PathArray => {
+            write_json_string(into, header.key_field)?;
+            into.write_all(b":[")?;
+            for (pos, path_segment) in path.iter().enumerate() {
+                into.write_all(path_segment)?;
+                if pos != path.len() - 1 {
+                    into.write_all(b",")?;
+                }
             }
+            into.write_all(b"],")?;
         }
-        self.write_all(br#"],"value":"#)?;
-        Ok(())
+        HeaderStyle::Dotted => {
+            into.write_all(br#""path":""#)?;
+            for (pos, path_segment) in path.iter().enumerate() {
+                if 0 != pos {
+                    write_json_string_content(into, header.separator)?;
+                }
+                write_dotted_segment(into, path_segment, header.separator)?;
+            }
+            into.write_all(br#"","#)?;
+        }
+        HeaderStyle::LeafKey => {
+            write_json_string(into, header.key_field)?;
+            into.write_all(b":")?;
+            match path.last() {
+                // keys are already strings
+                Some(key) if key.starts_with(b"\"") => into.write_all(key)?,
+                Some(index) => write_json_string(into, index)?,
+                None => into.write_all(b"null")?,
+            }
+            into.write_all(b",")?;
+        }
+        HeaderStyle::None
+        | HeaderStyle::WithLine
+        | HeaderStyle::ByteRange
+        | HeaderStyle::Sequence => (),
     }
+    write_json_string(into, header.value_field)?;
+    into.write_all(b":")?;
+    Ok(())
+}
 
-    /// Called when an item is finished.
-    fn observe_end(&mut self, header_style: HeaderStyle) -> io::Result<()> {
-        match header_style {
-            HeaderStyle::None => self.write_all(b"\n"),
-            HeaderStyle::PathArray => self.write_all(b"}\n"),
+/// The default [Sinker::observe_end_with] for a `Write`, for sinks which wrap it.
+pub(crate) fn write_end<W: MiniWrite + ?Sized>(into: &mut W, header: &Header) -> io::Result<()> {
+    if let Some(end) = header.end {
+        into.write_all(format!(r#","end":{}}}"#, end).as_bytes())?;
+    } else if header.wrapped() {
+        into.write_all(b"}")?;
+    }
+    into.write_all(header.record_separator)
+}
+
+/// Write arbitrary bytes as a JSON string, escaping only what is necessary.
+fn write_json_string<W: MiniWrite + ?Sized>(into: &mut W, bytes: &[u8]) -> io::Result<()> {
+    into.write_all(b"\"")?;
+    write_json_string_content(into, bytes)?;
+    into.write_all(b"\"")
+}
+
+/// [write_json_string], without the quotes.
+pub(crate) fn write_json_string_content<W: MiniWrite + ?Sized>(
+    into: &mut W,
+    bytes: &[u8],
+) -> io::Result<()> {
+    let mut start = 0;
+    for (pos, &b) in bytes.iter().enumerate() {
+        if b'"' != b && b'\\' != b && !b.is_ascii_control() {
+            continue;
+        }
+        into.write_all(&bytes[start..pos])?;
+        start = pos + 1;
+        match b {
+            b'"' | b'\\' => into.write_all(&[b'\\', b])?,
+            b'\t' => into.write_all(br"\t")?,
+            _ => into.write_all(format!("\\u{:04x}", b).as_bytes())?,
         }
     }
+    into.write_all(&bytes[start..])
 }
 
-impl<T: Write> Sinker for T {}
+/// Write a path segment, a JSON string or a number, into a [HeaderStyle::Dotted] string,
+/// escaping the separator and backslashes.
+fn write_dotted_segment<W: MiniWrite + ?Sized>(
+    into: &mut W,
+    segment: &[u8],
+    separator: &[u8],
+) -> io::Result<()> {
+    let content = match segment {
+        [b'"', content @ .., b'"'] => content,
+        number => return into.write_all(number),
+    };
+    let mut start = 0;
+    let mut pos = 0;
+    while pos < content.len() {
+        if b'\\' == content[pos] {
+            if Some(&b'\\') == content.get(pos + 1) {
+                into.write_all(&content[start..pos])?;
+                into.write_all(br"\\\\")?;
+                start = pos + 2;
+            }
+            // other escapes are copied through
+            pos += 2;
+            continue;
+        }
+        if !separator.is_empty() && content[pos..].starts_with(separator) {
+            into.write_all(&content[start..pos])?;
+            into.write_all(br"\\")?;
+            write_json_string_content(into, separator)?;
+            pos += separator.len();
+            start = pos;
+            continue;
+        }
+        pos += 1;
+    }
+    into.write_all(&content[start..])
+}
@@ -0,0 +1,699 @@
+//! Ready-made [Sinker] implementations, for common ways of consuming documents.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+use crate::field::find_field;
+use crate::sink::{forward_hooks, Forward};
+use crate::unescape::unescape;
+use crate::{Header, ItemKind, MiniWrite, Sinker};
+
+#[cfg(feature = "gzip")]
+pub use crate::gzip::GzipRecords;
+#[cfg(feature = "rayon")]
+pub use crate::parallel::ParallelSink;
+
+/// Compute a key from the path and value of a document, for [Key::With].
+pub type KeyFn = Box<dyn FnMut(&[Vec<u8>], &[u8]) -> Vec<u8>>;
+
+/// How [KeyedSink] derives the key for each document.
+pub enum Key {
+    /// The path, as a JSON array, as in [crate::HeaderStyle::PathArray].
+    ///
+    /// The path is only available if the header style computes it.
+    Path,
+
+    /// The value of this top-level field of the document, as JSON, e.g. `"x"` or `5`.
+    ///
+    /// The key is empty if the document is not an object, or does not have the field.
+    Field(Vec<u8>),
+
+    /// Call a function with the path and value of the document.
+    With(KeyFn),
+}
+
+impl Key {
+    fn compute(&mut self, path: &[Vec<u8>], value: &[u8]) -> Vec<u8> {
+        match self {
+            Key::Path => {
+                let mut key = b"[".to_vec();
+                key.extend_from_slice(&path.join(&b","[..]));
+                key.push(b']');
+                key
+            }
+            Key::Field(name) => find_field(value, name)
+                .map(|v| v.to_vec())
+                .unwrap_or_default(),
+            Key::With(f) => f(path, value),
+        }
+    }
+}
+
+/// Produce each document as a `(key, value)` pair, like a record for Kafka.
+///
+/// Each document is buffered, then its key is computed, according to the [Key],
+/// and both are passed to the callback. The value is the document without any header.
+pub struct KeyedSink<F> {
+    key: Key,
+    emit: F,
+    path: Vec<Vec<u8>>,
+    value: Vec<u8>,
+}
+
+impl<F: FnMut(Vec<u8>, Vec<u8>) -> io::Result<()>> KeyedSink<F> {
+    /// Call `emit` with the key and value of each document.
+    pub fn new(key: Key, emit: F) -> Self {
+        KeyedSink {
+            key,
+            emit,
+            path: Vec::new(),
+            value: Vec::with_capacity(1024),
+        }
+    }
+}
+
+impl<F> MiniWrite for KeyedSink<F> {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.value.extend_from_slice(buf);
+        Ok(())
+    }
+}
+
+impl<F: FnMut(Vec<u8>, Vec<u8>) -> io::Result<()>> Sinker for KeyedSink<F> {
+    fn observe_new_item_with(&mut self, path: &[Vec<u8>], _: &Header) -> io::Result<()> {
+        self.path.clear();
+        self.path.extend_from_slice(path);
+        Ok(())
+    }
+
+    fn observe_end_with(&mut self, _: &Header) -> io::Result<()> {
+        let key = self.key.compute(&self.path, &self.value);
+        let value = std::mem::replace(&mut self.value, Vec::with_capacity(1024));
+        (self.emit)(key, value)
+    }
+}
+
+/// Call a function with the path and value of each document, as in [fn_sink].
+pub struct FnSink<F> {
+    f: F,
+    path: Vec<Vec<u8>>,
+    value: Vec<u8>,
+}
+
+/// Call `f` once for each document, with its path and its whole value, without any header.
+///
+/// The path is only available if the header style computes it, as for [Key::Path].
+///
+/// ```
+/// use unnest_ndjson::sinks::fn_sink;
+/// use unnest_ndjson::{unnest_to_ndjson_with, Config};
+///
+/// let mut count = 0;
+/// unnest_to_ndjson_with(&b"[1, 2, 3]"[..], fn_sink(|_, _| count += 1), &Config::new(1))?;
+/// assert_eq!(3, count);
+/// # Ok::<(), unnest_ndjson::UnnestError>(())
+/// ```
+pub fn fn_sink<F: FnMut(&[Vec<u8>], &[u8])>(f: F) -> FnSink<F> {
+    FnSink {
+        f,
+        path: Vec::new(),
+        value: Vec::with_capacity(1024),
+    }
+}
+
+impl<F> MiniWrite for FnSink<F> {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.value.extend_from_slice(buf);
+        Ok(())
+    }
+}
+
+impl<F: FnMut(&[Vec<u8>], &[u8])> Sinker for FnSink<F> {
+    fn observe_new_item_with(&mut self, path: &[Vec<u8>], _: &Header) -> io::Result<()> {
+        self.path.clear();
+        self.path.extend_from_slice(path);
+        Ok(())
+    }
+
+    fn observe_end_with(&mut self, _: &Header) -> io::Result<()> {
+        (self.f)(&self.path, &self.value);
+        self.value.clear();
+        Ok(())
+    }
+}
+
+/// A document, as collected by [CollectSink]: its path, and its value, without any header.
+pub type Collected = (Vec<Vec<u8>>, Vec<u8>);
+
+/// Keep every document in memory, as its path and its value, e.g. for small inputs, or tests.
+/// See [crate::unnest_collect].
+///
+/// The path is only available if the header style computes it, as for [Key::Path]. Pass a
+/// `&mut CollectSink` as the sink, then call [CollectSink::into_inner] for the documents.
+#[derive(Default)]
+pub struct CollectSink {
+    documents: Vec<Collected>,
+    path: Vec<Vec<u8>>,
+    value: Vec<u8>,
+}
+
+impl CollectSink {
+    /// Start with no documents.
+    pub fn new() -> Self {
+        CollectSink::default()
+    }
+
+    /// The documents, in the order they were produced.
+    pub fn into_inner(self) -> Vec<Collected> {
+        self.documents
+    }
+}
+
+impl MiniWrite for &mut CollectSink {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.value.extend_from_slice(buf);
+        Ok(())
+    }
+}
+
+impl Sinker for &mut CollectSink {
+    fn observe_new_item_with(&mut self, path: &[Vec<u8>], _: &Header) -> io::Result<()> {
+        self.path = path.to_vec();
+        Ok(())
+    }
+
+    fn observe_end_with(&mut self, _: &Header) -> io::Result<()> {
+        let path = std::mem::take(&mut self.path);
+        let value = std::mem::take(&mut self.value);
+        self.documents.push((path, value));
+        Ok(())
+    }
+}
+
+/// Call a function with the documents in batches, e.g. to insert each batch into a database
+/// in a single transaction.
+///
+/// Each document is its value, without any header. A batch holds `batch_size` documents,
+/// except the last, which is passed on by [Sinker::flush], at the end of the input, and may
+/// be smaller. A batch is never empty.
+///
+/// ```
+/// use unnest_ndjson::sinks::BatchSink;
+/// use unnest_ndjson::{unnest_to_ndjson_with, Config};
+///
+/// let mut sizes = Vec::new();
+/// let sink = BatchSink::new(2, |batch| Ok(sizes.push(batch.len())));
+/// unnest_to_ndjson_with(&b"[1, 2, 3]"[..], sink, &Config::new(1))?;
+/// assert_eq!(vec![2, 1], sizes);
+/// # Ok::<(), unnest_ndjson::UnnestError>(())
+/// ```
+pub struct BatchSink<F> {
+    f: F,
+    batch_size: usize,
+    batch: Vec<Vec<u8>>,
+    value: Vec<u8>,
+}
+
+impl<F: FnMut(&[Vec<u8>]) -> io::Result<()>> BatchSink<F> {
+    /// Pass `batch_size` documents at a time to `f`, which must be at least one.
+    pub fn new(batch_size: usize, f: F) -> Self {
+        assert!(batch_size > 0, "a batch must have a document");
+        BatchSink {
+            f,
+            batch_size,
+            batch: Vec::with_capacity(batch_size),
+            value: Vec::with_capacity(1024),
+        }
+    }
+
+    fn emit(&mut self) -> io::Result<()> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        (self.f)(&self.batch)?;
+        self.batch.clear();
+        Ok(())
+    }
+}
+
+impl<F> MiniWrite for BatchSink<F> {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.value.extend_from_slice(buf);
+        Ok(())
+    }
+}
+
+impl<F: FnMut(&[Vec<u8>]) -> io::Result<()>> Sinker for BatchSink<F> {
+    fn observe_new_item_with(&mut self, _: &[Vec<u8>], _: &Header) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn observe_end_with(&mut self, _: &Header) -> io::Result<()> {
+        let value = std::mem::replace(&mut self.value, Vec::with_capacity(1024));
+        self.batch.push(value);
+        if self.batch.len() >= self.batch_size {
+            self.emit()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.emit()
+    }
+}
+
+/// Pass everything through to another sink, and call a function with each
+/// [Sinker::observe_progress], e.g. to draw a progress bar against the size of the input.
+///
+/// ```
+/// use unnest_ndjson::sinks::WithProgress;
+/// use unnest_ndjson::{unnest_to_ndjson_with, Config};
+///
+/// let mut offsets = Vec::new();
+/// let mut out = Vec::new();
+/// let sink = WithProgress::new(&mut out, |offset| offsets.push(offset));
+/// unnest_to_ndjson_with(&b"[1, 2] "[..], sink, &Config::new(1).progress_every(Some(0)))?;
+/// assert_eq!(vec![2, 5, 7], offsets);
+/// # Ok::<(), unnest_ndjson::UnnestError>(())
+/// ```
+pub struct WithProgress<S, F> {
+    inner: S,
+    f: F,
+}
+
+impl<S: Sinker, F: FnMut(u64)> WithProgress<S, F> {
+    /// Pass everything to `inner`, and each offset to `f`.
+    pub fn new(inner: S, f: F) -> Self {
+        WithProgress { inner, f }
+    }
+}
+
+impl<S: Sinker, F> MiniWrite for WithProgress<S, F> {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.inner.write_all(buf)
+    }
+}
+
+impl<S: Sinker, F: FnMut(u64)> Sinker for WithProgress<S, F> {
+    fn observe_new_item_with(&mut self, path: &[Vec<u8>], header: &Header) -> io::Result<()> {
+        self.inner.observe_new_item_with(path, header)
+    }
+
+    fn observe_end_with(&mut self, header: &Header) -> io::Result<()> {
+        self.inner.observe_end_with(header)
+    }
+
+    forward_hooks!();
+}
+
+impl<S: Sinker, F: FnMut(u64)> Forward for WithProgress<S, F> {
+    type Inner = S;
+
+    fn inner(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    fn observe_progress(&mut self, offset: u64) -> io::Result<()> {
+        (self.f)(offset);
+        self.inner.observe_progress(offset)
+    }
+}
+
+/// Pass everything through to two sinks, e.g. to write the output to a file, and to
+/// index it, at the same time.
+///
+/// Each call goes to `first`, then to `second`, stopping at the first error.
+///
+/// ```
+/// use unnest_ndjson::sinks::{fn_sink, TeeSink};
+/// use unnest_ndjson::{unnest_to_ndjson_with, Config};
+///
+/// let mut out = Vec::new();
+/// let mut count = 0;
+/// let sink = TeeSink::new(&mut out, fn_sink(|_, _| count += 1));
+/// unnest_to_ndjson_with(&b"[1, 2]"[..], sink, &Config::new(1))?;
+/// assert_eq!(&b"1\n2\n"[..], &out[..]);
+/// assert_eq!(2, count);
+/// # Ok::<(), unnest_ndjson::UnnestError>(())
+/// ```
+pub struct TeeSink<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A: Sinker, B: Sinker> TeeSink<A, B> {
+    /// Pass everything to `first`, then to `second`.
+    pub fn new(first: A, second: B) -> Self {
+        TeeSink { first, second }
+    }
+
+    /// The two sinks, in the order they were given.
+    pub fn into_inner(self) -> (A, B) {
+        (self.first, self.second)
+    }
+}
+
+impl<A: Sinker, B: Sinker> MiniWrite for TeeSink<A, B> {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.first.write_all(buf)?;
+        self.second.write_all(buf)
+    }
+}
+
+impl<A: Sinker, B: Sinker> Sinker for TeeSink<A, B> {
+    fn observe_new_item_with(&mut self, path: &[Vec<u8>], header: &Header) -> io::Result<()> {
+        self.first.observe_new_item_with(path, header)?;
+        self.second.observe_new_item_with(path, header)
+    }
+
+    fn observe_end_with(&mut self, header: &Header) -> io::Result<()> {
+        self.first.observe_end_with(header)?;
+        self.second.observe_end_with(header)
+    }
+
+    fn observe_capped(&mut self, path: &[Vec<u8>]) -> io::Result<()> {
+        self.first.observe_capped(path)?;
+        self.second.observe_capped(path)
+    }
+
+    fn observe_string_class(
+        &mut self,
+        path: &[Vec<u8>],
+        class: crate::StringClass,
+    ) -> io::Result<()> {
+        self.first.observe_string_class(path, class)?;
+        self.second.observe_string_class(path, class)
+    }
+
+    fn observe_progress(&mut self, offset: u64) -> io::Result<()> {
+        self.first.observe_progress(offset)?;
+        self.second.observe_progress(offset)
+    }
+
+    fn observe_top_level(&mut self, kind: ItemKind) -> io::Result<()> {
+        self.first.observe_top_level(kind)?;
+        self.second.observe_top_level(kind)
+    }
+
+    fn observe_document_len(&mut self, len: usize) -> io::Result<()> {
+        self.first.observe_document_len(len)?;
+        self.second.observe_document_len(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.first.flush()?;
+        self.second.flush()
+    }
+
+    fn observe_heartbeat(&mut self, record: &[u8]) -> io::Result<()> {
+        self.first.observe_heartbeat(record)?;
+        self.second.observe_heartbeat(record)
+    }
+}
+
+/// Split the documents between files in a directory, `shard-0.ndjson` to `shard-{n-1}.ndjson`,
+/// by a hash of their [Key], so documents with the same key are in the same file.
+///
+/// Each line is a document, without any header. Files are only created, replacing any
+/// existing file, when a document is written to them. Pass a `&mut Sharded` as the sink,
+/// then call [Sharded::finish] to flush the files and learn of any errors.
+pub struct Sharded {
+    dir: PathBuf,
+    key: Key,
+    files: Vec<Option<BufWriter<File>>>,
+    path: Vec<Vec<u8>>,
+    value: Vec<u8>,
+}
+
+impl Sharded {
+    /// Use `shards` files in `dir`, which must exist. `shards` must be at least one.
+    pub fn new(dir: impl Into<PathBuf>, shards: usize, key: Key) -> Self {
+        assert!(shards > 0, "at least one shard is required");
+        Sharded {
+            dir: dir.into(),
+            key,
+            files: (0..shards).map(|_| None).collect(),
+            path: Vec::new(),
+            value: Vec::with_capacity(1024),
+        }
+    }
+
+    /// Flush all of the files which were written to.
+    pub fn finish(self) -> io::Result<()> {
+        for file in self.files.into_iter().flatten() {
+            let _file = file.into_inner().map_err(|e| e.into_error())?;
+        }
+        Ok(())
+    }
+}
+
+impl MiniWrite for &mut Sharded {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.value.extend_from_slice(buf);
+        Ok(())
+    }
+}
+
+impl Sinker for &mut Sharded {
+    fn observe_new_item_with(&mut self, path: &[Vec<u8>], _: &Header) -> io::Result<()> {
+        self.path.clear();
+        self.path.extend_from_slice(path);
+        Ok(())
+    }
+
+    fn observe_end_with(&mut self, _: &Header) -> io::Result<()> {
+        let key = self.key.compute(&self.path, &self.value);
+        let shard = (fnv1a(&key) % self.files.len() as u64) as usize;
+        let file = match &mut self.files[shard] {
+            Some(file) => file,
+            slot @ None => {
+                let name = self.dir.join(format!("shard-{}.ndjson", shard));
+                slot.insert(BufWriter::new(File::create(name)?))
+            }
+        };
+        self.value.push(b'\n');
+        Write::write_all(file, &self.value)?;
+        self.value.clear();
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        for file in self.files.iter_mut().flatten() {
+            Write::flush(file)?;
+        }
+        Ok(())
+    }
+}
+
+/// Write each document, as a line, to a data file, and a fixed-width entry for it to an index,
+/// e.g. to sort the documents by their [Key] without reading them all into memory.
+///
+/// Each entry is 20 bytes: a hash of the key, the offset of the document in the data, and
+/// its length, without the newline, as a little-endian `u64`, `u64` and `u32`. Pass a
+/// `&mut IndexedDataSink` as the sink, then call [IndexedDataSink::finish] to flush both.
+pub struct IndexedDataSink<D: Write, I: Write> {
+    data: BufWriter<D>,
+    index: BufWriter<I>,
+    key: Key,
+    offset: u64,
+    path: Vec<Vec<u8>>,
+    value: Vec<u8>,
+}
+
+impl<D: Write, I: Write> IndexedDataSink<D, I> {
+    /// Write the documents to `data`, and an entry for each, with the hash of its `key`,
+    /// to `index`. Both are buffered.
+    pub fn new(data: D, index: I, key: Key) -> Self {
+        IndexedDataSink {
+            data: BufWriter::new(data),
+            index: BufWriter::new(index),
+            key,
+            offset: 0,
+            path: Vec::new(),
+            value: Vec::with_capacity(1024),
+        }
+    }
+
+    /// Flush both writers, and return them.
+    pub fn finish(self) -> io::Result<(D, I)> {
+        Ok((
+            self.data.into_inner().map_err(|e| e.into_error())?,
+            self.index.into_inner().map_err(|e| e.into_error())?,
+        ))
+    }
+}
+
+impl<D: Write, I: Write> MiniWrite for &mut IndexedDataSink<D, I> {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.value.extend_from_slice(buf);
+        Ok(())
+    }
+}
+
+impl<D: Write, I: Write> Sinker for &mut IndexedDataSink<D, I> {
+    fn observe_new_item_with(&mut self, path: &[Vec<u8>], _: &Header) -> io::Result<()> {
+        self.path.clear();
+        self.path.extend_from_slice(path);
+        Ok(())
+    }
+
+    fn observe_end_with(&mut self, _: &Header) -> io::Result<()> {
+        let len = u32::try_from(self.value.len()).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidData, "document too long to index")
+        })?;
+        let key = self.key.compute(&self.path, &self.value);
+        let mut entry = [0u8; 20];
+        entry[..8].copy_from_slice(&fnv1a(&key).to_le_bytes());
+        entry[8..16].copy_from_slice(&self.offset.to_le_bytes());
+        entry[16..].copy_from_slice(&len.to_le_bytes());
+        Write::write_all(&mut self.index, &entry)?;
+
+        self.value.push(b'\n');
+        Write::write_all(&mut self.data, &self.value)?;
+        self.offset += self.value.len() as u64;
+        self.value.clear();
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Write::flush(&mut self.data)?;
+        Write::flush(&mut self.index)
+    }
+}
+
+/// What [CsvSink] does with a document which is an object or an array.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CsvNested {
+    /// Leave it out.
+    Skip,
+    /// Write it as compact JSON, in the `value` cell.
+    Json,
+}
+
+/// Write each document as a row of CSV, with its path and its value, after a `path,value`
+/// header row, e.g. to load flat scalars into a spreadsheet.
+///
+/// The path is its keys and indexes joined with `.`, with escapes in keys decoded, so the
+/// header style must compute it, as for [Key::Path]. A string value is its text, and other
+/// scalars are as they were written. Cells are quoted as necessary, and rows end with
+/// `\r\n`. Pass a `&mut CsvSink` as the sink, then call [CsvSink::finish] to flush it.
+///
+/// ```
+/// use unnest_ndjson::sinks::{CsvNested, CsvSink};
+/// use unnest_ndjson::{unnest_to_ndjson_with, Config, HeaderStyle};
+///
+/// let mut csv = CsvSink::new(Vec::new(), CsvNested::Skip);
+/// let config = Config::new(1).header_style(HeaderStyle::PathArray);
+/// unnest_to_ndjson_with(&br#"{"a": "x,y", "b": 2}"#[..], &mut csv, &config)?;
+/// assert_eq!(&b"path,value\r\na,\"x,y\"\r\nb,2\r\n"[..], &csv.finish()?[..]);
+/// # Ok::<(), unnest_ndjson::UnnestError>(())
+/// ```
+pub struct CsvSink<W: Write> {
+    out: BufWriter<W>,
+    nested: CsvNested,
+    started: bool,
+    path: Vec<Vec<u8>>,
+    value: Vec<u8>,
+}
+
+impl<W: Write> CsvSink<W> {
+    /// Write the rows to `out`, which is buffered, handling objects and arrays as `nested`.
+    pub fn new(out: W, nested: CsvNested) -> Self {
+        CsvSink {
+            out: BufWriter::new(out),
+            nested,
+            started: false,
+            path: Vec::new(),
+            value: Vec::with_capacity(1024),
+        }
+    }
+
+    /// Flush the writer, and return it. The header row is written even if there were no
+    /// documents.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.start()?;
+        self.out.into_inner().map_err(|e| e.into_error())
+    }
+
+    fn start(&mut self) -> io::Result<()> {
+        if !self.started {
+            self.started = true;
+            Write::write_all(&mut self.out, b"path,value\r\n")?;
+        }
+        Ok(())
+    }
+}
+
+/// Write a cell, in quotes if it contains anything which would otherwise end it.
+fn write_csv_cell(out: &mut impl Write, cell: &[u8]) -> io::Result<()> {
+    if !cell
+        .iter()
+        .any(|b| matches!(b, b',' | b'"' | b'\r' | b'\n'))
+    {
+        return Write::write_all(out, cell);
+    }
+    Write::write_all(out, b"\"")?;
+    for part in cell.split_inclusive(|&b| b'"' == b) {
+        Write::write_all(out, part)?;
+        if part.ends_with(b"\"") {
+            Write::write_all(out, b"\"")?;
+        }
+    }
+    Write::write_all(out, b"\"")
+}
+
+impl<W: Write> MiniWrite for &mut CsvSink<W> {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.value.extend_from_slice(buf);
+        Ok(())
+    }
+}
+
+impl<W: Write> Sinker for &mut CsvSink<W> {
+    fn observe_new_item_with(&mut self, path: &[Vec<u8>], _: &Header) -> io::Result<()> {
+        self.path.clear();
+        self.path.extend_from_slice(path);
+        Ok(())
+    }
+
+    fn observe_end_with(&mut self, _: &Header) -> io::Result<()> {
+        let nested = matches!(self.value.first(), Some(b'{' | b'['));
+        if nested && CsvNested::Skip == self.nested {
+            self.value.clear();
+            return Ok(());
+        }
+        self.start()?;
+        let mut path = Vec::new();
+        for (pos, segment) in self.path.iter().enumerate() {
+            if 0 != pos {
+                path.push(b'.');
+            }
+            match &segment[..] {
+                [b'"', key @ .., b'"'] => path.extend_from_slice(&unescape(key)),
+                index => path.extend_from_slice(index),
+            }
+        }
+        write_csv_cell(&mut self.out, &path)?;
+        Write::write_all(&mut self.out, b",")?;
+        match &self.value[..] {
+            [b'"', text @ .., b'"'] => write_csv_cell(&mut self.out, &unescape(text))?,
+            value => write_csv_cell(&mut self.out, value)?,
+        }
+        Write::write_all(&mut self.out, b"\r\n")?;
+        self.value.clear();
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Write::flush(&mut self.out)
+    }
+}
+
+/// A hash which, unlike the standard library's, will not change between releases,
+/// so the same key goes to the same shard.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
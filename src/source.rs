@@ -1,45 +1,251 @@
 use std::io;
 use std::io::Read;
+use std::time::{Duration, Instant};
 
 use iowrap::ReadMany as _;
+use memchr::{memchr_iter, memrchr};
+
+use crate::error::Position;
+
+/// The buffer size, unless [crate::Config::buffer_size] says otherwise.
+pub const DEFAULT_CAPACITY: usize = 16 * 1024;
 
 /// A more aggressive BufReader with some utility methods.
 pub struct Source<R: Read> {
     inner: R,
-    buf: [u8; 16 * 1024],
+    buf: Box<[u8]>,
     len: usize,
     pos: usize,
+    /// Bytes which are no longer in the buffer.
+    discarded: u64,
+    /// Newlines in data which is no longer in the buffer.
+    lines: u64,
+    /// The offset of the start of the line containing the start of the buffer.
+    line_start: u64,
+    /// How much of the buffer has been included in `counted_lines`, for [Source::line].
+    counted: usize,
+    counted_lines: u64,
+    /// How long [Source::fill] waits for a full buffer, if there is some data.
+    patience: Option<Duration>,
+    /// Whether the reader has run out, for [Source::at_eof].
+    eof: bool,
 }
 
 impl<R: Read> Source<R> {
-    pub fn new(inner: R) -> Self {
+    /// Read up to `capacity` bytes at a time, which must be at least one.
+    pub fn with_capacity(inner: R, capacity: usize) -> Self {
+        assert!(capacity > 0, "a buffer is required");
         Source {
             inner,
-            buf: [0u8; 16 * 1024],
+            buf: vec![0u8; capacity].into_boxed_slice(),
             len: 0,
             pos: 0,
+            discarded: 0,
+            lines: 0,
+            line_start: 0,
+            counted: 0,
+            counted_lines: 0,
+            patience: None,
+            eof: false,
         }
     }
 
+    /// Start with `prefix` in the buffer, as though it had been read from `inner`, for input
+    /// which has already been partly read, e.g. to detect its format. The buffer grows to
+    /// hold all of `prefix`, if it is larger than `capacity`.
+    pub fn with_initial_buffer(prefix: &[u8], inner: R, capacity: usize) -> Self {
+        let mut source = Source::with_capacity(inner, capacity.max(prefix.len()));
+        source.buf[..prefix.len()].copy_from_slice(prefix);
+        source.len = prefix.len();
+        source
+    }
+
+    /// Treat the first byte of the input as being at `position`, e.g. to continue counting
+    /// from the end of some earlier input.
+    pub fn starting_at(mut self, position: Position) -> Self {
+        self.discarded = position.offset;
+        self.lines = position.line - 1;
+        self.line_start = position.offset - (position.column - 1);
+        self
+    }
+
+    /// Stop filling the buffer once some data has arrived and this much time has passed,
+    /// instead of waiting for the buffer to be full, which may take forever for slow input.
+    pub fn patience(mut self, patience: Option<Duration>) -> Self {
+        self.patience = patience;
+        self
+    }
+
+    /// The 1-based line number of the next byte.
+    ///
+    /// This is cheap to call repeatedly, as newlines are only counted once.
+    pub fn line(&mut self) -> u64 {
+        let end = self.pos.max(self.counted);
+        self.counted_lines += count_newlines(&self.buf[self.counted..end]);
+        self.counted = end;
+        1 + self.lines + self.counted_lines
+    }
+
+    /// Where the byte at `idx` in the buffer is in the whole input.
+    fn position_at(&self, idx: usize) -> Position {
+        let before = &self.buf[..idx];
+        let offset = self.discarded + idx as u64;
+        let line_start = match memrchr(b'\n', before) {
+            Some(newline) => self.discarded + newline as u64 + 1,
+            None => self.line_start,
+        };
+        Position {
+            offset,
+            line: 1 + self.lines + count_newlines(before),
+            column: 1 + offset - line_start,
+        }
+    }
+
+    /// The position of the most recently consumed byte, for reporting errors.
+    pub fn last_position(&self) -> Position {
+        self.position_at(self.pos.saturating_sub(1))
+    }
+
+    /// The most recently consumed byte, if it is still in the buffer.
+    pub fn previous(&self) -> Option<u8> {
+        self.pos.checked_sub(1).map(|idx| self.buf[idx])
+    }
+
+    /// The offset of the next byte. This is cheaper than [Source::position].
+    pub fn offset(&self) -> u64 {
+        self.discarded + self.pos as u64
+    }
+
+    /// How many bytes have been read from the reader, whether or not they are consumed.
+    pub fn bytes_read(&self) -> u64 {
+        self.discarded + self.len as u64
+    }
+
+    /// The bytes which have been read from the reader, but not consumed, e.g. to hand back
+    /// when stopping early.
+    pub fn remaining(&self) -> &[u8] {
+        self.buf()
+    }
+
+    /// The position of the next byte, i.e. the end of the input, if it has been reached.
+    pub fn position(&self) -> Position {
+        self.position_at(self.pos)
+    }
+
+    /// Note the lines in the buffer, before it is discarded.
+    fn discard(&mut self) {
+        let buf = &self.buf[..self.len];
+        self.lines += count_newlines(buf);
+        if let Some(newline) = memrchr(b'\n', buf) {
+            self.line_start = self.discarded + newline as u64 + 1;
+        }
+        self.discarded += self.len as u64;
+        self.pos = 0;
+        self.len = 0;
+        self.counted = 0;
+        self.counted_lines = 0;
+    }
+
     /// Attempt to read as much as possible into the buffer.
     ///
     /// If the buffer contains fully read data, discard it and fill the entire buffer again.
     ///
-    /// Unlike BufReader, this will not give up the first time `read()` returns.
+    /// Unlike BufReader, this will not give up the first time `read()` returns,
+    /// unless it has run out of [Source::patience].
     pub fn fill(&mut self) -> io::Result<()> {
         if self.pos == self.len {
-            self.pos = 0;
-            self.len = 0;
+            self.discard();
         }
-        let free = &mut self.buf[self.len..];
-        let found = self.inner.read_many(free)?;
+        let found = match self.patience {
+            Some(patience) => self.read_patiently(patience)?,
+            None => self.inner.read_many(&mut self.buf[self.len..])?,
+        };
         if 0 == found && 0 == self.len {
+            self.eof = true;
             return Err(io::ErrorKind::UnexpectedEof.into());
         }
         self.len += found;
         Ok(())
     }
 
+    /// Like `read_many`, but checking the time between each `read()`.
+    fn read_patiently(&mut self, patience: Duration) -> io::Result<usize> {
+        let start = Instant::now();
+        let mut found = 0;
+        while self.len + found < self.buf.len() {
+            match self.inner.read(&mut self.buf[self.len + found..]) {
+                Ok(0) => break,
+                Ok(n) => found += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+            if start.elapsed() >= patience {
+                break;
+            }
+        }
+        Ok(found)
+    }
+
+    /// Whether the input has been entirely consumed, reading more if necessary.
+    ///
+    /// Errors from the reader, including its own `UnexpectedEof`, are returned as-is.
+    pub fn at_eof(&mut self) -> io::Result<bool> {
+        if self.pos < self.len {
+            return Ok(false);
+        }
+        match self.fill() {
+            Ok(()) => Ok(false),
+            Err(_) if self.eof => Ok(true),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether the next bytes are `prefix`, reading more if necessary, without consuming
+    /// them. `false` if `prefix` is longer than the buffer.
+    pub fn starts_with(&mut self, prefix: &[u8]) -> io::Result<bool> {
+        while self.len - self.pos < prefix.len() {
+            if !prefix.starts_with(self.buf()) || prefix.len() > self.buf.len() || self.eof {
+                return Ok(false);
+            }
+            self.keep_unconsumed();
+            let before = self.len;
+            match self.fill() {
+                Ok(()) if self.len > before => (),
+                Ok(()) => return Ok(false),
+                Err(_) if self.eof => return Ok(false),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(self.buf().starts_with(prefix))
+    }
+
+    /// Discard only the consumed part of the buffer, moving the rest to the start.
+    fn keep_unconsumed(&mut self) {
+        let consumed = &self.buf[..self.pos];
+        self.lines += count_newlines(consumed);
+        if let Some(newline) = memrchr(b'\n', consumed) {
+            self.line_start = self.discarded + newline as u64 + 1;
+        }
+        self.discarded += self.pos as u64;
+        self.buf.copy_within(self.pos..self.len, 0);
+        self.len -= self.pos;
+        self.pos = 0;
+        self.counted = 0;
+        self.counted_lines = 0;
+    }
+
+    /// Go back to the start of the line containing the next byte, e.g. to try the rest of
+    /// a line again. `false` if the start of the line is no longer in the buffer.
+    pub fn rewind_to_line_start(&mut self) -> bool {
+        // the bytes we go back over have no newlines, so the counted lines are still right
+        match memrchr(b'\n', &self.buf[..self.pos]) {
+            Some(newline) => self.pos = newline + 1,
+            None if self.line_start == self.discarded => self.pos = 0,
+            None => return false,
+        }
+        true
+    }
+
     /// Access the valid portion of the buffer
     #[inline]
     pub fn buf(&self) -> &[u8] {
@@ -57,8 +263,7 @@ impl<R: Read> Source<R> {
     /// This is more efficient than consume (although probably irrelevant in practice!).
     #[inline]
     pub fn all_useless(&mut self) {
-        self.pos = 0;
-        self.len = 0;
+        self.discard();
     }
 
     #[inline]
@@ -83,3 +288,7 @@ impl<R: Read> Source<R> {
         }
     }
 }
+
+fn count_newlines(buf: &[u8]) -> u64 {
+    memchr_iter(b'\n', buf).count() as u64
+}
@@ -0,0 +1,27 @@
+use crate::UnnestError;
+
+/// What happened during [crate::unnest_to_ndjson_stats].
+#[derive(Debug, Default)]
+#[non_exhaustive]
+pub struct UnnestStats {
+    /// The number of documents produced.
+    pub documents: u64,
+
+    /// The number of bytes read from the input, including any [UnnestStats::remaining].
+    pub bytes_read: u64,
+
+    /// The most objects and arrays which were open at once, anywhere in the input.
+    /// `0` if the input only contained scalars.
+    pub max_depth_seen: usize,
+
+    /// The problems with the input which were skipped, for [crate::Config::continue_on_error].
+    /// See [UnnestError::position] for where each was noticed.
+    pub errors: Vec<UnnestError>,
+
+    /// If the input was not read to the end, because of [crate::Config::max_documents],
+    /// the bytes which had been read from it, but not yet parsed.
+    ///
+    /// These come before anything still available from the reader. `None` if the input
+    /// was read to the end, as it always is unless [crate::Config::max_documents] is reached.
+    pub remaining: Option<Vec<u8>>,
+}
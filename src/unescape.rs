@@ -0,0 +1,72 @@
+//! Decoding JSON strings to their text, for [crate::Config::raw_strings].
+
+/// The text of a string, given its content between the quotes, as written by `parse_string`,
+/// so every escape is valid.
+///
+/// A `\u` escape of an unpaired surrogate, which has no UTF-8 encoding, becomes U+FFFD.
+pub(crate) fn unescape(string: &[u8]) -> Vec<u8> {
+    let mut text = Vec::with_capacity(string.len());
+    let mut rest = string;
+    while let Some(escape) = rest.iter().position(|&b| b'\\' == b) {
+        text.extend_from_slice(&rest[..escape]);
+        let (decoded, len) = match rest[escape + 1] {
+            b'b' => ('\x08', 2),
+            b'f' => ('\x0c', 2),
+            b'n' => ('\n', 2),
+            b'r' => ('\r', 2),
+            b't' => ('\t', 2),
+            b'u' => unicode(&rest[escape..]),
+            // `"`, `\` and `/`
+            other => (char::from(other), 2),
+        };
+        let mut buf = [0u8; 4];
+        text.extend_from_slice(decoded.encode_utf8(&mut buf).as_bytes());
+        rest = &rest[escape + len..];
+    }
+    text.extend_from_slice(rest);
+    text
+}
+
+/// The character for the `\uXXXX` escape at the start of `escape`, or for the pair of them
+/// if it is a high surrogate followed by a low one, and how many bytes were used.
+fn unicode(escape: &[u8]) -> (char, usize) {
+    let high = hex(&escape[2..6]);
+    if let (0xD800..=0xDBFF, Some(b"\\u")) = (high, escape.get(6..8)) {
+        let low = hex(&escape[8..12]);
+        if let 0xDC00..=0xDFFF = low {
+            let code = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+            return (char::from_u32(code).expect("a valid pair"), 12);
+        }
+    }
+    (
+        char::from_u32(high).unwrap_or(char::REPLACEMENT_CHARACTER),
+        6,
+    )
+}
+
+fn hex(digits: &[u8]) -> u32 {
+    digits.iter().fold(0, |code, &h| {
+        code << 4 | (h as char).to_digit(16).expect("checked by parse_string")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::unescape;
+
+    fn text(string: &str) -> String {
+        String::from_utf8(unescape(string.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!("plain", text("plain"));
+        assert_eq!("a\nb\t\"c\\/", text(r#"a\nb\t\"c\\\/"#));
+        assert_eq!("\x08\x0c\r", text(r"\b\f\r"));
+        assert_eq!("AB", text(r"\u0041\u0042"));
+        assert_eq!("r\u{eb}r", text(r"r\u00ebr"));
+        assert_eq!("\u{1f600}", text(r"\ud83d\ude00"));
+        assert_eq!("\u{fffd}x", text(r"\ud83dx"));
+        assert_eq!("\u{fffd}\u{fffd}", text(r"\ude00\ud83d"));
+    }
+}
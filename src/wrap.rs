@@ -0,0 +1,107 @@
+use std::io;
+
+use crate::sink::{forward_hooks, write_end, write_start, Forward};
+use crate::{Header, MiniWrite, Sinker};
+
+/// Break long lines of the default formatting for display, for [crate::Config::wrap_width].
+///
+/// The current line is held until it is finished, or is too long, so it can be broken
+/// after the last `,`, `{` or `[` which was not in a string.
+pub(crate) struct Wrapped<S> {
+    inner: S,
+    width: usize,
+    line: Vec<u8>,
+    /// Where the current line could be broken, after a separator.
+    safe: Option<usize>,
+    in_string: bool,
+    escaped: bool,
+}
+
+impl<S: Sinker> Wrapped<S> {
+    pub(crate) fn new(inner: S, width: usize) -> Self {
+        Wrapped {
+            inner,
+            width,
+            line: Vec::with_capacity(width + 1),
+            safe: None,
+            in_string: false,
+            escaped: false,
+        }
+    }
+
+    /// Write the line up to `end`, and a newline, keeping the rest.
+    fn break_at(&mut self, end: usize) -> io::Result<()> {
+        self.inner.write_all(&self.line[..end])?;
+        self.inner.write_all(b"\n")?;
+        let _ = self.line.drain(..end);
+        self.safe = None;
+        Ok(())
+    }
+}
+
+impl<S: Sinker> MiniWrite for Wrapped<S> {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        for &b in buf {
+            self.line.push(b);
+            if self.line.len() > self.width && b'\n' != b {
+                if let Some(safe) = self.safe {
+                    self.break_at(safe)?;
+                }
+            }
+            if self.in_string {
+                if self.escaped {
+                    self.escaped = false;
+                } else if b'\\' == b {
+                    self.escaped = true;
+                } else if b'"' == b {
+                    self.in_string = false;
+                }
+                continue;
+            }
+            match b {
+                b'"' => self.in_string = true,
+                b',' | b'{' | b'[' => self.safe = Some(self.line.len()),
+                b'\n' => {
+                    self.inner.write_all(&self.line)?;
+                    self.line.clear();
+                    self.safe = None;
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Everything else is written through the default formatting, and so is wrapped.
+impl<S: Sinker> Sinker for Wrapped<S> {
+    fn observe_new_item_with(&mut self, path: &[Vec<u8>], header: &Header) -> io::Result<()> {
+        write_start(self, path, header)
+    }
+
+    fn observe_end_with(&mut self, header: &Header) -> io::Result<()> {
+        write_end(self, header)
+    }
+
+    forward_hooks!();
+}
+
+impl<S: Sinker> Forward for Wrapped<S> {
+    type Inner = S;
+
+    fn inner(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Write anything left over, from an unfinished line.
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.write_all(&self.line)?;
+        self.line.clear();
+        self.inner.flush()
+    }
+
+    fn observe_heartbeat(&mut self, record: &[u8]) -> io::Result<()> {
+        self.write_all(record)?;
+        self.write_all(b"\n")
+    }
+}
@@ -0,0 +1,119 @@
+#![cfg(feature = "async")]
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use unnest_ndjson::{unnest_to_ndjson_async, Config, HeaderStyle, UnnestError};
+
+/// Produce one byte per successful read, and make every other read wait.
+struct Trickle {
+    data: Vec<u8>,
+    pos: usize,
+    ready: bool,
+}
+
+impl Trickle {
+    fn new(data: &[u8]) -> Self {
+        Trickle {
+            data: data.to_vec(),
+            pos: 0,
+            ready: false,
+        }
+    }
+}
+
+impl AsyncRead for Trickle {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if !self.ready {
+            self.ready = true;
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        self.ready = false;
+        if let Some(&b) = self.data.get(self.pos) {
+            buf.put_slice(&[b]);
+            self.pos += 1;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Fail every write.
+struct Broken;
+
+impl AsyncWrite for Broken {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        _buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(Err(io::Error::other("disk full")))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[tokio::test]
+async fn one_byte_at_a_time() -> Result<(), UnnestError> {
+    let input =
+        br#"{"a": "a long string, with \"escapes\"", "b": [12345.678e-9, true], "c": null}"#;
+    let mut out = Vec::new();
+    unnest_to_ndjson_async(
+        Trickle::new(input),
+        &mut out,
+        &Config::new(1).header_style(HeaderStyle::PathArray),
+    )
+    .await?;
+    assert_eq!(
+        concat!(
+            r#"{"key":["a"],"value":"a long string, with \"escapes\""}"#,
+            "\n",
+            r#"{"key":["b"],"value":[12345.678e-9,true]}"#,
+            "\n",
+            r#"{"key":["c"],"value":null}"#,
+            "\n",
+        ),
+        String::from_utf8(out).unwrap()
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn large_output() -> Result<(), UnnestError> {
+    let input = format!("[{}0]", "[1,2,3],".repeat(100_000));
+    let mut out = Vec::new();
+    unnest_to_ndjson_async(input.as_bytes(), &mut out, &Config::new(1)).await?;
+    assert_eq!(100_001, out.iter().filter(|&&b| b'\n' == b).count());
+    Ok(())
+}
+
+#[tokio::test]
+async fn errors() {
+    let mut out = Vec::new();
+    match unnest_to_ndjson_async(Trickle::new(b"[1, 2"), &mut out, &Config::new(1)).await {
+        Err(UnnestError::Truncated { .. }) => (),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn broken_output() {
+    // far more than the chunks in flight, so the parser would block if nobody was reading
+    let input = format!("[{}0]", "[1,2,3],".repeat(100_000));
+    match unnest_to_ndjson_async(input.as_bytes(), Broken, &Config::new(1)).await {
+        Err(UnnestError::Io(e)) => assert_eq!("disk full", e.to_string()),
+        other => panic!("{:?}", other),
+    }
+}
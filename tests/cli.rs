@@ -0,0 +1,188 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn unnest(args: &[&str], env: &[(&str, &str)], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_unnest-ndjson"))
+        .args(args)
+        .env_remove("UNNEST_TARGET")
+        .env_remove("UNNEST_HEADER_STYLE")
+        .env_remove("UNNEST_BUFFER_SIZE")
+        .envs(env.iter().copied())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("spawn");
+    // the process may exit, for bad arguments, before reading its input
+    let _ = child.stdin.take().expect("piped").write_all(input);
+    child.wait_with_output().expect("wait")
+}
+
+#[test]
+fn target_from_env() {
+    let input = br#"{"a": {"b": 1, "c": [2]}}"#;
+    let out = unnest(&[], &[("UNNEST_TARGET", "2")], input);
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!("1\n[2]\n", String::from_utf8_lossy(&out.stdout));
+
+    // the argument wins
+    let out = unnest(&["1"], &[("UNNEST_TARGET", "2")], input);
+    assert_eq!(
+        "{\"b\":1,\"c\":[2]}\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = unnest(&[], &[], input);
+    assert_eq!(Some(5), out.status.code());
+}
+
+#[test]
+fn header_style_and_buffer_size_from_env() {
+    let out = unnest(
+        &[],
+        &[
+            ("UNNEST_TARGET", "1"),
+            ("UNNEST_HEADER_STYLE", "dotted"),
+            ("UNNEST_BUFFER_SIZE", "3"),
+        ],
+        br#"{"a": 1, "b": "xyz"}"#,
+    );
+    assert_eq!(
+        "{\"path\":\"a\",\"value\":1}\n{\"path\":\"b\",\"value\":\"xyz\"}\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = unnest(
+        &["--dump-config"],
+        &[("UNNEST_TARGET", "1"), ("UNNEST_BUFFER_SIZE", "3")],
+        b"",
+    );
+    assert!(String::from_utf8_lossy(&out.stdout).contains("buffer_size: 3\n"));
+
+    let out = unnest(&[], &[("UNNEST_HEADER_STYLE", "fancy")], b"");
+    assert_eq!(Some(4), out.status.code());
+}
+
+#[test]
+fn input_file() {
+    let mut file = tempfile::NamedTempFile::new().expect("temp file");
+    file.write_all(br#"[1, {"a": 2}]"#).expect("write");
+    let path = file.path().to_str().expect("utf-8 path");
+
+    let out = unnest(&["--input", path, "1"], &[], b"[3]");
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!("1\n{\"a\":2}\n", String::from_utf8_lossy(&out.stdout));
+
+    let missing = file.path().with_extension("missing");
+    let out = unnest(&["--input", missing.to_str().unwrap(), "1"], &[], b"");
+    assert_eq!(Some(6), out.status.code());
+
+    let out = unnest(&["1", "--input"], &[], b"");
+    assert_eq!(Some(3), out.status.code());
+}
+
+#[test]
+fn output_file() {
+    let dir = tempfile::tempdir().expect("temp dir");
+    let path = dir.path().join("out.ndjson");
+    let path = path.to_str().expect("utf-8 path");
+
+    let out = unnest(&["--output", path, "1"], &[], b"[1, 2]");
+    assert!(out.status.success(), "{:?}", out);
+    assert!(out.stdout.is_empty());
+    assert_eq!("1\n2\n", std::fs::read_to_string(path).unwrap());
+
+    // a problem with the input is not a problem with the output
+    let out = unnest(&["--output", path, "1"], &[], b"[1, 2");
+    assert_eq!(Some(1), out.status.code());
+
+    let out = unnest(
+        &["--output", dir.path().to_str().unwrap(), "1"],
+        &[],
+        b"[1]",
+    );
+    assert_eq!(Some(7), out.status.code());
+}
+
+#[test]
+fn limit() {
+    let out = unnest(&["--limit", "3", "1"], &[], b"[1, 2, 3, 4, 5, 6");
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(b"1\n2\n3\n"[..], out.stdout[..]);
+
+    let out = unnest(
+        &["--skip", "2", "--limit", "3", "1"],
+        &[],
+        b"[1, 2, 3, 4, 5, 6, 7",
+    );
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!(b"3\n4\n5\n"[..], out.stdout[..]);
+
+    let out = unnest(&["--limit", "many", "1"], &[], b"[1]");
+    assert_eq!(Some(3), out.status.code());
+}
+
+#[test]
+fn header_style_flags() {
+    let input = br#"{"a": {"b": 1}}"#;
+    let stdout = |args: &[&str]| {
+        let out = unnest(args, &[], input);
+        assert!(out.status.success(), "{:?}", out);
+        String::from_utf8_lossy(&out.stdout).to_string()
+    };
+    assert_eq!(
+        "{\"key\":[\"a\",\"b\"],\"value\":1}\n",
+        stdout(&["--path", "2"])
+    );
+    assert_eq!(
+        "{\"path\":\"a.b\",\"value\":1}\n",
+        stdout(&["--dotted", "2"])
+    );
+    assert_eq!(
+        "{\"path\":\"a/b\",\"value\":1}\n",
+        stdout(&["--dotted=/", "2"])
+    );
+    assert_eq!(
+        "{\"start\":12,\"value\":1,\"end\":13}\n",
+        stdout(&["--byte-range", "2"])
+    );
+    assert_eq!("{\"seq\":0,\"value\":1}\n", stdout(&["--seq", "2"]));
+    assert_eq!("{\"key\":\"b\",\"value\":1}\n", stdout(&["--leaf", "2"]));
+    assert_eq!("{\"line\":1,\"value\":1}\n", stdout(&["--line", "2"]));
+    assert_eq!(
+        "{\"key\":[\"a\",\"b\"],\"value\":1}\n",
+        stdout(&["--path", "--path", "2"])
+    );
+
+    // the flag wins over the environment
+    let out = unnest(&["--seq", "2"], &[("UNNEST_HEADER_STYLE", "path")], input);
+    assert_eq!(
+        "{\"seq\":0,\"value\":1}\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+
+    let out = unnest(&["--path", "--dotted", "2"], &[], input);
+    assert_eq!(Some(3), out.status.code());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("conflicts"));
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn gzipped_input() {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(br#"{"a": [1, 2]}"#).expect("compress");
+    let compressed = encoder.finish().expect("compress");
+
+    let out = unnest(&["2"], &[], &compressed);
+    assert!(out.status.success(), "{:?}", out);
+    assert_eq!("1\n2\n", String::from_utf8_lossy(&out.stdout));
+
+    let mut file = tempfile::NamedTempFile::new().expect("temp file");
+    file.write_all(&compressed).expect("write");
+    let path = file.path().to_str().expect("utf-8 path");
+    let out = unnest(&["--input", path, "--path", "1"], &[], b"");
+    assert_eq!(
+        "{\"key\":[\"a\"],\"value\":[1,2]}\n",
+        String::from_utf8_lossy(&out.stdout)
+    );
+}
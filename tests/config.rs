@@ -0,0 +1,1577 @@
+use std::io;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use unnest_ndjson::{
+    unnest_to_ndjson_after, unnest_to_ndjson_stats, unnest_to_ndjson_with, Collisions, Config,
+    Fingerprint, Header, HeaderStyle, ItemKind, ItemMeta, MiniWrite, NormalizeKey, NumberRounding,
+    ScalarRoot, Sinker, StringClass, UnnestError,
+};
+
+fn run(input: &[u8], config: &Config) -> Result<String, UnnestError> {
+    let mut out = Vec::with_capacity(input.len());
+    unnest_to_ndjson_with(input, &mut out, config)?;
+    Ok(String::from_utf8(out).expect("valid utf-8"))
+}
+
+#[test]
+fn skeleton() -> Result<(), UnnestError> {
+    assert_eq!(
+        "{\"a\":\"number\",\"b\":\"string\"}\n",
+        run(br#"{"a":1,"b":"x"}"#, &Config::new(0).skeleton(true))?
+    );
+    assert_eq!(
+        "\"boolean\"\n[\"null\",{\"c\":\"number\"}]\n",
+        run(
+            br#"[true, [null, {"c": -2.5}]]"#,
+            &Config::new(1).skeleton(true)
+        )?
+    );
+    Ok(())
+}
+
+#[test]
+fn attach_comments() -> Result<(), UnnestError> {
+    let input = br#"[
+  // note
+  {"a": 1},
+  {"a": 2 // inside
+  }
+]"#;
+    assert_eq!(
+        "{\"comment\":\"note\",\"value\":{\"a\":1}}\n{\"a\":2}\n",
+        run(input, &Config::new(1).attach_comments(true))?
+    );
+    assert_eq!(
+        "{\"comment\":\"note\",\"key\":[0],\"value\":{\"a\":1}}\n{\"key\":[1],\"value\":{\"a\":2}}\n",
+        run(
+            input,
+            &Config::new(1)
+                .attach_comments(true)
+                .header_style(HeaderStyle::PathArray)
+        )?
+    );
+    assert_eq!(
+        io::ErrorKind::InvalidData,
+        io::Error::from(run(input, &Config::new(1)).unwrap_err()).kind()
+    );
+    Ok(())
+}
+
+#[test]
+fn uniform_keys() -> Result<(), UnnestError> {
+    let config = Config::new(1).enforce_uniform_keys(true);
+    assert_eq!(
+        "{\"a\":1,\"b\":2}\n{\"b\":3,\"a\":4}\n5\n",
+        run(br#"[{"a":1,"b":2},{"b":3,"a":4},5]"#, &config)?
+    );
+    match run(br#"[{"a":1,"b":2},{"a":3,"c":{"b":4}}]"#, &config) {
+        Err(UnnestError::InconsistentShape { missing, extra }) => {
+            assert_eq!(vec![br#""b""#.to_vec()], missing);
+            assert_eq!(vec![br#""c""#.to_vec()], extra);
+        }
+        other => panic!("unexpected: {:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+fn max_documents_per_top_level() -> Result<(), UnnestError> {
+    #[derive(Default)]
+    struct Capped {
+        out: Vec<u8>,
+        capped: Vec<Vec<Vec<u8>>>,
+    }
+
+    impl MiniWrite for &mut Capped {
+        fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+            self.out.write_all(buf)
+        }
+    }
+
+    impl Sinker for &mut Capped {
+        fn observe_capped(&mut self, path: &[Vec<u8>]) -> io::Result<()> {
+            self.capped.push(path.to_vec());
+            Ok(())
+        }
+    }
+
+    let mut sink = Capped::default();
+    unnest_to_ndjson_with(
+        &b"[1, 2, 3] [4, 5, [6]]\n[7]"[..],
+        &mut sink,
+        &Config::new(1)
+            .header_style(HeaderStyle::PathArray)
+            .max_documents_per_top_level(2),
+    )?;
+    assert_eq!(
+        concat!(
+            "{\"key\":[0],\"value\":1}\n",
+            "{\"key\":[1],\"value\":2}\n",
+            "{\"key\":[0],\"value\":4}\n",
+            "{\"key\":[1],\"value\":5}\n",
+            "{\"key\":[0],\"value\":7}\n",
+        ),
+        String::from_utf8_lossy(&sink.out)
+    );
+    assert_eq!(vec![vec![b"2".to_vec()], vec![b"2".to_vec()]], sink.capped);
+    Ok(())
+}
+
+#[test]
+fn leaf_key() -> Result<(), UnnestError> {
+    let input = br#"{"doubleObject":{"aye":7,"be":8},"array":[9]}"#;
+    let config = Config::new(2).header_style(HeaderStyle::LeafKey);
+    assert_eq!(
+        concat!(
+            r#"{"key":"aye","value":7}"#,
+            "\n",
+            r#"{"key":"be","value":8}"#,
+            "\n",
+            r#"{"key":"0","value":9}"#,
+            "\n",
+        ),
+        run(input, &config)?
+    );
+    assert_eq!(
+        "{\"key\":null,\"value\":[1]}\n",
+        run(b"[1]", &Config::new(0).header_style(HeaderStyle::LeafKey))?
+    );
+    Ok(())
+}
+
+#[test]
+fn raw_strings() -> Result<(), UnnestError> {
+    let config = Config::new(1).raw_strings(true);
+    assert_eq!("a\nb\nA\n", run(br#"["a\nb","\u0041"]"#, &config)?);
+    assert_eq!(
+        "{\"s\":\"\\u0041\"}\n5\n\u{1f600}\n",
+        run(br#"[{"s":"\u0041"}, 5, "\ud83d\ude00"]"#, &config)?
+    );
+    Ok(())
+}
+
+#[test]
+fn dotted() -> Result<(), UnnestError> {
+    let input = br#"{"x": {"a.b": 1, "c": [2], "d\\e": 3}, "y": [{"z": 4}]}"#;
+    assert_eq!(
+        concat!(
+            r#"{"path":"x.a\\.b","value":1}"#,
+            "\n",
+            r#"{"path":"x.c","value":[2]}"#,
+            "\n",
+            r#"{"path":"x.d\\\\e","value":3}"#,
+            "\n",
+            r#"{"path":"y.0","value":{"z":4}}"#,
+            "\n",
+        ),
+        run(input, &Config::new(2).header_style(HeaderStyle::Dotted))?
+    );
+    assert_eq!(
+        concat!(
+            r#"{"path":"x/a.b","value":1}"#,
+            "\n",
+            r#"{"path":"x/c","value":[2]}"#,
+            "\n",
+            r#"{"path":"x/d\\\\e","value":3}"#,
+            "\n",
+            r#"{"path":"y/0","value":{"z":4}}"#,
+            "\n",
+        ),
+        run(
+            input,
+            &Config::new(2)
+                .header_style(HeaderStyle::Dotted)
+                .path_separator("/")
+        )?
+    );
+    Ok(())
+}
+
+#[test]
+fn exponents() -> Result<(), UnnestError> {
+    let input = b"[1E10, 1e10, 1.5E-3, 1e+2, 7]";
+    assert_eq!(
+        "1E10\n1e10\n1.5E-3\n1e+2\n7\n",
+        run(input, &Config::new(1).validate_numbers(true))?
+    );
+    assert_eq!(
+        "1e10\n1e10\n1.5e-3\n1e+2\n7\n",
+        run(input, &Config::new(1).normalize_numbers(true))?
+    );
+    assert_eq!(
+        "1e10\n1e10\n1.5e-3\n1e+2\n7\n",
+        run(
+            input,
+            &Config::new(1)
+                .validate_numbers(true)
+                .normalize_numbers(true)
+        )?
+    );
+    Ok(())
+}
+
+#[test]
+fn number_precision() -> Result<(), UnnestError> {
+    let input = br#"{"pi": 3.14159, "n": 12345678901234567890, "half": 0.125, "s": "3.14159"}"#;
+    assert_eq!(
+        "{\"pi\":3.14,\"n\":12345678901234567890,\"half\":0.13,\"s\":\"3.14159\"}\n",
+        run(
+            input,
+            &Config::new(0).number_precision(Some(NumberRounding::decimal_places(2)))
+        )?
+    );
+    assert_eq!(
+        "{\"pi\":3.14,\"n\":12345678901234567890,\"half\":0.12,\"s\":\"3.14159\"}\n",
+        run(
+            input,
+            &Config::new(0)
+                .number_precision(Some(NumberRounding::decimal_places(2).half_even(true)))
+        )?
+    );
+    // too large to round, so left alone
+    assert_eq!(
+        "[1.2345e9223372036854775807]\n",
+        run(
+            b"[1.2345e9223372036854775807]",
+            &Config::new(0).number_precision(Some(NumberRounding::decimal_places(2)))
+        )?
+    );
+    Ok(())
+}
+
+#[test]
+fn after_prefix() -> Result<(), UnnestError> {
+    // the prefix is bigger than the buffer, and ends in the middle of a number
+    let config = Config::new(1)
+        .buffer_size(4)
+        .header_style(HeaderStyle::WithLine);
+    let mut out = Vec::new();
+    let stats = unnest_to_ndjson_after(
+        b"{\"a\": [1],\n\"b\": 2",
+        &b"3,\n\"c\": 4}"[..],
+        &mut out,
+        &config,
+    )?;
+    assert_eq!(
+        "{\"line\":1,\"value\":[1]}\n{\"line\":2,\"value\":23}\n{\"line\":3,\"value\":4}\n",
+        String::from_utf8(out).unwrap()
+    );
+    assert_eq!(3, stats.documents);
+    assert_eq!(27, stats.bytes_read);
+    Ok(())
+}
+
+#[test]
+fn max_documents() -> Result<(), UnnestError> {
+    let input = br#"[{"a":1}, {"a":2}, {"a":3}]"#;
+    let mut out = Vec::new();
+    let stats = unnest_to_ndjson_stats(&input[..], &mut out, &Config::new(1).max_documents(2))?;
+    assert_eq!("{\"a\":1}\n{\"a\":2}\n", String::from_utf8(out).unwrap());
+    assert_eq!(2, stats.documents);
+    assert_eq!(Some(&br#", {"a":3}]"#[..]), stats.remaining.as_deref());
+
+    let mut out = Vec::new();
+    let stats = unnest_to_ndjson_stats(&input[..], &mut out, &Config::new(1))?;
+    assert_eq!(3, stats.documents);
+    assert_eq!(None, stats.remaining);
+
+    // never reached, so the input is read to the end
+    let mut out = Vec::new();
+    let stats = unnest_to_ndjson_stats(&input[..], &mut out, &Config::new(1).max_documents(5))?;
+    assert_eq!(3, stats.documents);
+    assert_eq!(None, stats.remaining);
+    Ok(())
+}
+
+#[test]
+fn skip_documents() -> Result<(), UnnestError> {
+    let docs = (0..10)
+        .map(|i| format!(r#"{{"n":[{}]}}"#, i))
+        .collect::<Vec<_>>();
+    let input = format!("[{}]", docs.join(", "));
+    let mut out = Vec::new();
+    let config = Config::new(1).skip_documents(2).max_documents(3);
+    let stats = unnest_to_ndjson_stats(input.as_bytes(), &mut out, &config)?;
+    assert_eq!(
+        "{\"n\":[2]}\n{\"n\":[3]}\n{\"n\":[4]}\n",
+        String::from_utf8(out).unwrap()
+    );
+    assert_eq!(3, stats.documents);
+
+    let config = Config::new(1)
+        .header_style(HeaderStyle::PathArray)
+        .skip_documents(8);
+    assert_eq!(
+        "{\"key\":[8],\"value\":{\"n\":[8]}}\n{\"key\":[9],\"value\":{\"n\":[9]}}\n",
+        run(input.as_bytes(), &config)?
+    );
+    Ok(())
+}
+
+#[test]
+fn stats() -> Result<(), UnnestError> {
+    let input = br#"{"a": [1, {"b": [[]]}], "c": 2} [3] 4"#;
+    for config in [
+        Config::new(1),
+        Config::new(1).buffer_size(3),
+        Config::new(1).path_prefix([r#""c""#]),
+        Config::new(1).flatten_to_object("."),
+    ] {
+        let stats = unnest_to_ndjson_stats(&input[..], io::sink(), &config)?;
+        assert_eq!(5, stats.max_depth_seen, "{}", config);
+        assert_eq!(input.len() as u64, stats.bytes_read, "{}", config);
+    }
+
+    let stats = unnest_to_ndjson_stats(&input[..], io::sink(), &Config::new(1))?;
+    assert_eq!(4, stats.documents);
+    let stats = unnest_to_ndjson_stats(&b"1 2"[..], io::sink(), &Config::new(1))?;
+    assert_eq!((2, 0), (stats.documents, stats.max_depth_seen));
+    Ok(())
+}
+
+#[test]
+fn lenient() -> Result<(), UnnestError> {
+    let input = br#"// exported from somewhere
+{
+  "a": [1, 2, +3,], // trailing
+  "b": NaN,
+  "c": -Infinity,
+  "d": {"e": true, "f": +Infinity,},
+}"#;
+    assert_eq!(
+        "[1,2,3]\nnull\nnull\n{\"e\":true,\"f\":null}\n",
+        run(input, &Config::new(1).lenient())?
+    );
+    assert!(run(input, &Config::new(1)).is_err());
+    assert!(run(input, &Config::new(1).strict().lenient()).is_ok());
+    Ok(())
+}
+
+#[test]
+fn single_quotes() -> Result<(), UnnestError> {
+    let config = Config::new(0).allow_single_quotes(true);
+    assert_eq!("{\"a\":\"b\"}\n", run(b"{'a': 'b'}", &config)?);
+    assert_eq!(
+        "[\"it's\",\"say \\\"hi\\\"\",\"\\n\",\"x\"]\n",
+        run(br#"['it\'s', 'say "hi"', '\n', "x"]"#, &config)?
+    );
+    assert!(run(b"{'a': 'b'}", &Config::new(0)).is_err());
+    Ok(())
+}
+
+#[test]
+fn comments() -> Result<(), UnnestError> {
+    let config = Config::new(1).allow_comments(true);
+    let input = br#"/* header */ [
+  // first
+  1, /* between */ 2,
+  {"a" /* key */ : /* value */ 3}, // after
+  [4/**/]
+]"#;
+    assert_eq!("1\n2\n{\"a\":3}\n[4]\n", run(input, &config)?);
+    assert!(run(input, &Config::new(1)).is_err());
+
+    match run(b"[1, /* never closed *", &config) {
+        Err(UnnestError::Truncated { position }) => assert_eq!(21, position.offset),
+        other => panic!("{:?}", other),
+    }
+    match run(b"[1] /* after the end", &config) {
+        Err(UnnestError::Truncated { .. }) => (),
+        other => panic!("{:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+fn unquoted_keys() -> Result<(), UnnestError> {
+    let config = Config::new(0).allow_unquoted_keys(true);
+    assert_eq!(
+        "{\"a\":1,\"$b\":2,\"_c\":3,\"d4\":{\"e\":5}}\n",
+        run(b"{a: 1, $b: 2, _c: 3, d4 :{e:5}}", &config)?
+    );
+    assert!(run(b"{a-b: 1}", &config).is_err());
+    assert!(run(b"{4a: 1}", &config).is_err());
+    assert!(run(b"{a: 1}", &Config::new(0)).is_err());
+    Ok(())
+}
+
+#[test]
+fn scalar_root() -> Result<(), UnnestError> {
+    let input = b"42 [1] \"x\"";
+    assert_eq!("42\n1\n\"x\"\n", run(input, &Config::new(1))?);
+    assert_eq!(
+        "42\n1\n\"x\"\n",
+        run(input, &Config::new(1).scalar_root(ScalarRoot::Emit))?
+    );
+    assert_eq!(
+        "1\n",
+        run(input, &Config::new(1).scalar_root(ScalarRoot::Skip))?
+    );
+    match run(input, &Config::new(1).scalar_root(ScalarRoot::Error)) {
+        Err(UnnestError::ScalarRoot { position }) => assert_eq!(0, position.offset),
+        other => panic!("{:?}", other),
+    }
+    // at the target, a scalar is an ordinary document
+    assert_eq!(
+        "42\n[1]\n\"x\"\n",
+        run(input, &Config::new(0).scalar_root(ScalarRoot::Error))?
+    );
+    Ok(())
+}
+
+#[test]
+fn describe() {
+    let description = Config::new(3)
+        .header_style(HeaderStyle::Dotted)
+        .max_depth(10)
+        .to_string();
+    assert!(description.contains("target: 3\n"), "{}", description);
+    assert!(
+        description.contains("header_style: Dotted\n"),
+        "{}",
+        description
+    );
+    assert!(
+        description.contains("max_depth: Some(10)\n"),
+        "{}",
+        description
+    );
+    assert!(description.contains("skeleton: false\n"), "{}", description);
+}
+
+#[test]
+fn heartbeat() -> Result<(), UnnestError> {
+    /// Produce each chunk after a delay, as a slow network connection might.
+    struct Slow(Vec<&'static [u8]>);
+
+    impl io::Read for Slow {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() {
+                return Ok(0);
+            }
+            thread::sleep(Duration::from_millis(100));
+            let chunk = self.0.remove(0);
+            buf[..chunk.len()].copy_from_slice(chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    let slow = || Slow(vec![b"[{\"a\":", b"1}, ", b"{\"a\":2}", b"]"]);
+    let config = Config::new(1).heartbeat(Some(Duration::from_millis(20)));
+
+    let mut out = Vec::new();
+    unnest_to_ndjson_with(slow(), &mut out, &config)?;
+    // nothing is produced in the middle of the first document, although it was slow
+    assert_eq!(
+        concat!(
+            "{\"__heartbeat__\":true}\n",
+            "{\"a\":1}\n",
+            "{\"__heartbeat__\":true}\n",
+            "{\"a\":2}\n",
+            "{\"__heartbeat__\":true}\n",
+        ),
+        String::from_utf8(out).unwrap()
+    );
+
+    let mut out = Vec::new();
+    unnest_to_ndjson_with(slow(), &mut out, &config.clone().heartbeat_record("{}"))?;
+    assert_eq!(
+        "{}\n{\"a\":1}\n{}\n{\"a\":2}\n{}\n",
+        String::from_utf8(out).unwrap()
+    );
+
+    let mut out = Vec::new();
+    unnest_to_ndjson_with(slow(), &mut out, &Config::new(1))?;
+    assert_eq!("{\"a\":1}\n{\"a\":2}\n", String::from_utf8(out).unwrap());
+
+    /// Buffer each document, without knowing about heartbeats.
+    #[derive(Default)]
+    struct Buffered {
+        doc: Vec<u8>,
+        docs: Vec<Vec<u8>>,
+    }
+
+    impl MiniWrite for &mut Buffered {
+        fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+            self.doc.extend_from_slice(buf);
+            Ok(())
+        }
+    }
+
+    impl Sinker for &mut Buffered {
+        fn observe_end_with(&mut self, _: &Header) -> io::Result<()> {
+            self.docs.push(std::mem::take(&mut self.doc));
+            Ok(())
+        }
+    }
+
+    let mut sink = Buffered::default();
+    unnest_to_ndjson_with(slow(), &mut sink, &config)?;
+    assert_eq!(
+        vec![b"{\"a\":1}".to_vec(), b"{\"a\":2}".to_vec()],
+        sink.docs
+    );
+    Ok(())
+}
+
+#[test]
+fn path_prefix() -> Result<(), UnnestError> {
+    let input = br#"{
+  "number": 5,
+  "flatObject": {"baz": 6},
+  "doubleObject": {"aye": 7, "be": 8},
+  "doubleArray": [5, 6]
+}"#;
+    assert_eq!(
+        "{\"key\":[\"doubleObject\",\"aye\"],\"value\":7}\n{\"key\":[\"doubleObject\",\"be\"],\"value\":8}\n",
+        run(
+            input,
+            &Config::new(2)
+                .header_style(HeaderStyle::PathArray)
+                .path_prefix([r#""doubleObject""#])
+        )?
+    );
+    assert_eq!(
+        "7\n8\n",
+        run(input, &Config::new(2).path_prefix([r#""doubleObject""#]))?
+    );
+    assert_eq!(
+        "6\n",
+        run(
+            input,
+            &Config::new(2).path_prefix([r#""doubleArray""#, "1"])
+        )?
+    );
+    // the scalar "number" is a document shorter than the prefix
+    assert_eq!(
+        "",
+        run(input, &Config::new(2).path_prefix([r#""number""#, "0"]))?
+    );
+    assert_eq!(
+        "",
+        run(
+            input,
+            &Config::new(1).path_prefix([r#""doubleObject""#, r#""aye""#])
+        )?
+    );
+    Ok(())
+}
+
+#[test]
+fn root_path() -> Result<(), UnnestError> {
+    let input = br#"{"meta":{"items":[0]},"data":{"items":[1,2,3]},"after":[4]}"#;
+    let root = [r#""data""#, r#""items""#];
+    assert_eq!("1\n2\n3\n", run(input, &Config::new(1).root_path(root))?);
+    assert_eq!(
+        "{\"key\":[1],\"value\":2}\n",
+        run(
+            input,
+            &Config::new(1)
+                .root_path(root)
+                .path_prefix(["1"])
+                .header_style(HeaderStyle::PathArray)
+        )?
+    );
+    assert_eq!("[1,2,3]\n", run(input, &Config::new(0).root_path(root))?);
+    assert_eq!("", run(input, &Config::new(1).root_path([r#""missing""#]))?);
+    Ok(())
+}
+
+#[test]
+fn extra_targets() -> Result<(), UnnestError> {
+    let input = br#"{"a": {"x": 1, "y": [2]}, "b": {"z": 3}, "c": 4}"#;
+    assert_eq!(
+        concat!(
+            "{\"target\":2,\"value\":1}\n",
+            "{\"target\":2,\"value\":[2]}\n",
+            "{\"target\":1,\"value\":{\"x\":1,\"y\":[2]}}\n",
+            "{\"target\":2,\"value\":3}\n",
+            "{\"target\":1,\"value\":{\"z\":3}}\n",
+            "{\"target\":1,\"value\":4}\n",
+        ),
+        run(input, &Config::new(1).extra_targets([2]))?
+    );
+    assert_eq!(
+        concat!(
+            "{\"seq\":0,\"target\":2,\"value\":1}\n",
+            "{\"seq\":1,\"target\":2,\"value\":[2]}\n",
+            "{\"seq\":2,\"target\":1,\"value\":{\"x\":1,\"y\":[2]}}\n",
+            "{\"seq\":3,\"target\":2,\"value\":3}\n",
+            "{\"seq\":4,\"target\":1,\"value\":{\"z\":3}}\n",
+            "{\"seq\":5,\"target\":1,\"value\":4}\n",
+        ),
+        run(
+            input,
+            &Config::new(1)
+                .extra_targets([2])
+                .header_style(HeaderStyle::Sequence)
+        )?
+    );
+    assert_eq!(
+        concat!(
+            "{\"target\":3,\"key\":[\"a\",\"y\",0],\"value\":2}\n",
+            "{\"target\":1,\"key\":[\"a\"],\"value\":{\"x\":1,\"y\":[2]}}\n",
+            "{\"target\":1,\"key\":[\"b\"],\"value\":{\"z\":3}}\n",
+            "{\"target\":1,\"key\":[\"c\"],\"value\":4}\n",
+        ),
+        run(
+            input,
+            &Config::new(1)
+                .extra_targets([3, 0, 1])
+                .header_style(HeaderStyle::PathArray)
+        )?
+    );
+    Ok(())
+}
+
+#[test]
+fn extra_targets_with_uniform_keys() -> Result<(), UnnestError> {
+    let config = Config::new(1)
+        .header_style(HeaderStyle::PathArray)
+        .enforce_uniform_keys(true)
+        .extra_targets([2]);
+    assert_eq!(
+        concat!(
+            "{\"target\":2,\"key\":[0,\"a\"],\"value\":{\"b\":1}}\n",
+            "{\"target\":1,\"key\":[0],\"value\":{\"a\":{\"b\":1}}}\n",
+            "{\"target\":2,\"key\":[1,\"a\"],\"value\":{\"b\":2}}\n",
+            "{\"target\":1,\"key\":[1],\"value\":{\"a\":{\"b\":2}}}\n",
+        ),
+        run(br#"[{"a":{"b":1}},{"a":{"b":2}}]"#, &config)?
+    );
+
+    let config = Config::new(0)
+        .header_style(HeaderStyle::Dotted)
+        .extra_targets([3])
+        .enforce_uniform_keys(true);
+    assert_eq!(
+        concat!(
+            "{\"target\":3,\"path\":\"A.B.C\",\"value\":1}\n",
+            "{\"target\":0,\"path\":\"\",\"value\":{\"A\":{\"B\":{\"C\":1}},\"a\":{\"b\":2}}}\n",
+        ),
+        run(br#"{"A":{"B":{"C":1}},"a":{"b":2}}"#, &config)?
+    );
+    Ok(())
+}
+
+#[test]
+fn leaves() -> Result<(), UnnestError> {
+    let input = br#"{"a":1,"b":{"c":[true,null,{"d":"x"}],"e":{}},"f":[]} 7"#;
+    assert_eq!(
+        concat!(
+            "{\"key\":[\"a\"],\"value\":1}\n",
+            "{\"key\":[\"b\",\"c\",0],\"value\":true}\n",
+            "{\"key\":[\"b\",\"c\",1],\"value\":null}\n",
+            "{\"key\":[\"b\",\"c\",2,\"d\"],\"value\":\"x\"}\n",
+            "{\"key\":[],\"value\":7}\n",
+        ),
+        run(input, &Config::leaves())?
+    );
+    Ok(())
+}
+
+#[test]
+fn flatten_to_object() -> Result<(), UnnestError> {
+    let config = Config::new(0).flatten_to_object(".");
+    assert_eq!("{\"a.b.0\":5}\n", run(br#"{"a":{"b":[5]}}"#, &config)?);
+    assert_eq!(
+        "{\"a\":1,\"b.0\":true,\"b.1.c\":\"x\",\"d\":{},\"e\":[]}\n",
+        run(
+            br#"{"a": 1, "b": [true, {"c": "x"}], "d": {}, "e": []}"#,
+            &config
+        )?
+    );
+    assert_eq!(
+        "{\"0/k\":1}\n{\"\":2}\n",
+        run(br#"[{"k": 1}] 2"#, &Config::new(0).flatten_to_object("/"))?
+    );
+    assert_eq!(
+        "{\"k\":1}\n{\"k\":2}\n",
+        run(
+            br#"[{"k": 1}, {"k": 2}]"#,
+            &Config::new(1).flatten_to_object(".")
+        )?
+    );
+
+    let colliding = br#"{"a.b": 1, "a": {"b": 2}, "c": 3}"#;
+    match run(colliding, &config) {
+        Err(UnnestError::DuplicateKey { key, .. }) => assert_eq!(br#""a.b""#.to_vec(), key),
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(
+        "{\"a.b\":2,\"c\":3}\n",
+        run(colliding, &config.collisions(Collisions::LastWins))?
+    );
+    Ok(())
+}
+
+#[test]
+fn key_globs() -> Result<(), UnnestError> {
+    let input =
+        br#"{"user_id": 1, "name": "x", "password": "p", "password_id": 2, "tags": [3, 4]}"#;
+    assert_eq!(
+        "1\n2\n",
+        run(input, &Config::new(1).include_keys(["*_id"]))?
+    );
+    assert_eq!(
+        "1\n\"x\"\n[3,4]\n",
+        run(input, &Config::new(1).exclude_keys(["pass*"]))?
+    );
+    // exclude wins over include
+    assert_eq!(
+        "1\n",
+        run(
+            input,
+            &Config::new(1)
+                .include_keys(["*_id", "name"])
+                .exclude_keys(["pass*", "n?me"])
+        )?
+    );
+    // indexes are keys too
+    assert_eq!(
+        "{\"key\":[\"tags\",1],\"value\":4}\n",
+        run(
+            input,
+            &Config::new(2)
+                .header_style(HeaderStyle::PathArray)
+                .include_keys(["1"])
+        )?
+    );
+    Ok(())
+}
+
+#[test]
+fn byte_range() -> Result<(), UnnestError> {
+    let input = b"{\"a\": 5, \"b\": [ 6 ,\n 7 ] }\n  [true]";
+    let output = run(input, &Config::new(1).header_style(HeaderStyle::ByteRange))?;
+    assert_eq!(
+        concat!(
+            "{\"start\":6,\"value\":5,\"end\":7}\n",
+            "{\"start\":14,\"value\":[6,7],\"end\":24}\n",
+            "{\"start\":30,\"value\":true,\"end\":34}\n",
+        ),
+        output
+    );
+    assert_eq!(&b"[ 6 ,\n 7 ]"[..], &input[14..24]);
+    assert_eq!(&b"true"[..], &input[30..34]);
+
+    assert_eq!(
+        "{\"start\":0,\"value\":[1],\"end\":5}\n",
+        run(
+            b"[ 1 ]",
+            &Config::new(0).header_style(HeaderStyle::ByteRange)
+        )?
+    );
+    Ok(())
+}
+
+#[test]
+fn field_names() -> Result<(), UnnestError> {
+    assert_eq!(
+        "{\"id\":[\"a\"],\"doc\":{\"b\":1}}\n",
+        run(
+            br#"{"a": {"b": 1}}"#,
+            &Config::new(1)
+                .header_style(HeaderStyle::PathArray)
+                .key_field("id")
+                .value_field("doc")
+        )?
+    );
+    assert_eq!(
+        "{\"line\":1,\"a \\\"b\\\"\":5}\n",
+        run(
+            b"[5]",
+            &Config::new(1)
+                .header_style(HeaderStyle::WithLine)
+                .value_field("a \"b\"")
+        )?
+    );
+    Ok(())
+}
+
+#[test]
+fn sequence() -> Result<(), UnnestError> {
+    let input = br#"{"a": [1, 2], "b": [3]}"#;
+    assert_eq!(
+        "{\"seq\":0,\"value\":1}\n{\"seq\":1,\"value\":2}\n{\"seq\":2,\"value\":3}\n",
+        run(input, &Config::new(2).header_style(HeaderStyle::Sequence))?
+    );
+    assert_eq!(
+        concat!(
+            "{\"seq\":\"000000\",\"value\":1}\n",
+            "{\"seq\":\"000001\",\"value\":2}\n",
+            "{\"seq\":\"000002\",\"value\":3}\n",
+        ),
+        run(
+            input,
+            &Config::new(2)
+                .header_style(HeaderStyle::Sequence)
+                .sequence_width(6)
+        )?
+    );
+    Ok(())
+}
+
+#[test]
+fn classify_strings() -> Result<(), UnnestError> {
+    #[derive(Default)]
+    struct Classes {
+        out: Vec<u8>,
+        classes: Vec<(Vec<Vec<u8>>, StringClass)>,
+    }
+
+    impl MiniWrite for &mut Classes {
+        fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+            self.out.write_all(buf)
+        }
+    }
+
+    impl Sinker for &mut Classes {
+        fn observe_string_class(&mut self, path: &[Vec<u8>], class: StringClass) -> io::Result<()> {
+            self.classes.push((path.to_vec(), class));
+            Ok(())
+        }
+    }
+
+    let input = br#"{"when": "2024-01-01", "who": {"email": "a@b.com", "n": 5, "name": "x"}}"#;
+    let config = Config::new(1).header_style(HeaderStyle::PathArray);
+    let mut sink = Classes::default();
+    unnest_to_ndjson_with(
+        &input[..],
+        &mut sink,
+        &config.clone().classify_strings(true),
+    )?;
+    assert_eq!(
+        vec![
+            (vec![br#""when""#.to_vec()], StringClass::Date),
+            (vec![br#""who""#.to_vec()], StringClass::Email),
+            (vec![br#""who""#.to_vec()], StringClass::Text),
+        ],
+        sink.classes
+    );
+    assert_eq!(run(input, &config)?, String::from_utf8(sink.out).unwrap());
+
+    let mut sink = Classes::default();
+    unnest_to_ndjson_with(&input[..], &mut sink, &config)?;
+    assert!(sink.classes.is_empty());
+
+    // still seen through the wrappers, even when the documents themselves aren't produced
+    let mut sink = Classes::default();
+    unnest_to_ndjson_with(
+        &input[..],
+        &mut sink,
+        &config.clone().classify_strings(true).group_by(["n"]),
+    )?;
+    assert_eq!(3, sink.classes.len());
+    Ok(())
+}
+
+#[test]
+fn continue_on_error() -> Result<(), UnnestError> {
+    let input = concat!(
+        r#"{"a": [1, 2]}"#,
+        "\n",
+        r#"{"b": {"c": [3], "d": 1 2}}"#,
+        "\n",
+        r#"{"e": {"f": 4}}"#,
+        "\n",
+        r#"{"g": [5"#,
+        "\n",
+        r#"{"h": 6}"#,
+        "\n",
+        r#"{"i": [7"#,
+    );
+    let config = |target| {
+        Config::new(target)
+            .header_style(HeaderStyle::PathArray)
+            .continue_on_error(true)
+    };
+
+    let mut out = Vec::new();
+    let stats = unnest_to_ndjson_stats(input.as_bytes(), &mut out, &config(1))?;
+    assert_eq!(
+        concat!(
+            r#"{"key":["a"],"value":[1,2]}"#,
+            "\n",
+            r#"{"key":["e"],"value":{"f":4}}"#,
+            "\n",
+            r#"{"key":["h"],"value":6}"#,
+            "\n",
+        ),
+        String::from_utf8(out).unwrap()
+    );
+    assert_eq!(3, stats.documents);
+    // the unterminated line is only noticed on the next line, which is still produced
+    let lines: Vec<_> = (stats.errors.iter())
+        .map(|e| e.position().map(|position| position.line))
+        .collect();
+    assert_eq!(vec![Some(2), Some(5), Some(6)], lines, "{:?}", stats.errors);
+
+    // the documents before the problem are kept, and nothing is left of the broken path
+    assert_eq!(
+        concat!(
+            r#"{"key":["a",0],"value":1}"#,
+            "\n",
+            r#"{"key":["a",1],"value":2}"#,
+            "\n",
+            r#"{"key":["b","c"],"value":[3]}"#,
+            "\n",
+            r#"{"key":["b","d"],"value":1}"#,
+            "\n",
+            r#"{"key":["e","f"],"value":4}"#,
+            "\n",
+            r#"{"key":["g",0],"value":5}"#,
+            "\n",
+            r#"{"key":["h"],"value":6}"#,
+            "\n",
+            r#"{"key":["i",0],"value":7}"#,
+            "\n",
+        ),
+        run(input.as_bytes(), &config(2))?
+    );
+
+    assert!(run(input.as_bytes(), &config(1).continue_on_error(false)).is_err());
+    Ok(())
+}
+
+#[test]
+fn header_index() -> Result<(), UnnestError> {
+    #[derive(Default)]
+    struct Indexes {
+        seen: Vec<(u64, Vec<Vec<u8>>)>,
+    }
+
+    impl MiniWrite for &mut Indexes {
+        fn write_all(&mut self, _: &[u8]) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Sinker for &mut Indexes {
+        fn observe_new_item_with(&mut self, path: &[Vec<u8>], header: &Header) -> io::Result<()> {
+            self.seen.push((header.index, path.to_vec()));
+            Ok(())
+        }
+
+        fn observe_end_with(&mut self, header: &Header) -> io::Result<()> {
+            assert_eq!(
+                self.seen.last().map(|(index, _)| *index),
+                Some(header.index)
+            );
+            Ok(())
+        }
+    }
+
+    let input = br#"{"a": [1, 2], "b": [], "c": [3]} {"d": [4]}"#;
+    let config = Config::new(2)
+        .header_style(HeaderStyle::PathArray)
+        .skip_documents(1);
+    let mut sink = Indexes::default();
+    unnest_to_ndjson_with(&input[..], &mut sink, &config)?;
+    let path = |key: &str, index: &str| vec![key.as_bytes().to_vec(), index.as_bytes().to_vec()];
+    assert_eq!(
+        vec![
+            (0, path(r#""a""#, "1")),
+            (1, path(r#""c""#, "0")),
+            (2, path(r#""d""#, "0")),
+        ],
+        sink.seen
+    );
+    Ok(())
+}
+
+#[test]
+fn header_item() -> Result<(), UnnestError> {
+    #[derive(Default)]
+    struct Items {
+        seen: Vec<ItemMeta>,
+    }
+
+    impl MiniWrite for &mut Items {
+        fn write_all(&mut self, _: &[u8]) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Sinker for &mut Items {
+        fn observe_new_item_with(&mut self, _: &[Vec<u8>], header: &Header) -> io::Result<()> {
+            self.seen.push(header.item);
+            Ok(())
+        }
+
+        fn observe_end_with(&mut self, header: &Header) -> io::Result<()> {
+            assert_eq!(self.seen.last(), Some(&header.item));
+            Ok(())
+        }
+    }
+
+    let input = br#"{"a": [{"b": 1}, [2], "c", 3, null, []]} 4"#;
+    let config = Config::new(2).header_style(HeaderStyle::PathArray);
+    let mut sink = Items::default();
+    unnest_to_ndjson_with(&input[..], &mut sink, &config)?;
+    assert_eq!(
+        vec![
+            (2, ItemKind::Object),
+            (2, ItemKind::Array),
+            (2, ItemKind::Scalar),
+            (2, ItemKind::Scalar),
+            (2, ItemKind::Scalar),
+            (2, ItemKind::Array),
+            (0, ItemKind::Scalar),
+        ],
+        sink.seen
+            .iter()
+            .map(|item| (item.depth, item.kind))
+            .collect::<Vec<_>>()
+    );
+    Ok(())
+}
+
+#[test]
+fn observe_paths() -> Result<(), UnnestError> {
+    #[derive(Default)]
+    struct Routed {
+        paths: Vec<Vec<Vec<u8>>>,
+        written: Vec<u8>,
+    }
+
+    impl MiniWrite for &mut Routed {
+        fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+            self.written.extend_from_slice(buf);
+            Ok(())
+        }
+    }
+
+    impl Sinker for &mut Routed {
+        fn observe_new_item_with(&mut self, path: &[Vec<u8>], _: &Header) -> io::Result<()> {
+            self.paths.push(path.to_vec());
+            Ok(())
+        }
+    }
+
+    let input = br#"{"a": {"b": 1}, "c": [2]}"#;
+    let config = Config::new(2).observe_paths(true);
+    let mut sink = Routed::default();
+    unnest_to_ndjson_with(&input[..], &mut sink, &config)?;
+    let path = |key: &str, index: &str| vec![key.as_bytes().to_vec(), index.as_bytes().to_vec()];
+    assert_eq!(
+        vec![path(r#""a""#, r#""b""#), path(r#""c""#, "0")],
+        sink.paths
+    );
+    assert_eq!("1\n2\n", String::from_utf8_lossy(&sink.written));
+
+    // and the default sink still writes only the values
+    assert_eq!("1\n2\n", run(input, &config)?);
+    Ok(())
+}
+
+#[test]
+fn top_level_kind() -> Result<(), UnnestError> {
+    /// The events, in order: a top-level kind, or a document.
+    #[derive(Default)]
+    struct Events {
+        seen: Vec<Option<ItemKind>>,
+    }
+
+    impl MiniWrite for &mut Events {
+        fn write_all(&mut self, _: &[u8]) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Sinker for &mut Events {
+        fn observe_top_level(&mut self, kind: ItemKind) -> io::Result<()> {
+            self.seen.push(Some(kind));
+            Ok(())
+        }
+
+        fn observe_end_with(&mut self, _: &Header) -> io::Result<()> {
+            self.seen.push(None);
+            Ok(())
+        }
+    }
+
+    let input = br#"{"a": 1, "b": 2} [3] 4 []"#;
+    let mut sink = Events::default();
+    unnest_to_ndjson_with(&input[..], &mut sink, &Config::new(1))?;
+    assert_eq!(
+        vec![
+            Some(ItemKind::Object),
+            None,
+            None,
+            Some(ItemKind::Array),
+            None,
+            Some(ItemKind::Scalar),
+            None,
+            Some(ItemKind::Array),
+        ],
+        sink.seen
+    );
+    Ok(())
+}
+
+#[test]
+fn reject_duplicate_keys() -> Result<(), UnnestError> {
+    let config = Config::new(1).reject_duplicate_keys(true);
+    let input = br#"[{"a": 1, "b": {"a": 2}}, {"a": {"b": 3}, "b": 4}]"#;
+    assert_eq!(run(input, &Config::new(1))?, run(input, &config)?);
+
+    for input in [
+        &br#"[{"a": 1, "b": 2}, {"a": 1, "a": 2}]"#[..],
+        br#"{"x": [{"a": 1, "b": 2, "a": 3}]}"#,
+        br#"{"a": 1, "a": 2}"#,
+    ] {
+        match run(input, &config) {
+            Err(UnnestError::DuplicateKey { key, .. }) => assert_eq!(br#""a""#[..], key[..]),
+            other => panic!("{:?}", other),
+        }
+        run(input, &Config::new(1))?;
+    }
+
+    let config = config.rename_keys(maplit::hashmap! { b"b".to_vec() => b"a".to_vec() });
+    assert!(run(br#"{"a": 1, "b": 2}"#, &config).is_err());
+    Ok(())
+}
+
+#[test]
+fn flush() -> Result<(), UnnestError> {
+    #[derive(Default)]
+    struct Flushes {
+        out: Vec<u8>,
+        flushes: usize,
+    }
+
+    impl MiniWrite for &mut Flushes {
+        fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+            assert_eq!(0, self.flushes);
+            self.out.write_all(buf)
+        }
+    }
+
+    impl Sinker for &mut Flushes {
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes += 1;
+            Ok(())
+        }
+    }
+
+    for config in [
+        Config::new(1),
+        Config::new(1).wrap_width(Some(3)),
+        Config::new(1).group_by([r#""a""#]),
+    ] {
+        let mut sink = Flushes::default();
+        unnest_to_ndjson_with(&br#"[{"a": 1}, {"a": 2}]"#[..], &mut sink, &config)?;
+        assert_eq!(1, sink.flushes, "{}", config);
+        assert!(!sink.out.is_empty());
+    }
+
+    let mut out = Vec::new();
+    let mut writer = io::BufWriter::new(&mut out);
+    unnest_to_ndjson_with(&b"[1, 2]"[..], &mut writer, &Config::new(1))?;
+    assert!(writer.buffer().is_empty());
+    drop(writer);
+    assert_eq!(b"1\n2\n"[..], out[..]);
+    Ok(())
+}
+
+#[test]
+fn document_len() -> Result<(), UnnestError> {
+    #[derive(Default)]
+    struct Lengths {
+        out: Vec<u8>,
+        lengths: Vec<usize>,
+    }
+
+    impl MiniWrite for &mut Lengths {
+        fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+            self.out.write_all(buf)
+        }
+    }
+
+    impl Sinker for &mut Lengths {
+        fn observe_new_item_with(&mut self, path: &[Vec<u8>], header: &Header) -> io::Result<()> {
+            header.write_start(self, path)
+        }
+
+        fn observe_end_with(&mut self, header: &Header) -> io::Result<()> {
+            header.write_end(self)
+        }
+
+        fn observe_document_len(&mut self, len: usize) -> io::Result<()> {
+            self.lengths.push(len);
+            Ok(())
+        }
+    }
+
+    let input = b"[1, 22, 333]";
+    let mut sink = Lengths::default();
+    unnest_to_ndjson_with(&input[..], &mut sink, &Config::new(1))?;
+    assert_eq!(vec![2, 3, 4], sink.lengths);
+
+    for config in [
+        Config::new(1).header_style(HeaderStyle::PathArray),
+        Config::new(1)
+            .header_style(HeaderStyle::ByteRange)
+            .json_seq(true),
+        Config::new(0).pretty(Some(2)),
+    ] {
+        let mut sink = Lengths::default();
+        unnest_to_ndjson_with(&input[..], &mut sink, &config)?;
+        assert_eq!(sink.out.len(), sink.lengths.iter().sum::<usize>());
+    }
+    Ok(())
+}
+
+#[test]
+fn record_separator() -> Result<(), UnnestError> {
+    let input = br#"{"a": [1, {"b": "x\ny"}]}"#;
+    let config = Config::new(2).record_separator(b"\x1e".to_vec());
+    let out = run(input, &config)?;
+    assert_eq!("1\x1e{\"b\":\"x\\ny\"}\x1e", out);
+
+    let parsed = out
+        .split_terminator('\x1e')
+        .map(serde_json::from_str)
+        .collect::<Result<Vec<serde_json::Value>, _>>()
+        .expect("valid json");
+    assert_eq!(
+        vec![serde_json::json!(1), serde_json::json!({"b": "x\ny"})],
+        parsed
+    );
+
+    let wrapped = config.header_style(HeaderStyle::PathArray);
+    assert_eq!(
+        "{\"key\":[\"a\",0],\"value\":1}\x1e{\"key\":[\"a\",1],\"value\":{\"b\":\"x\\ny\"}}\x1e",
+        run(input, &wrapped)?
+    );
+    Ok(())
+}
+
+#[test]
+fn trailing_separator() -> Result<(), UnnestError> {
+    let input = br#"{"a": [1, {"b": 2}, 1]}"#;
+    let between = Config::new(2).trailing_separator(false);
+    assert_eq!("1\n{\"b\":2}\n1\n", run(input, &Config::new(2))?);
+    assert_eq!("1\n{\"b\":2}\n1", run(input, &between)?);
+    assert_eq!("", run(b"[]", &between)?);
+    assert_eq!(
+        "{\"key\":[\"a\",0],\"value\":1},{\"key\":[\"a\",2],\"value\":1}",
+        run(
+            input,
+            &between
+                .clone()
+                .header_style(HeaderStyle::PathArray)
+                .record_separator(",")
+                .filter_equals(Vec::<Vec<u8>>::new(), "1")
+        )?
+    );
+    assert_eq!(
+        "{\"value\":1,\"count\":2}\n{\"value\":2,\"count\":1}",
+        run(
+            br#"[{"n": 1}, {"n": 2}, {"n": 1}]"#,
+            &Config::new(1)
+                .trailing_separator(false)
+                .group_by([r#""n""#])
+        )?
+    );
+    Ok(())
+}
+
+#[test]
+fn json_seq() -> Result<(), UnnestError> {
+    let input = br#"{"a": 1, "b": [2]}"#;
+    let config = Config::new(1).json_seq(true);
+    assert_eq!("\x1e1\n\x1e[2]\n", run(input, &config)?);
+    assert_eq!(
+        "\x1e{\"key\":[\"a\"],\"value\":1}\n\x1e{\"key\":[\"b\"],\"value\":[2]}\n",
+        run(input, &config.clone().header_style(HeaderStyle::PathArray))?
+    );
+    assert_eq!(
+        "\x1e{\"start\":6,\"value\":1,\"end\":7}\n\x1e{\"start\":14,\"value\":[2],\"end\":17}\n",
+        run(input, &config.header_style(HeaderStyle::ByteRange))?
+    );
+    Ok(())
+}
+
+#[test]
+fn filter_equals() -> Result<(), UnnestError> {
+    let input = br#"[{"status":"active","id":1}, {"status":"gone","id":2}, 3,
+        {"id":3, "status": "active"}, {"meta": {"status": "active"}, "id": 4}]"#;
+    let config = Config::new(1).filter_equals([r#""status""#], r#""active""#);
+    assert_eq!(
+        "{\"status\":\"active\",\"id\":1}\n{\"id\":3,\"status\":\"active\"}\n",
+        run(input, &config)?
+    );
+    assert_eq!(
+        "{\"key\":[4],\"value\":{\"meta\":{\"status\":\"active\"},\"id\":4}}\n",
+        run(
+            input,
+            &Config::new(1)
+                .header_style(HeaderStyle::PathArray)
+                .filter_equals([r#""meta""#, r#""status""#], r#""active""#)
+        )?
+    );
+
+    // only the documents which match are counted, and numbered
+    let mut out = Vec::new();
+    let stats = unnest_to_ndjson_stats(
+        &input[..],
+        &mut out,
+        &config
+            .clone()
+            .max_documents(1)
+            .header_style(HeaderStyle::Sequence),
+    )?;
+    assert_eq!(
+        "{\"seq\":0,\"value\":{\"status\":\"active\",\"id\":1}}\n",
+        String::from_utf8(out).unwrap()
+    );
+    assert_eq!(1, stats.documents);
+    let mut out = Vec::new();
+    let stats = unnest_to_ndjson_stats(
+        &input[..],
+        &mut out,
+        &config.max_documents(2).header_style(HeaderStyle::Sequence),
+    )?;
+    assert_eq!(
+        concat!(
+            "{\"seq\":0,\"value\":{\"status\":\"active\",\"id\":1}}\n",
+            "{\"seq\":1,\"value\":{\"id\":3,\"status\":\"active\"}}\n",
+        ),
+        String::from_utf8(out).unwrap()
+    );
+    assert_eq!(2, stats.documents);
+    assert_eq!(
+        Some(&br#", {"meta": {"status": "active"}, "id": 4}]"#[..]),
+        stats.remaining.as_deref()
+    );
+    Ok(())
+}
+
+#[test]
+fn group_by() -> Result<(), UnnestError> {
+    let config = Config::new(1).group_by([r#""s""#]);
+    assert_eq!(
+        "{\"value\":\"a\",\"count\":2}\n{\"value\":\"b\",\"count\":1}\n",
+        run(br#"[{"s":"a"},{"s":"a"},{"s":"b"}]"#, &config)?
+    );
+    assert_eq!(
+        "{\"value\":5,\"count\":1}\n",
+        run(br#"[{"s":5},{"s":[5]},{"t":5},6]"#, &config)?
+    );
+    match run(br#"[{"s":1},{"s":1},{"s":2}]"#, &config.max_groups(1)) {
+        Err(UnnestError::Io(e)) => assert_eq!(io::ErrorKind::InvalidData, e.kind()),
+        other => panic!("{:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+fn pretty() -> Result<(), UnnestError> {
+    let input = br#"{"a": {"b": [1, 2], "c": {}, "d": "x,{\"", "e": []}, "f": 3}"#;
+    let expected = r#"{"key":["a"],"value":{
+  "b": [
+    1,
+    2
+  ],
+  "c": {},
+  "d": "x,{\"",
+  "e": []
+}}
+{"key":["f"],"value":3}
+"#;
+    let config = Config::new(1)
+        .header_style(HeaderStyle::PathArray)
+        .pretty(Some(2));
+    assert_eq!(expected, run(input, &config)?);
+    Ok(())
+}
+
+#[test]
+fn pretty_unbalanced() -> Result<(), UnnestError> {
+    // the stray closers are let through, but have nothing to dedent
+    assert_eq!(
+        "1\n4\n,\n\n5\n\n]\n",
+        run(b"[1]\n4,\n5]", &Config::new(1).pretty(Some(2)))?
+    );
+    Ok(())
+}
+
+#[test]
+fn verbatim() -> Result<(), UnnestError> {
+    let input = b"{\"x\":  { \"a\" :  5 } , \"y\": [ 1 ,\n\t2, [ ] ],\"z\" : 3 }";
+    let config = Config::new(1).verbatim(true);
+    assert_eq!(
+        "{ \"a\" :  5 }\n[ 1 ,\n\t2, [ ] ]\n3\n",
+        run(input, &config)?
+    );
+    assert_eq!(
+        "{\"key\":[\"x\"],\"value\":{ \"a\" :  5 }}\n",
+        run(
+            br#"{"x":  { "a" :  5 } }"#,
+            &config.header_style(HeaderStyle::PathArray)
+        )?
+    );
+    Ok(())
+}
+
+#[test]
+fn fingerprint() -> Result<(), UnnestError> {
+    let input = br#"[{"id": 1, "at": "10:00", "tags": {"b": 1, "a": 2}},
+        {"tags": {"a": 2, "b": 1}, "at": "10:05", "id": 1},
+        {"id": 2, "at": "10:00", "tags": {}}]"#;
+    let config = Config::new(1).fingerprint([r#""at""#], Fingerprint::Fnv1a);
+    let out = run(input, &config)?;
+    let fingerprints = out
+        .lines()
+        .map(|line| {
+            let doc: serde_json::Value = serde_json::from_str(line).expect("valid json");
+            doc["fingerprint"].as_str().expect("string").to_string()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(3, fingerprints.len());
+    assert_eq!(16, fingerprints[0].len());
+    assert_eq!(fingerprints[0], fingerprints[1]);
+    assert_ne!(fingerprints[0], fingerprints[2]);
+    assert!(out.starts_with(&format!(
+        "{{\"fingerprint\":\"{}\",\"value\":{{\"id\":1,",
+        fingerprints[0]
+    )));
+    Ok(())
+}
+
+#[test]
+fn byte_order_mark() -> Result<(), UnnestError> {
+    let config = Config::new(1).header_style(HeaderStyle::ByteRange);
+    assert_eq!(
+        "{\"start\":8,\"value\":1,\"end\":9}\n",
+        run(b"\xef\xbb\xbf{\"a\":1}", &config)?
+    );
+    assert_eq!("1\n", run(b"\xef\xbb\xbf {\"a\":1}", &Config::new(1))?);
+    // elsewhere, it is not skipped
+    assert!(!matches!(
+        run(b"[1] \xef\xbb\xbf[2]", &Config::new(1)).as_deref(),
+        Ok("1\n2\n")
+    ));
+    Ok(())
+}
+
+#[test]
+fn document_delimiter() -> Result<(), UnnestError> {
+    let config = Config::new(1).document_delimiter(Some(b"\n---\n".to_vec()));
+    assert_eq!(
+        "1\n2\n3\n",
+        run(b"---\n[1, 2]\n---\n{\"a\": 3}\n---\n", &config)?
+    );
+    assert_eq!("-1\n-2\n", run(b"[-1]\n---\n[-2]", &config)?);
+    assert_eq!(
+        "1\n2\n",
+        run(b"[1] ---\n [2]", &config.clone().buffer_size(4))?
+    );
+
+    match run(b"[1]\n[2]", &config) {
+        Err(UnnestError::Syntax { position, .. }) => {
+            assert_eq!((2, 1), (position.line, position.column))
+        }
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(
+        "1\n2\n3\n",
+        run(
+            b"[1] [2] --- [3]",
+            &config.require_document_delimiter(false)
+        )?
+    );
+    Ok(())
+}
+
+#[test]
+fn rename_keys() -> Result<(), UnnestError> {
+    let renames = maplit::hashmap! {
+        b"userId".to_vec() => b"user_id".to_vec(),
+        b"q".to_vec() => b"say \"hi\"".to_vec(),
+    };
+    let input = br#"{"userId": {"userId": 5, "q": 1, "userIds": 2}}"#;
+    let config = |target| {
+        Config::new(target)
+            .header_style(HeaderStyle::PathArray)
+            .rename_keys(renames.clone())
+    };
+    assert_eq!(
+        "{\"key\":[\"user_id\"],\"value\":{\"user_id\":5,\"say \\\"hi\\\"\":1,\"userIds\":2}}\n",
+        run(input, &config(1))?
+    );
+    assert_eq!(
+        "{\"key\":[\"user_id\",\"user_id\"],\"value\":5}\n",
+        run(br#"{"userId": {"userId": 5}}"#, &config(2))?
+    );
+    Ok(())
+}
+
+#[test]
+fn normalize_path_keys() -> Result<(), UnnestError> {
+    let config = Config::new(1)
+        .header_style(HeaderStyle::PathArray)
+        .normalize_path_keys(Some(NormalizeKey::AsciiLowercase));
+    assert_eq!(
+        "{\"key\":[\"a\"],\"value\":1}\n{\"key\":[\"b\"],\"value\":{\"C\":2}}\n",
+        run(br#"{"A": 1, "B": {"C": 2}}"#, &config)?
+    );
+    assert_eq!(
+        "{\"key\":[\"b\",\"c\"],\"value\":2}\n",
+        run(
+            br#"{"B": {"C": 2}, "D": {"E": 3}}"#,
+            &Config::new(2)
+                .header_style(HeaderStyle::PathArray)
+                .normalize_path_keys(Some(NormalizeKey::AsciiLowercase))
+                .path_prefix([r#""b""#])
+        )?
+    );
+
+    let quoted = NormalizeKey::With(Arc::new(|key: &[u8]| [b"\"", key, b"\""].concat()));
+    let config = config.normalize_path_keys(Some(quoted));
+    assert_eq!(
+        r#"{"key":["\"a\""],"value":{"B":1}}"#,
+        run(br#"{"a": {"B": 1}}"#, &config)?.trim_end()
+    );
+    Ok(())
+}
+
+#[test]
+fn wrap_width() -> Result<(), UnnestError> {
+    let record = (0..30)
+        .map(|i| format!(r#""k{}": [{}, "a,b"]"#, i, i))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let input = format!(
+        "[{{{}}}, 5, {{\"a\": \"this string is too long to fit\"}}]",
+        record
+    );
+    for style in [HeaderStyle::None, HeaderStyle::PathArray] {
+        let config = Config::new(1).header_style(style);
+        let unwrapped = run(input.as_bytes(), &config)?;
+        let wrapped = run(input.as_bytes(), &config.wrap_width(Some(20)))?;
+        assert_eq!(unwrapped.replace('\n', ""), wrapped.replace('\n', ""));
+        let lines = wrapped.lines().collect::<Vec<_>>();
+        assert!(lines.len() > 30, "{}", wrapped);
+        assert!(lines
+            .iter()
+            .all(|line| line.len() <= 20 || line.contains("too long")));
+    }
+    Ok(())
+}
+
+#[test]
+fn python_literals() -> Result<(), UnnestError> {
+    let config = Config::new(1).python_literals(true);
+    assert_eq!("True\nFalse\nNone\n", run(b"[true,false,null]", &config)?);
+    assert_eq!(
+        "{\"a\":[True,\"true\",1.5,None]}\n",
+        run(br#"[{"a": [true, "true", 1.5, null]}]"#, &config)?
+    );
+    assert_eq!("None\n", run(b"[NaN]", &config.allow_js_numbers(true))?);
+    Ok(())
+}
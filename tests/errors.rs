@@ -0,0 +1,337 @@
+use std::io;
+
+use unnest_ndjson::{
+    unnest_to_ndjson, unnest_to_ndjson_stats, unnest_to_ndjson_with, Config, HeaderStyle,
+    UnnestError,
+};
+
+fn error(input: &[u8]) -> UnnestError {
+    let mut out = Vec::new();
+    unnest_to_ndjson_with(input, &mut out, &Config::new(1)).unwrap_err()
+}
+
+#[test]
+fn misplaced_comma() {
+    match error(br#"{"a", 1}"#) {
+        UnnestError::Syntax { position, message } => {
+            assert_eq!(4, position.offset);
+            assert_eq!((1, 5), (position.line, position.column));
+            assert_eq!("expected ':'", message);
+        }
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn missing_comma_on_a_later_line() {
+    let err = error(b"[\n  {\"a\": 1}\n  {\"b\": 2}\n]");
+    match &err {
+        UnnestError::Syntax { position, .. } => {
+            assert_eq!(15, position.offset);
+            assert_eq!((3, 3), (position.line, position.column));
+        }
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(
+        "expected ',' or ']' at line 3, column 3 (byte 15)",
+        err.to_string()
+    );
+}
+
+#[test]
+fn unterminated_string() {
+    match error(br#"["abc"#) {
+        UnnestError::Truncated { position } => assert_eq!(5, position.offset),
+        other => panic!("{:?}", other),
+    }
+
+    let mut out = Vec::new();
+    let err = unnest_to_ndjson(&br#"["abc"#[..], &mut out, 1, HeaderStyle::None).unwrap_err();
+    assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+}
+
+#[test]
+fn malformed_numbers() {
+    let config = Config::new(1).validate_numbers(true);
+    for bad in ["12.3.4", "--5", "0x10", "1e", "01", "+1"] {
+        let input = format!("[0, {}]", bad);
+        let mut out = Vec::new();
+        match unnest_to_ndjson_with(input.as_bytes(), &mut out, &config) {
+            Err(UnnestError::Syntax { position, message }) => {
+                assert_eq!(4, position.offset, "{}", bad);
+                assert_eq!("invalid number", message);
+            }
+            other => panic!("{}: {:?}", bad, other),
+        }
+    }
+
+    let mut out = Vec::new();
+    unnest_to_ndjson_with(&b"[-0, 1E+10, 0.5, true, null]"[..], &mut out, &config).unwrap();
+    assert_eq!(&b"-0\n1E+10\n0.5\ntrue\nnull\n"[..], &out[..]);
+}
+
+#[test]
+fn strict() {
+    let config = Config::new(1).strict();
+    for bad in [
+        &b"[+1]"[..],
+        b"[01]",
+        b"[1.]",
+        b"[nul]",
+        b"[\"\\uDE00\"]",
+        b"[\"a\tb\"]",
+        b"[\"a\x01\"]",
+        b"[1,\x0c2]",
+        b"[\"\xff\"]",
+        b"[1,]",
+        b"{\"a\":1,}",
+        b"{,\"a\":1}",
+    ] {
+        let mut out = Vec::new();
+        match unnest_to_ndjson_with(bad, &mut out, &config) {
+            Err(UnnestError::Syntax { .. }) => (),
+            other => panic!("{:?}: {:?}", String::from_utf8_lossy(bad), other),
+        }
+        // all of these are accepted by default
+        unnest_to_ndjson_with(bad, &mut out, &Config::new(1)).unwrap();
+    }
+
+    let mut out = Vec::new();
+    unnest_to_ndjson_with(
+        &b"[\"a\\tb\", {\"k\": 1, \"k\": 2},\r\n -0.5e3]"[..],
+        &mut out,
+        &config,
+    )
+    .unwrap();
+    assert_eq!(&b"\"a\\tb\"\n{\"k\":1,\"k\":2}\n-0.5e3\n"[..], &out[..]);
+}
+
+#[test]
+fn control_characters() {
+    let input = b"[\"a\tb\", \"c\x00d\", \"e\nf\"]";
+    let run = |config: &Config| {
+        let mut out = Vec::new();
+        unnest_to_ndjson_with(&input[..], &mut out, config).map(|_| out)
+    };
+
+    // only line feeds and carriage returns are rejected, as they would break the line
+    match run(&Config::new(1)) {
+        Err(UnnestError::Syntax { position, message }) => {
+            assert_eq!(17, position.offset);
+            assert_eq!("unescaped newline in string", message);
+        }
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(
+        &b"\"a\tb\"\n\"c\x00d\"\n\"e\nf\"\n"[..],
+        &run(&Config::new(1).allow_raw_newlines(true)).unwrap()[..]
+    );
+    assert_eq!(
+        &b"\"a\\tb\"\n\"c\\u0000d\"\n\"e\\u000af\"\n"[..],
+        &run(&Config::new(1).escape_control_characters(true)).unwrap()[..]
+    );
+    for config in [
+        Config::new(1).reject_control_characters(true),
+        Config::new(1)
+            .reject_control_characters(true)
+            .escape_control_characters(true),
+    ] {
+        match run(&config) {
+            Err(UnnestError::Syntax { position, message }) => {
+                assert_eq!(3, position.offset);
+                assert_eq!("control character in string", message);
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+}
+
+#[test]
+fn long_whitespace() {
+    let indent = " \t\r\n".repeat(20);
+    let input = format!("[1,{}2,{}\x0c3]", indent, indent);
+    let mut out = Vec::new();
+    unnest_to_ndjson_with(input.as_bytes(), &mut out, &Config::new(1)).unwrap();
+    assert_eq!(&b"1\n2\n3\n"[..], &out[..]);
+
+    let config = Config::new(1).spec_whitespace(true);
+    match unnest_to_ndjson_with(input.as_bytes(), &mut Vec::new(), &config) {
+        Err(UnnestError::Syntax { position, .. }) => {
+            assert_eq!(input.len() - 3, position.offset as usize)
+        }
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn invalid_literals() {
+    let config = Config::new(1).validate_literals(true);
+    for bad in ["tru", "TRUE", "True", "nullx", "fals", "x"] {
+        for input in [format!("[0, {}]", bad), format!("[0, {}", bad)] {
+            let mut out = Vec::new();
+            match unnest_to_ndjson_with(input.as_bytes(), &mut out, &config) {
+                Err(UnnestError::Syntax { position, message }) => {
+                    assert_eq!(4, position.offset, "{}", input);
+                    assert_eq!("invalid literal", message);
+                }
+                other => panic!("{}: {:?}", input, other),
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    unnest_to_ndjson_with(&b"[true, false, null, 5, \"x\"]"[..], &mut out, &config).unwrap();
+    assert_eq!(&b"true\nfalse\nnull\n5\n\"x\"\n"[..], &out[..]);
+}
+
+#[test]
+fn surrogates() {
+    let config = Config::new(1).validate_surrogates(true);
+    for bad in [
+        r#"["\uD83D"]"#,
+        r#"["\uD83Dx"]"#,
+        r#"["\uD83D\n"]"#,
+        r#"["\uD83DA"]"#,
+        r#"["\uDE00"]"#,
+        r#"["\uDE00\uD83D"]"#,
+    ] {
+        let mut out = Vec::new();
+        match unnest_to_ndjson_with(bad.as_bytes(), &mut out, &config) {
+            Err(UnnestError::Syntax { message, .. }) => assert_eq!("unpaired surrogate", message),
+            other => panic!("{}: {:?}", bad, other),
+        }
+    }
+
+    let mut out = Vec::new();
+    let input = r#"["\uD83D\uDE00", "\u00e9\uFFFF"]"#;
+    unnest_to_ndjson_with(input.as_bytes(), &mut out, &config).unwrap();
+    assert_eq!(
+        "\"\\uD83D\\uDE00\"\n\"\\u00e9\\uFFFF\"\n",
+        String::from_utf8(out).unwrap()
+    );
+}
+
+#[test]
+fn utf8() {
+    let config = Config::new(1).validate_utf8(true);
+    for (bad, offset) in [
+        // a three byte character, missing its last byte
+        (&b"[\"r\xe2\x82\"]"[..], 5),
+        (b"[\"\xe2\x82\"]", 4),
+        (b"[\"r\xffx\"]", 3),
+        (b"[\"\xc3\xa9\xa9\"]", 4),
+        (b"[\"\xed\xa0\x80\"]", 2),
+        (b"[\"\xc0\xaf\"]", 2),
+        (b"[{\"\xe9\": 1}]", 4),
+    ] {
+        let mut out = Vec::new();
+        match unnest_to_ndjson_with(bad, &mut out, &config) {
+            Err(UnnestError::Syntax { position, message }) => {
+                assert_eq!("invalid UTF-8 in string", message);
+                assert_eq!(offset, position.offset, "{:?}", bad);
+            }
+            other => panic!("{:?}: {:?}", String::from_utf8_lossy(bad), other),
+        }
+        unnest_to_ndjson_with(bad, &mut out, &Config::new(1)).unwrap();
+    }
+
+    let input = r#"["résumé", {"ключ": "€𝄞"}]"#;
+    for buffer_size in [1, 2, 3, 1024] {
+        let mut out = Vec::new();
+        let config = config.clone().buffer_size(buffer_size);
+        unnest_to_ndjson_with(input.as_bytes(), &mut out, &config).unwrap();
+        assert_eq!(
+            "\"résumé\"\n{\"ключ\":\"€𝄞\"}\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+}
+
+#[test]
+fn single_document() {
+    let config = Config::new(1).single_document(true);
+    let mut out = Vec::new();
+    unnest_to_ndjson_with(&b" {\"a\":1} \n"[..], &mut out, &config).unwrap();
+    assert_eq!(&b"1\n"[..], &out[..]);
+
+    for (input, offset) in [(&br#"{"a":1} null"#[..], 8), (br#"{"a":1}garbage"#, 7)] {
+        match unnest_to_ndjson_with(input, &mut Vec::new(), &config) {
+            Err(UnnestError::Syntax { position, message }) => {
+                assert_eq!("trailing data after the document", message);
+                assert_eq!(offset, position.offset);
+            }
+            other => panic!("{:?}: {:?}", String::from_utf8_lossy(input), other),
+        }
+    }
+    // a stream of documents, by default
+    unnest_to_ndjson_with(&br#"{"a":1} null"#[..], &mut Vec::new(), &Config::new(1)).unwrap();
+}
+
+#[test]
+fn only_whitespace() {
+    let spaces = vec![b' '; 100];
+    for input in [&b""[..], b"  \n", b"\r\n\t", &spaces] {
+        for target in 0..3 {
+            for buffer_size in [1, 7, 16 * 1024] {
+                let config = Config::new(target)
+                    .buffer_size(buffer_size)
+                    .header_style(HeaderStyle::PathArray);
+                let mut out = Vec::new();
+                let stats = unnest_to_ndjson_stats(input, &mut out, &config).unwrap();
+                assert_eq!(0, stats.documents);
+                assert_eq!(input.len() as u64, stats.bytes_read);
+                assert!(out.is_empty());
+            }
+        }
+    }
+
+    // but a document which was started must be finished
+    for target in 0..3 {
+        let config = Config::new(target);
+        match unnest_to_ndjson_with(&b"  {\"a\":"[..], &mut Vec::new(), &config) {
+            Err(UnnestError::Truncated { position }) => assert_eq!(7, position.offset),
+            other => panic!("{}: {:?}", target, other),
+        }
+    }
+}
+
+#[test]
+fn clean_end_or_truncated() {
+    let config = Config::new(1).allow_comments(true);
+    for clean in [
+        &b""[..],
+        b"  \n",
+        b"[1]",
+        b"[1]\n\n",
+        b"[1] [2]",
+        b"[1] // done",
+        b"[1] /* done */ ",
+    ] {
+        let mut out = Vec::new();
+        if let Err(e) = unnest_to_ndjson_with(clean, &mut out, &config) {
+            panic!("{:?}: {:?}", String::from_utf8_lossy(clean), e);
+        }
+    }
+
+    for truncated in [
+        &b"["[..],
+        b"[1",
+        b"[1,",
+        b"[1, ",
+        b"{\"a\"",
+        b"{\"a\":",
+        b"[\"a",
+        b"[1] /",
+        b"[1] /* done",
+    ] {
+        let mut out = Vec::new();
+        let err = match unnest_to_ndjson_with(truncated, &mut out, &config) {
+            Err(err @ UnnestError::Truncated { .. }) => err,
+            other => panic!("{:?}: {:?}", String::from_utf8_lossy(truncated), other),
+        };
+        if let UnnestError::Truncated { position } = &err {
+            assert_eq!(truncated.len() as u64, position.offset);
+        }
+        assert_eq!(io::ErrorKind::UnexpectedEof, io::Error::from(err).kind());
+    }
+}
@@ -0,0 +1,67 @@
+#![cfg(feature = "gzip")]
+
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+use unnest_ndjson::sinks::GzipRecords;
+use unnest_ndjson::{unnest_to_ndjson_with, Config, HeaderStyle, UnnestError};
+
+fn compressed(level: u32) -> Result<Vec<u8>, UnnestError> {
+    let input = format!("[{}]", vec![r#"{"a":"potato"}"#; 1000].join(","));
+    let mut out = Vec::new();
+    unnest_to_ndjson_with(
+        input.as_bytes(),
+        &mut out,
+        &Config::new(1).output_compression(level),
+    )?;
+    Ok(out)
+}
+
+fn decompress(buf: &[u8]) -> String {
+    let mut out = String::new();
+    GzDecoder::new(buf)
+        .read_to_string(&mut out)
+        .expect("valid gzip");
+    out
+}
+
+#[test]
+fn output_compression() -> Result<(), UnnestError> {
+    let expected = "{\"a\":\"potato\"}\n".repeat(1000);
+    let stored = compressed(0)?;
+    let best = compressed(9)?;
+    assert_eq!(expected, decompress(&stored));
+    assert_eq!(expected, decompress(&best));
+    assert!(stored.len() > expected.len());
+    assert!(best.len() < expected.len() / 10);
+    Ok(())
+}
+
+#[test]
+fn gzip_records() -> Result<(), UnnestError> {
+    let mut out = Vec::new();
+    unnest_to_ndjson_with(
+        &b"[1, 2, 3]"[..],
+        GzipRecords::new(&mut out, 6),
+        &Config::new(1).header_style(HeaderStyle::PathArray),
+    )?;
+
+    let mut frames = Vec::new();
+    let mut rest = &out[..];
+    while !rest.is_empty() {
+        let (len, after) = rest.split_at(4);
+        let len = u32::from_be_bytes(len.try_into().unwrap()) as usize;
+        let (frame, after) = after.split_at(len);
+        frames.push(decompress(frame));
+        rest = after;
+    }
+    assert_eq!(
+        vec![
+            "{\"key\":[0],\"value\":1}\n",
+            "{\"key\":[1],\"value\":2}\n",
+            "{\"key\":[2],\"value\":3}\n",
+        ],
+        frames
+    );
+    Ok(())
+}
@@ -0,0 +1,164 @@
+use std::io;
+
+use unnest_ndjson::{
+    documents, documents_after, unnest_iter, Appending, Config, Document, HeaderStyle, Progress,
+    UnnestError,
+};
+
+fn strings(docs: &[Document]) -> Vec<(Vec<String>, String)> {
+    docs.iter()
+        .map(|doc| {
+            let path = doc
+                .path
+                .iter()
+                .map(|p| String::from_utf8_lossy(p).to_string())
+                .collect();
+            (path, String::from_utf8_lossy(&doc.value).to_string())
+        })
+        .collect()
+}
+
+#[test]
+fn scalars() -> io::Result<()> {
+    let docs = unnest_iter(&br#"[5,"x",true]"#[..], 1, HeaderStyle::None)
+        .collect::<io::Result<Vec<Document>>>()?;
+    assert_eq!(
+        vec![
+            (vec![], "5".to_string()),
+            (vec![], r#""x""#.to_string()),
+            (vec![], "true".to_string()),
+        ],
+        strings(&docs)
+    );
+    Ok(())
+}
+
+#[test]
+fn paths_across_top_level_values() -> io::Result<()> {
+    let docs = unnest_iter(
+        &br#"{"a": {"b": [1]}, "c": []} [{"d": 2}]"#[..],
+        2,
+        HeaderStyle::PathArray,
+    )
+    .collect::<io::Result<Vec<Document>>>()?;
+    assert_eq!(
+        vec![
+            (
+                vec![r#""a""#.to_string(), r#""b""#.to_string()],
+                "[1]".to_string()
+            ),
+            (vec!["0".to_string(), r#""d""#.to_string()], "2".to_string()),
+        ],
+        strings(&docs)
+    );
+    Ok(())
+}
+
+#[test]
+fn errors_end_the_iteration() {
+    let mut docs = unnest_iter(&b"[1, 2 3]"[..], 1, HeaderStyle::None);
+    assert_eq!(b"1".to_vec(), docs.next().unwrap().unwrap().value);
+    assert_eq!(b"2".to_vec(), docs.next().unwrap().unwrap().value);
+    assert!(docs.next().unwrap().is_err());
+    assert!(docs.next().is_none());
+}
+
+#[test]
+fn unsupported_options() {
+    for config in [
+        Config::new(1).continue_on_error(true),
+        Config::new(1).filter_equals([r#""s""#], r#""x""#),
+        Config::new(1).group_by(["a"]),
+        Config::new(1).pretty(Some(2)),
+    ] {
+        let mut docs = documents(&br#"[{"s":"x"},{"s":"y"}]"#[..], &config);
+        let e = docs.next().unwrap().unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, e.kind(), "{}", config);
+        assert!(docs.next().is_none());
+
+        match Appending::new(&config) {
+            Err(UnnestError::Io(e)) => assert_eq!(io::ErrorKind::InvalidInput, e.kind()),
+            Err(other) => panic!("{:?}", other),
+            Ok(_) => panic!("{}", config),
+        }
+    }
+}
+
+#[test]
+fn max_documents() {
+    let docs = documents(&b"[1, 2, 3]"[..], &Config::new(1).max_documents(2));
+    assert_eq!(2, docs.count());
+}
+
+#[test]
+fn after_prefix() -> io::Result<()> {
+    // the prefix is bigger than the buffer, and ends in the middle of a number
+    let config = Config::new(1).buffer_size(4);
+    let docs = documents_after(br#"{"a": [1], "b": 2"#, &b"3, \"c\": 4}"[..], &config)
+        .map(|doc| doc.map(|doc| String::from_utf8(doc.value).unwrap()))
+        .collect::<io::Result<Vec<_>>>()?;
+    assert_eq!(vec!["[1]", "23", "4"], docs);
+    Ok(())
+}
+
+#[test]
+fn appending() -> Result<(), UnnestError> {
+    fn value(progress: Progress) -> String {
+        match progress {
+            Progress::Document(doc) => String::from_utf8(doc.value).unwrap(),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    let mut docs = Appending::new(&Config::new(1))?;
+    assert_eq!(Progress::NeedMoreData, docs.try_next()?);
+    docs.push(b"[1,2");
+    assert_eq!("1", value(docs.try_next()?));
+    assert_eq!(Progress::NeedMoreData, docs.try_next()?);
+    assert_eq!(Progress::NeedMoreData, docs.try_next()?);
+    docs.push(b",3]");
+    assert_eq!("2", value(docs.try_next()?));
+    assert_eq!("3", value(docs.try_next()?));
+    assert_eq!(Progress::NeedMoreData, docs.try_next()?);
+
+    // a document split part way through a string, then another top-level value
+    docs.push(b"\n[{\"a\": \"x");
+    assert_eq!(Progress::NeedMoreData, docs.try_next()?);
+    docs.push(b"y\"}, true]");
+    assert_eq!(r#"{"a":"xy"}"#, value(docs.try_next()?));
+    assert_eq!("true", value(docs.try_next()?));
+    assert_eq!(Progress::NeedMoreData, docs.try_next()?);
+
+    // errors are reported against the whole input
+    docs.push(b" [1 2]");
+    assert_eq!("1", value(docs.try_next()?));
+    match docs.try_next() {
+        Err(UnnestError::Syntax { position, .. }) => {
+            assert_eq!(
+                (31, 2, 24),
+                (position.offset, position.line, position.column)
+            );
+        }
+        other => panic!("{:?}", other),
+    }
+    Ok(())
+}
+
+#[test]
+fn appending_byte_order_mark() -> Result<(), UnnestError> {
+    let mut docs = Appending::new(&Config::new(1))?;
+    docs.push(b"\xef\xbb");
+    assert_eq!(Progress::NeedMoreData, docs.try_next()?);
+    docs.push(b"\xbf[1]");
+    assert!(matches!(docs.try_next()?, Progress::Document(doc) if doc.value == b"1"));
+    Ok(())
+}
+
+#[test]
+fn appending_max_documents() -> Result<(), UnnestError> {
+    let mut docs = Appending::new(&Config::new(1).max_documents(1))?;
+    docs.push(b"[1, 2, 3]");
+    assert!(matches!(docs.try_next()?, Progress::Document(_)));
+    assert_eq!(Progress::Finished, docs.try_next()?);
+    Ok(())
+}
@@ -3,8 +3,9 @@ use serde_json::json;
 use serde_json::to_vec_pretty;
 use serde_json::Value;
 use std::io;
-use std::io::Cursor;
-use unnest_ndjson::{unnest_to_ndjson, HeaderStyle, MiniWrite, Sinker};
+use unnest_ndjson::{
+    unnest_count, unnest_to_ndjson, unnest_to_ndjson_with, Config, HeaderStyle, MiniWrite, Sinker,
+};
 
 fn test_with(orig: &Value, expected: &[Value], target: usize, header_style: HeaderStyle) {
     let input = io::Cursor::new(to_vec_pretty(&orig).expect("serialisation of reference value"));
@@ -150,6 +151,25 @@ fn unicodes_str() {
     assert_eq!(capture.caught, vec!["\"r\\u00ebr\""]);
 }
 
+#[test]
+fn surrogate_pair_str() {
+    // escapes are copied, not decoded, even when split between reads
+    let input = br#"{"smile": "\ud83d\ude00", "split": "....\ud83d\ude00"}"#;
+    for buffer_size in [3, 4, 5, 16 * 1024] {
+        let mut capture = Capture::default();
+        let config = Config::new(1)
+            .buffer_size(buffer_size)
+            .validate_surrogates(true);
+        unnest_to_ndjson_with(&input[..], &mut capture, &config).expect("unnest");
+        assert_eq!(
+            capture.caught,
+            vec![r#""\ud83d\ude00""#, r#""....\ud83d\ude00""#],
+            "{}",
+            buffer_size
+        );
+    }
+}
+
 #[test]
 fn double_level_object() {
     test_with(
@@ -213,3 +233,78 @@ fn double_level_object_no_headers() {
         HeaderStyle::None,
     );
 }
+
+#[test]
+fn with_line() {
+    let mut output = Vec::new();
+    unnest_to_ndjson(
+        &br#"[
+  5,
+  {
+    "a": [
+      1
+    ]
+  },
+
+  "potato", [], true
+]"#[..],
+        &mut output,
+        1,
+        HeaderStyle::WithLine,
+    )
+    .expect("unnest");
+    assert_eq!(
+        concat!(
+            "{\"line\":2,\"value\":5}\n",
+            "{\"line\":3,\"value\":{\"a\":[1]}}\n",
+            "{\"line\":9,\"value\":\"potato\"}\n",
+            "{\"line\":9,\"value\":[]}\n",
+            "{\"line\":9,\"value\":true}\n",
+        ),
+        String::from_utf8_lossy(&output)
+    );
+}
+
+fn unnest_str(input: &str, target: usize, header_style: HeaderStyle) -> String {
+    let mut output = Vec::new();
+    unnest_to_ndjson(input.as_bytes(), &mut output, target, header_style).expect("unnest");
+    String::from_utf8(output).expect("valid utf-8")
+}
+
+#[test]
+fn top_level_scalars() {
+    assert_eq!("1\n2\n3\n", unnest_str("1\n2\n3", 0, HeaderStyle::None));
+    assert_eq!("1\n2\n3\n", unnest_str("1\n2\n3\n", 0, HeaderStyle::None));
+    assert_eq!(
+        "\"a\"\n\"b\"\n",
+        unnest_str("\"a\"\n\"b\"", 0, HeaderStyle::None)
+    );
+    assert_eq!(
+        concat!(
+            "{\"key\":[],\"value\":1}\n",
+            "{\"key\":[],\"value\":2}\n",
+            "{\"key\":[],\"value\":3}\n",
+        ),
+        unnest_str("1\n2\n3", 0, HeaderStyle::PathArray)
+    );
+    assert_eq!(
+        "{\"key\":[],\"value\":\"a\"}\n{\"key\":[],\"value\":\"b\"}\n",
+        unnest_str("\"a\"\n\"b\"", 0, HeaderStyle::PathArray)
+    );
+}
+
+#[test]
+fn count() -> io::Result<()> {
+    let array = br#"[5, "potato", true, {}, {"baz": 6}, {"foo": {"bar": 6}}, {"aye": 7, "be": 8}, [], [5], [5, 6]]"#;
+    assert_eq!(10, unnest_count(&array[..], 1)?);
+    assert_eq!(1, unnest_count(&array[..], 0)?);
+    // the scalars are also documents, as there is nothing to remove
+    assert_eq!(10, unnest_count(&array[..], 2)?);
+    assert_eq!(0, unnest_count(&b"{}"[..], 1)?);
+    assert_eq!(0, unnest_count(&b"[]"[..], 1)?);
+    assert_eq!(
+        io::ErrorKind::UnexpectedEof,
+        unnest_count(&b"[1, 2"[..], 1).unwrap_err().kind()
+    );
+    Ok(())
+}
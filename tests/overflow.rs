@@ -3,8 +3,8 @@ use std::io;
 use serde_json::from_slice;
 use serde_json::json;
 use serde_json::Value;
-use unnest_ndjson::unnest_to_ndjson;
 use unnest_ndjson::HeaderStyle;
+use unnest_ndjson::{unnest_to_ndjson, unnest_to_ndjson_with, Config, UnnestError};
 
 #[test]
 fn stack_abuse() {
@@ -38,3 +38,91 @@ fn stack_abuse() {
     assert!(key.iter().all(|v| v == &json!(0)));
     assert_eq!(val, &vec![json!(5)]);
 }
+
+#[test]
+fn deep_nesting_without_recursion() {
+    let level = 500_000;
+    let mut buf = Vec::with_capacity(level * 2 + 1);
+    buf.extend_from_slice(&vec![b'['; level]);
+    buf.push(b'5');
+    buf.extend_from_slice(&vec![b']'; level]);
+
+    let mut out = Vec::with_capacity(buf.len());
+    unnest_to_ndjson(&buf[..], &mut out, 1, HeaderStyle::None).expect("success");
+    assert_eq!(&buf[1..buf.len() - 1], &out[..out.len() - 1]);
+    assert_eq!(Some(&b'\n'), out.last());
+
+    let mut out = Vec::with_capacity(buf.len() * 2);
+    unnest_to_ndjson(&buf[..], &mut out, level - 1, HeaderStyle::PathArray).expect("success");
+    let mut expected = br#"{"key":["#.to_vec();
+    expected.extend_from_slice("0,".repeat(level - 1).as_bytes());
+    expected.pop();
+    expected.extend_from_slice(br#"],"value":[5]}"#);
+    expected.push(b'\n');
+    assert_eq!(expected, out);
+}
+
+#[test]
+fn max_depth() {
+    let mut buf = Vec::with_capacity(10_001);
+    buf.extend_from_slice(&vec![b'['; 5000]);
+    buf.push(b'5');
+    buf.extend_from_slice(&vec![b']'; 5000]);
+
+    let config = Config::new(1).max_depth(100);
+    match unnest_to_ndjson_with(&buf[..], &mut Vec::new(), &config) {
+        Err(UnnestError::TooDeep { max_depth: 100 }) => (),
+        other => panic!("unexpected: {:?}", other),
+    }
+
+    let mut out = Vec::new();
+    unnest_to_ndjson_with(&b"[[[5]]]"[..], &mut out, &Config::new(1).max_depth(3))
+        .expect("exactly at the limit");
+    assert_eq!(b"[[5]]\n", &out[..]);
+}
+
+#[test]
+fn small_buffer() -> Result<(), UnnestError> {
+    let mut input = b"[".to_vec();
+    let mut expected = Vec::new();
+    for i in 0..200_000 {
+        if i != 0 {
+            input.extend_from_slice(b",\n ");
+        }
+        let item = format!("{{\"id\":{},\"name\":\"item \\\"{}\\\"\"}}", i, i);
+        input.extend_from_slice(item.as_bytes());
+        expected.extend_from_slice(item.as_bytes());
+        expected.push(b'\n');
+    }
+    input.push(b']');
+    assert!(input.len() > 4 * 1024 * 1024);
+
+    for buffer_size in [1024, 1] {
+        let mut out = Vec::with_capacity(expected.len());
+        unnest_to_ndjson_with(
+            &input[..],
+            &mut out,
+            &Config::new(1).buffer_size(buffer_size),
+        )?;
+        assert!(expected == out, "buffer_size: {}", buffer_size);
+    }
+    Ok(())
+}
+
+#[test]
+fn deep_object_inside_a_document() {
+    let level = 200_000;
+    let mut value = Vec::with_capacity(level * 6 + 1);
+    value.extend_from_slice(r#"{"a":"#.repeat(level).as_bytes());
+    value.push(b'5');
+    value.extend_from_slice(&vec![b'}'; level]);
+
+    let mut input = b"[".to_vec();
+    input.extend_from_slice(&value);
+    input.push(b']');
+
+    let mut out = Vec::with_capacity(input.len());
+    unnest_to_ndjson(&input[..], &mut out, 1, HeaderStyle::None).expect("success");
+    value.push(b'\n');
+    assert_eq!(value, out);
+}
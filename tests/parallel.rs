@@ -0,0 +1,56 @@
+#![cfg(feature = "rayon")]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use unnest_ndjson::sinks::ParallelSink;
+use unnest_ndjson::{unnest_to_ndjson_with, Config, HeaderStyle, UnnestError};
+
+/// Something slow enough to be worth spreading over threads.
+fn collatz_steps(mut n: u64) -> u64 {
+    let mut steps = 0;
+    while n > 1 {
+        n = if n.is_multiple_of(2) {
+            n / 2
+        } else {
+            3 * n + 1
+        };
+        steps += 1;
+    }
+    steps
+}
+
+#[test]
+fn all_processed_in_order() -> Result<(), UnnestError> {
+    let input = format!(
+        "[{}]",
+        (1..=10_000)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    );
+    let calls = AtomicUsize::new(0);
+    let mut results = Vec::new();
+    let sink = ParallelSink::new(
+        64,
+        |path, value| {
+            calls.fetch_add(1, Ordering::Relaxed);
+            let n: u64 = std::str::from_utf8(value).unwrap().parse().unwrap();
+            (path.to_vec(), n, collatz_steps(n))
+        },
+        |result| {
+            results.push(result);
+            Ok(())
+        },
+    );
+    let config = Config::new(1).header_style(HeaderStyle::PathArray);
+    unnest_to_ndjson_with(input.as_bytes(), sink, &config)?;
+
+    assert_eq!(10_000, calls.into_inner());
+    assert_eq!(10_000, results.len());
+    for (i, (path, n, steps)) in results.into_iter().enumerate() {
+        assert_eq!(vec![i.to_string().into_bytes()], path);
+        assert_eq!(i as u64 + 1, n);
+        assert_eq!(collatz_steps(n), steps);
+    }
+    Ok(())
+}
@@ -0,0 +1,48 @@
+#![cfg(any(feature = "yaml", feature = "toml"))]
+
+use serde_json::{json, Value};
+use unnest_ndjson::{unnest_to_ndjson_with, Config, Format, Reencode, UnnestError};
+
+const INPUT: &[u8] = br#"[{"name": "a", "ports": [80, 443], "tls": {"on": true}}, {"name": "b"}]"#;
+
+fn reencode(format: Format, input: &[u8]) -> Result<String, UnnestError> {
+    let mut out = Vec::new();
+    unnest_to_ndjson_with(input, Reencode::new(format, &mut out), &Config::new(1))?;
+    Ok(String::from_utf8(out).expect("valid utf-8"))
+}
+
+fn expected() -> Vec<Value> {
+    vec![
+        json!({"name": "a", "ports": [80, 443], "tls": {"on": true}}),
+        json!({"name": "b"}),
+    ]
+}
+
+#[cfg(feature = "yaml")]
+#[test]
+fn yaml() -> Result<(), UnnestError> {
+    use serde::Deserialize;
+
+    let text = reencode(Format::Yaml, INPUT)?;
+    assert!(text.starts_with("---\nname: a\n"), "{}", text);
+    let docs: Vec<Value> = serde_yaml::Deserializer::from_str(&text)
+        .map(|doc| Value::deserialize(doc).expect("valid yaml"))
+        .collect();
+    assert_eq!(expected(), docs);
+    Ok(())
+}
+
+#[cfg(feature = "toml")]
+#[test]
+fn toml() -> Result<(), UnnestError> {
+    let text = reencode(Format::Toml, INPUT)?;
+    let docs: Vec<Value> = text
+        .split("# ---\n")
+        .filter(|doc| !doc.trim().is_empty())
+        .map(|doc| toml::from_str(doc).expect("valid toml"))
+        .collect();
+    assert_eq!(expected(), docs);
+
+    assert!(reencode(Format::Toml, b"[5]").is_err());
+    Ok(())
+}
@@ -0,0 +1,311 @@
+use std::fs;
+use std::io;
+
+use unnest_ndjson::sinks::{
+    fn_sink, BatchSink, CollectSink, CsvNested, CsvSink, IndexedDataSink, Key, KeyedSink, Sharded,
+    TeeSink, WithProgress,
+};
+use unnest_ndjson::{
+    unnest_collect, unnest_to_ndjson_with, Config, Header, HeaderStyle, MiniWrite, Sinker,
+    UnnestError,
+};
+
+fn lossy(v: &[u8]) -> String {
+    String::from_utf8_lossy(v).to_string()
+}
+
+#[test]
+fn keyed_by_field() -> Result<(), UnnestError> {
+    let mut records = Vec::new();
+    let sink = KeyedSink::new(Key::Field(b"id".to_vec()), |k: Vec<u8>, v: Vec<u8>| {
+        records.push((lossy(&k), lossy(&v)));
+        Ok::<_, io::Error>(())
+    });
+    unnest_to_ndjson_with(
+        &br#"[{"id":"x","v":1}, {"v": 2, "id": 7}, {"v": 3}]"#[..],
+        sink,
+        &Config::new(1),
+    )?;
+    assert_eq!(
+        vec![
+            (r#""x""#.to_string(), r#"{"id":"x","v":1}"#.to_string()),
+            ("7".to_string(), r#"{"v":2,"id":7}"#.to_string()),
+            ("".to_string(), r#"{"v":3}"#.to_string()),
+        ],
+        records
+    );
+    Ok(())
+}
+
+#[test]
+fn keyed_by_path() -> Result<(), UnnestError> {
+    let mut records = Vec::new();
+    let sink = KeyedSink::new(Key::Path, |k: Vec<u8>, v: Vec<u8>| {
+        records.push((lossy(&k), lossy(&v)));
+        Ok::<_, io::Error>(())
+    });
+    unnest_to_ndjson_with(
+        &br#"{"a": {"b": 1}, "c": [2]}"#[..],
+        sink,
+        &Config::new(2).header_style(HeaderStyle::PathArray),
+    )?;
+    assert_eq!(
+        vec![
+            (r#"["a","b"]"#.to_string(), "1".to_string()),
+            (r#"["c",0]"#.to_string(), "2".to_string()),
+        ],
+        records
+    );
+    Ok(())
+}
+
+#[test]
+fn collect() -> Result<(), UnnestError> {
+    let input = br#"["a","b","c"]"#;
+    assert_eq!(
+        vec![
+            (vec![b"0".to_vec()], br#""a""#.to_vec()),
+            (vec![b"1".to_vec()], br#""b""#.to_vec()),
+            (vec![b"2".to_vec()], br#""c""#.to_vec()),
+        ],
+        unnest_collect(&input[..], 1, HeaderStyle::PathArray)?
+    );
+
+    let mut sink = CollectSink::new();
+    unnest_to_ndjson_with(&input[..], &mut sink, &Config::new(1))?;
+    let values: Vec<_> = sink
+        .into_inner()
+        .into_iter()
+        .map(|(path, value)| (path.len(), lossy(&value)))
+        .collect();
+    assert_eq!(
+        vec![
+            (0, "\"a\"".to_string()),
+            (0, "\"b\"".to_string()),
+            (0, "\"c\"".to_string())
+        ],
+        values
+    );
+    Ok(())
+}
+
+#[test]
+fn batches() -> Result<(), UnnestError> {
+    let mut batches = Vec::new();
+    let sink = BatchSink::new(3, |batch: &[Vec<u8>]| {
+        batches.push(batch.iter().map(|v| lossy(v)).collect::<Vec<_>>());
+        Ok(())
+    });
+    unnest_to_ndjson_with(&b"[1, 2, 3, 4, 5, [6], 7]"[..], sink, &Config::new(1))?;
+    assert_eq!(
+        vec![vec!["1", "2", "3"], vec!["4", "5", "[6]"], vec!["7"]],
+        batches
+    );
+    Ok(())
+}
+
+#[test]
+fn closure() -> Result<(), UnnestError> {
+    let mut seen = Vec::new();
+    unnest_to_ndjson_with(
+        &br#"["a", "b"]"#[..],
+        fn_sink(|path, value| seen.push((lossy(&path.concat()), lossy(value)))),
+        &Config::new(1).header_style(HeaderStyle::PathArray),
+    )?;
+    assert_eq!(
+        vec![
+            ("0".to_string(), r#""a""#.to_string()),
+            ("1".to_string(), r#""b""#.to_string()),
+        ],
+        seen
+    );
+    Ok(())
+}
+
+#[test]
+fn sharded() -> Result<(), UnnestError> {
+    let dir = tempfile::tempdir()?;
+    let input = (0..100)
+        .map(|i| format!(r#"{{"id":{},"v":"x"}}"#, i))
+        .collect::<Vec<_>>();
+    let mut shards = Sharded::new(dir.path(), 4, Key::Field(b"id".to_vec()));
+    unnest_to_ndjson_with(
+        format!("[{}]", input.join(",")).as_bytes(),
+        &mut shards,
+        &Config::new(1),
+    )?;
+    shards.finish()?;
+
+    let mut found = Vec::new();
+    for shard in 0..4 {
+        let text = fs::read_to_string(dir.path().join(format!("shard-{}.ndjson", shard)))?;
+        let lines = text.lines().map(str::to_string).collect::<Vec<_>>();
+        assert!(!lines.is_empty(), "shard {} is empty", shard);
+        found.extend(lines);
+    }
+    assert_eq!(4, fs::read_dir(dir.path())?.count());
+    found.sort();
+    let mut expected = input;
+    expected.sort();
+    assert_eq!(expected, found);
+    Ok(())
+}
+
+#[test]
+fn indexed() -> Result<(), UnnestError> {
+    let mut sink = IndexedDataSink::new(Vec::new(), Vec::new(), Key::Field(b"id".to_vec()));
+    unnest_to_ndjson_with(
+        &br#"[{"id": "b", "v": 1}, {"id": "a", "v": [2, 3]}, {"v": "x", "id": "b"}]"#[..],
+        &mut sink,
+        &Config::new(1),
+    )?;
+    let (data, index) = sink.finish()?;
+
+    assert_eq!(3 * 20, index.len());
+    let entries = index
+        .chunks(20)
+        .map(|entry| {
+            let hash = u64::from_le_bytes(entry[..8].try_into().unwrap());
+            let offset = u64::from_le_bytes(entry[8..16].try_into().unwrap()) as usize;
+            let len = u32::from_le_bytes(entry[16..].try_into().unwrap()) as usize;
+            assert_eq!(b'\n', data[offset + len]);
+            (hash, lossy(&data[offset..offset + len]))
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        vec![
+            r#"{"id":"b","v":1}"#,
+            r#"{"id":"a","v":[2,3]}"#,
+            r#"{"v":"x","id":"b"}"#,
+        ],
+        entries
+            .iter()
+            .map(|(_, doc)| doc.as_str())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(entries[0].0, entries[2].0);
+    assert_ne!(entries[0].0, entries[1].0);
+    assert_eq!(
+        data.len(),
+        entries.iter().map(|(_, doc)| doc.len() + 1).sum::<usize>()
+    );
+    Ok(())
+}
+
+#[test]
+fn csv() -> Result<(), UnnestError> {
+    let input = br#"{"name": "Sam \"S\" Smith", "age": 30, "tags": ["a"], "note": "one,\ntwo", "x\u0041": null}"#;
+    let config = Config::new(1).header_style(HeaderStyle::PathArray);
+
+    let mut csv = CsvSink::new(Vec::new(), CsvNested::Skip);
+    unnest_to_ndjson_with(&input[..], &mut csv, &config)?;
+    assert_eq!(
+        concat!(
+            "path,value\r\n",
+            "name,\"Sam \"\"S\"\" Smith\"\r\n",
+            "age,30\r\n",
+            "note,\"one,\ntwo\"\r\n",
+            "xA,null\r\n",
+        ),
+        lossy(&csv.finish()?)
+    );
+
+    let mut csv = CsvSink::new(Vec::new(), CsvNested::Json);
+    unnest_to_ndjson_with(&br#"{"tags": ["a", 1]}"#[..], &mut csv, &config)?;
+    assert_eq!(
+        "path,value\r\ntags,\"[\"\"a\"\",1]\"\r\n",
+        lossy(&csv.finish()?)
+    );
+
+    let csv = CsvSink::new(Vec::new(), CsvNested::Skip);
+    assert_eq!("path,value\r\n", lossy(&csv.finish()?));
+    Ok(())
+}
+
+#[derive(Default)]
+struct Counting {
+    documents: usize,
+    bytes: usize,
+    paths: Vec<Vec<Vec<u8>>>,
+}
+
+impl MiniWrite for &mut Counting {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.bytes += buf.len();
+        Ok(())
+    }
+}
+
+impl Sinker for &mut Counting {
+    fn observe_new_item_with(&mut self, path: &[Vec<u8>], _: &Header) -> io::Result<()> {
+        self.paths.push(path.to_vec());
+        Ok(())
+    }
+
+    fn observe_end_with(&mut self, _: &Header) -> io::Result<()> {
+        self.documents += 1;
+        Ok(())
+    }
+}
+
+#[test]
+fn tee() -> Result<(), UnnestError> {
+    let mut out = Vec::new();
+    let mut counting = Counting::default();
+    let config = Config::new(1).header_style(HeaderStyle::PathArray);
+    let sink = TeeSink::new(&mut out, &mut counting);
+    unnest_to_ndjson_with(&br#"{"a": [1, 2], "b": "three"}"#[..], sink, &config)?;
+    assert_eq!(
+        "{\"key\":[\"a\"],\"value\":[1,2]}\n{\"key\":[\"b\"],\"value\":\"three\"}\n",
+        lossy(&out)
+    );
+    assert_eq!(2, counting.documents);
+    assert_eq!("[1,2]".len() + "\"three\"".len(), counting.bytes);
+    assert_eq!(
+        vec![vec![b"\"a\"".to_vec()], vec![b"\"b\"".to_vec()]],
+        counting.paths
+    );
+    Ok(())
+}
+
+#[test]
+fn progress() -> Result<(), UnnestError> {
+    let input = format!("[{}]", vec![r#"{"a": [1, 2]}"#; 100].join(", "));
+    let config = Config::new(1)
+        .header_style(HeaderStyle::ByteRange)
+        .buffer_size(7);
+
+    let mut offsets = Vec::new();
+    let mut out = Vec::new();
+    let sink = WithProgress::new(&mut out, |offset| offsets.push(offset));
+    unnest_to_ndjson_with(
+        input.as_bytes(),
+        sink,
+        &config.clone().progress_every(Some(0)),
+    )?;
+    let ends = String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .map(|line| {
+            let doc: serde_json::Value = serde_json::from_str(line).unwrap();
+            doc["end"].as_u64().unwrap()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(101, offsets.len());
+    assert_eq!(ends[..], offsets[..100]);
+    assert_eq!(input.len() as u64, offsets[100]);
+
+    let mut offsets = Vec::new();
+    let sink = WithProgress::new(io::sink(), |offset| offsets.push(offset));
+    unnest_to_ndjson_with(input.as_bytes(), sink, &config.progress_every(Some(100)))?;
+    let (last, reports) = offsets.split_last().unwrap();
+    assert_eq!(input.len() as u64, *last);
+    assert!(reports.len() > 10);
+    assert!(
+        reports.windows(2).all(|w| w[1] - w[0] >= 100),
+        "{:?}",
+        offsets
+    );
+    assert!(reports.last() < Some(last));
+    Ok(())
+}